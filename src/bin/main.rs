@@ -1,70 +1,76 @@
 extern crate iron_oxide_browser;
-use iron_oxide_browser::{command, css, css_parse, dom, html_parse, layout, render, style};
+use iron_oxide_browser::{command, coverage, dom, html_parse, layout, loader, media, render, style};
+use iron_oxide_browser::loader::ResourceLoader;
 
 use std::env;
-use std::fs::File;
-use std::io::{Read, BufReader};
 
 fn main() {
-    let nodes = test_html();
+    let mut path = env::current_dir().unwrap();
+    path.push("tests/parserTestFiles/blockTypeTest.html");
+    let location = path.to_string_lossy();
+
+    let nodes = test_html(&location);
     for node in nodes.iter() {
         dom::pretty_print(node, 0);
     }
     let ref node = nodes[0];
 
     println!("");
-    let ss = test_css();
-    print!("{:?}", ss);
+    let sheets = loader::collect_stylesheets(node, &location, &loader::FileLoader);
+    for sheet in &sheets {
+        print!("{:?}", sheet);
+    }
+
+    // --coverage reports the rules no element matches instead of rendering
+    if env::args().any(|arg| arg == "--coverage") {
+        let mut coverage = coverage::Coverage::new(&sheets);
+        coverage.add_document(node);
+
+        println!("\n\nunused css of {} elements:", coverage.elements);
+        for unused in coverage.unused() {
+            println!("stylesheet {}, {:?}", unused.sheet, unused);
+        }
+        println!("\npruned stylesheets:");
+        for sheet in coverage.pruned() {
+            print!("{:?}", sheet);
+        }
+        return;
+    }
+
+    let mut viewport = layout::Dimensions::default();
+    viewport.content.width = 1024.0;
+    viewport.content.height = 768.0;
 
     println!("");
-    let style_tree_root = style::StyledNode::new(&node, &ss);
+    let media = media::MediaEnvironment::new(&viewport);
+    let mut style_context = style::StyleContext::new(&sheets, &media);
+    let style_tree_root = style_context.style(node);
     style::pretty_print(&style_tree_root, 0);
+    println!("style sharing: {} of {} elements ({:.0}%)", style_context.stats.hits,
+        style_context.stats.lookups, style_context.stats.hit_rate() * 100.0);
 
 
     println!("");
-    let mut viewport = layout::Dimensions::default();
-    viewport.content.width = 1024.0;
-    viewport.content.height = 768.0;
-    let layout_tree = layout::layout_tree(&style_tree_root, viewport);
+    let layout_tree = match layout::layout_tree(&style_tree_root, viewport) {
+        Some(layout_tree) => layout_tree,
+        None => return, // the document isn't displayed
+    };
     layout::pretty_print(&layout_tree, 0);
 
     let display_commands =  command::build_display_commands(&layout_tree);
     render::render_loop(&display_commands);
 }
 
-fn test_html() -> Vec<dom::Node> {
-    let mut path = env::current_dir().unwrap();
-    path.push("tests/parserTestFiles/blockTypeTest.html");
-
-    let mut file_reader = match File::open(&path) {
-        Ok(f) => BufReader::new(f),
-        Err(e) => panic!("file: {}\nerror: {}", path.display(), e)
+fn test_html(location: &str) -> Vec<dom::Node> {
+    let html_input = match loader::FileLoader.load(location) {
+        Ok(html) => html,
+        Err(e) => panic!("file: {}\nerror: {}", location, e)
     };
 
-    let mut html_input = String::new();
-    file_reader.read_to_string(&mut html_input).unwrap();
-
     let nodes = html_parse::HtmlParser::new(&html_input).parse_nodes();
 
     nodes
 }
 
-fn test_css() -> css::Stylesheet {
-    let mut path = env::current_dir().unwrap();
-    path.push("tests/parserTestFiles/blockTypeTest.css");
-
-    let mut file_reader = match File::open(&path) {
-        Ok(f) => BufReader::new(f),
-        Err(e) => panic!("file: {}\nerror: {}", path.display(), e)
-    };
-
-    let mut css_input = String::new();
-    file_reader.read_to_string(&mut css_input).unwrap();
-
-    let stylesheet = css_parse::CssParser::new(&css_input).parse_stylesheet();
-
-    stylesheet
-}
-
 // TODO change this into a binary crate consumer of the rest of the code
 // TODO change the rest of the code into library crates
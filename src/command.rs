@@ -1,12 +1,13 @@
 
-use css::{Value, Color};
-use layout::{LayoutBox, Rect};
+use css::Color;
+use layout::{BoxType, LayoutBox, Rect};
 use std::fmt;
 
 pub type DisplayList = Vec<DisplayCommand>;
 
 pub enum DisplayCommand {
     SolidRect(Color, Rect),
+    Text(String, Color, Rect),
 }
 impl fmt::Debug for DisplayCommand {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -14,6 +15,9 @@ impl fmt::Debug for DisplayCommand {
             DisplayCommand::SolidRect(ref c, ref r) => {
                 write!(f, "{:?} {:?}", c, r)
             },
+            DisplayCommand::Text(ref t, ref c, ref r) => {
+                write!(f, "{:?} {:?} {:?}", t, c, r)
+            },
         }
     }
 }
@@ -27,7 +31,7 @@ pub fn build_display_commands(root: &LayoutBox) -> DisplayList {
 fn render_layout_box(commands: &mut DisplayList, layout_box: &LayoutBox) {
     render_background(commands, layout_box);
     render_borders(commands, layout_box);
-    //TODO: Render text
+    render_text(commands, layout_box);
 
     for child in &layout_box.children {
         render_layout_box(commands, child);
@@ -35,56 +39,70 @@ fn render_layout_box(commands: &mut DisplayList, layout_box: &LayoutBox) {
 }
 
 fn render_background(commands: &mut DisplayList, layout_box: &LayoutBox) {
-    get_color(layout_box, "background-color").map(|color|
-        commands.push(DisplayCommand::SolidRect(color, layout_box.dimensions.border_box())));
+    // an anonymous box shares the style of its parent, which painted the background already
+    if let BoxType::Anonymous = *layout_box.box_type() {
+        return;
+    }
+    if let Some(color) = get_color(&layout_box.styled_node.computed().background_color, 1.0) {
+        commands.push(DisplayCommand::SolidRect(color, layout_box.dimensions.border_box()));
+    }
 }
 
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
-    match layout_box.styled_node.value(name) {
-        Some(v) => {
-            match **v {
-                Value::Color(ref c) => { return Some(c.clone()) },
-                _ => { return None },
-            }
-        },
-        None => { return None },
+fn render_text(commands: &mut DisplayList, layout_box: &LayoutBox) {
+    if let BoxType::TextRun(ref text) = *layout_box.box_type() {
+        if let Some(color) = get_color(&layout_box.styled_node.computed().color, 1.0) {
+            commands.push(DisplayCommand::Text(text.clone(), color, layout_box.dimensions.content));
+        }
     }
 }
 
-fn render_borders(commands: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return,
-    };
+/// Returns a color if painting it with the given size would be visible.
+fn get_color(color: &Color, size: f32) -> Option<Color> {
+    if color.a > 0.0 && size > 0.0 {
+        Some(color.clone())
+    } else {
+        None
+    }
+}
 
+fn render_borders(commands: &mut DisplayList, layout_box: &LayoutBox) {
     let d = &layout_box.dimensions;
     let border_box = d.border_box();
+    let colors = &layout_box.styled_node.computed().border_color;
 
-    commands.push(DisplayCommand::SolidRect(color.clone(), Rect {
-        x: border_box.x,
-        y: border_box.y,
-        width: d.border.left,
-        height: border_box.height,
-    }));
+    if let Some(color) = get_color(&colors.left, d.border.left) {
+        commands.push(DisplayCommand::SolidRect(color, Rect {
+            x: border_box.x,
+            y: border_box.y,
+            width: d.border.left,
+            height: border_box.height,
+        }));
+    }
 
-    commands.push(DisplayCommand::SolidRect(color.clone(), Rect {
-        x: border_box.x + border_box.width - d.border.right,
-        y: border_box.y,
-        width: d.border.right,
-        height: border_box.height,
-    }));
+    if let Some(color) = get_color(&colors.right, d.border.right) {
+        commands.push(DisplayCommand::SolidRect(color, Rect {
+            x: border_box.x + border_box.width - d.border.right,
+            y: border_box.y,
+            width: d.border.right,
+            height: border_box.height,
+        }));
+    }
 
-    commands.push(DisplayCommand::SolidRect(color.clone(), Rect {
-        x: border_box.x,
-        y: border_box.y,
-        width: border_box.width,
-        height: d.border.top,
-    }));
+    if let Some(color) = get_color(&colors.top, d.border.top) {
+        commands.push(DisplayCommand::SolidRect(color, Rect {
+            x: border_box.x,
+            y: border_box.y,
+            width: border_box.width,
+            height: d.border.top,
+        }));
+    }
 
-    commands.push(DisplayCommand::SolidRect(color, Rect {
-        x: border_box.x,
-        y: border_box.y + border_box.height - d.border.bottom,
-        width: border_box.width,
-        height: d.border.bottom,
-    }));
+    if let Some(color) = get_color(&colors.bottom, d.border.bottom) {
+        commands.push(DisplayCommand::SolidRect(color, Rect {
+            x: border_box.x,
+            y: border_box.y + border_box.height - d.border.bottom,
+            width: border_box.width,
+            height: d.border.bottom,
+        }));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,632 @@
+//! The `computed` module turns the computed values of an element into a typed style, with
+//! lengths in pixels and initial values filled in, for layout and painting to read.
+
+use css::{CalcNode, CalcType, Color, Keyword, Separator, Unit, Value};
+use properties::initial_value;
+
+use std::collections::HashMap;
+
+/// The typed computed style of a node.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ComputedStyle {
+    pub display: Display,
+    pub position: Position,
+    pub width: Size,
+    pub height: Size,
+    pub min_width: Size,
+    pub min_height: Size,
+    pub max_width: Option<LengthPercentage>, // None for no maximum
+    pub max_height: Option<LengthPercentage>,
+    pub margin: Edges<Size>,
+    pub padding: Edges<LengthPercentage>,
+    pub border_width: Edges<f32>, // 0 for sides without a border style
+    pub border_style: Edges<Keyword>,
+    pub border_color: Edges<Color>,
+    pub color: Color,
+    pub background_color: Color,
+    pub font_size: f32,
+    pub font_weight: f32,
+    pub font_style: Keyword,
+    pub font_family: Vec<String>,
+    pub line_height: LineHeight,
+    pub visibility: Keyword,
+}
+
+/// The display type of an element in the css display model: how its box takes part in the
+/// layout of its parent, and how the box lays out its children.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Display {
+    Normal { outside: DisplayOutside, inside: DisplayInside, list_item: bool },
+    Internal(DisplayInternal), // a part of a table, laid out by the table
+    Contents, // the element generates no box and its children take its place
+    None, // the element and its descendants generate no boxes
+}
+
+/// How a box takes part in the layout of its parent.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DisplayOutside {
+    Block,
+    Inline,
+}
+
+/// How a box lays out its children.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DisplayInside {
+    Flow,
+    FlowRoot, // flow layout in a new block formatting context
+    Table,
+    Flex,
+    Grid,
+}
+
+/// The parts of a table.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DisplayInternal {
+    TableRowGroup,
+    TableHeaderGroup,
+    TableFooterGroup,
+    TableRow,
+    TableCell,
+    TableColumnGroup,
+    TableColumn,
+    TableCaption,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+/// A length in pixels, or a percentage of a size that is only known during layout.
+#[derive(PartialEq, Clone, Debug)]
+pub enum LengthPercentage {
+    Px(f32),
+    Percentage(f32),
+    Calc(Box<CalcNode>), // a math function mixing percentages and lengths in pixels
+}
+
+/// A size that can be left for layout to decide.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Size {
+    Auto,
+    Length(LengthPercentage),
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LineHeight {
+    Normal,
+    Number(f32), // a multiple of the font size
+    Px(f32),
+}
+
+/// A value for each side of a box.
+#[derive(PartialEq, Clone, Copy, Default, Debug)]
+pub struct Edges<T> {
+    pub top: T,
+    pub right: T,
+    pub bottom: T,
+    pub left: T,
+}
+
+/// What the relative lengths of an element resolve against.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct LengthContext {
+    pub font_size: f32,
+    pub root_font_size: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+}
+
+impl Display {
+    pub const BLOCK: Display = Display::normal(DisplayOutside::Block, DisplayInside::Flow);
+    pub const INLINE: Display = Display::normal(DisplayOutside::Inline, DisplayInside::Flow);
+    pub const INLINE_BLOCK: Display = Display::normal(DisplayOutside::Inline, DisplayInside::FlowRoot);
+
+    /// Constructs the display type of a box that isn't a list item.
+    const fn normal(outside: DisplayOutside, inside: DisplayInside) -> Display {
+        Display::Normal { outside, inside, list_item: false }
+    }
+
+    /// Returns the display type of a display value, or None if the value is invalid.
+    ///
+    /// Besides the single keywords, a value can list an outer display type, an inner display
+    /// type and list-item in any order, ex. "inline flex" or "list-item flow-root". A missing
+    /// outer type is block, and a missing inner type is flow. A list item's inner type has
+    /// to be flow or flow-root.
+    pub fn from_value(value: &Value) -> Option<Display> {
+        let internal = |internal| Some(Display::Internal(internal));
+        let parts = match *value {
+            Value::Keyword(Keyword::None) => return Some(Display::None),
+            Value::Keyword(Keyword::Contents) => return Some(Display::Contents),
+            Value::Keyword(Keyword::InlineBlock) => return Some(Display::INLINE_BLOCK),
+            Value::Keyword(Keyword::InlineFlex) => return Some(Display::normal(DisplayOutside::Inline, DisplayInside::Flex)),
+            Value::Keyword(Keyword::InlineGrid) => return Some(Display::normal(DisplayOutside::Inline, DisplayInside::Grid)),
+            Value::Keyword(Keyword::InlineTable) => return Some(Display::normal(DisplayOutside::Inline, DisplayInside::Table)),
+            Value::Keyword(Keyword::TableRowGroup) => return internal(DisplayInternal::TableRowGroup),
+            Value::Keyword(Keyword::TableHeaderGroup) => return internal(DisplayInternal::TableHeaderGroup),
+            Value::Keyword(Keyword::TableFooterGroup) => return internal(DisplayInternal::TableFooterGroup),
+            Value::Keyword(Keyword::TableRow) => return internal(DisplayInternal::TableRow),
+            Value::Keyword(Keyword::TableCell) => return internal(DisplayInternal::TableCell),
+            Value::Keyword(Keyword::TableColumnGroup) => return internal(DisplayInternal::TableColumnGroup),
+            Value::Keyword(Keyword::TableColumn) => return internal(DisplayInternal::TableColumn),
+            Value::Keyword(Keyword::TableCaption) => return internal(DisplayInternal::TableCaption),
+            Value::List(ref parts, Separator::Space) if parts.len() <= 3 => &parts[..],
+            ref v => ::std::slice::from_ref(v),
+        };
+
+        let (mut outside, mut inside, mut list_item) = (None, None, false);
+
+        for part in parts {
+            match *part {
+                Value::Keyword(Keyword::Block) if outside.is_none() => outside = Some(DisplayOutside::Block),
+                Value::Keyword(Keyword::Inline) if outside.is_none() => outside = Some(DisplayOutside::Inline),
+                Value::Keyword(Keyword::ListItem) if !list_item => list_item = true,
+                Value::Keyword(Keyword::FlowRoot) if inside.is_none() => inside = Some(DisplayInside::FlowRoot),
+                Value::Keyword(Keyword::Table) if inside.is_none() => inside = Some(DisplayInside::Table),
+                Value::Keyword(Keyword::Flex) if inside.is_none() => inside = Some(DisplayInside::Flex),
+                Value::Keyword(Keyword::Grid) if inside.is_none() => inside = Some(DisplayInside::Grid),
+                Value::Ident(ref flow) if flow == "flow" && inside.is_none() => inside = Some(DisplayInside::Flow),
+                _ => return None,
+            }
+        }
+
+        let inside = inside.unwrap_or(DisplayInside::Flow);
+        if list_item && inside != DisplayInside::Flow && inside != DisplayInside::FlowRoot {
+            return None;
+        }
+
+        Some(Display::Normal { outside: outside.unwrap_or(DisplayOutside::Block), inside, list_item })
+    }
+
+    /// Returns the shortest value of the display type, a single keyword where there is one.
+    pub fn to_value(&self) -> Value {
+        let (outside, inside, list_item) = match *self {
+            Display::Normal { outside, inside, list_item } => (outside, inside, list_item),
+            Display::Internal(internal) => return Value::Keyword(match internal {
+                DisplayInternal::TableRowGroup => Keyword::TableRowGroup,
+                DisplayInternal::TableHeaderGroup => Keyword::TableHeaderGroup,
+                DisplayInternal::TableFooterGroup => Keyword::TableFooterGroup,
+                DisplayInternal::TableRow => Keyword::TableRow,
+                DisplayInternal::TableCell => Keyword::TableCell,
+                DisplayInternal::TableColumnGroup => Keyword::TableColumnGroup,
+                DisplayInternal::TableColumn => Keyword::TableColumn,
+                DisplayInternal::TableCaption => Keyword::TableCaption,
+            }),
+            Display::Contents => return Value::Keyword(Keyword::Contents),
+            Display::None => return Value::Keyword(Keyword::None),
+        };
+
+        let keyword = match (outside, inside, list_item) {
+            (DisplayOutside::Block, DisplayInside::Flow, false) => Some(Keyword::Block),
+            (DisplayOutside::Block, DisplayInside::Flow, true) => Some(Keyword::ListItem),
+            (DisplayOutside::Block, DisplayInside::FlowRoot, false) => Some(Keyword::FlowRoot),
+            (DisplayOutside::Block, DisplayInside::Table, _) => Some(Keyword::Table),
+            (DisplayOutside::Block, DisplayInside::Flex, _) => Some(Keyword::Flex),
+            (DisplayOutside::Block, DisplayInside::Grid, _) => Some(Keyword::Grid),
+            (DisplayOutside::Inline, DisplayInside::Flow, false) => Some(Keyword::Inline),
+            (DisplayOutside::Inline, DisplayInside::FlowRoot, false) => Some(Keyword::InlineBlock),
+            (DisplayOutside::Inline, DisplayInside::Table, _) => Some(Keyword::InlineTable),
+            (DisplayOutside::Inline, DisplayInside::Flex, _) => Some(Keyword::InlineFlex),
+            (DisplayOutside::Inline, DisplayInside::Grid, _) => Some(Keyword::InlineGrid),
+            _ => None,
+        };
+        if let Some(keyword) = keyword {
+            return Value::Keyword(keyword);
+        }
+
+        // the list items without a keyword, which leave out the defaults
+        let mut parts = Vec::new();
+        if outside == DisplayOutside::Inline {
+            parts.push(Value::Keyword(Keyword::Inline));
+        }
+        if inside == DisplayInside::FlowRoot {
+            parts.push(Value::Keyword(Keyword::FlowRoot));
+        }
+        parts.push(Value::Keyword(Keyword::ListItem));
+        Value::List(parts, Separator::Space)
+    }
+}
+
+impl ComputedStyle {
+    /// Constructs the typed style of an element from its computed values.
+    /// Properties without a value take their initial value.
+    ///
+    /// values: the computed values of the element, by property name.
+    /// lengths: what the relative lengths of the element resolve against.
+    pub fn new(values: &HashMap<String, Value>, lengths: &LengthContext) -> ComputedStyle {
+        let get = |name: &str| values.get(name).cloned().or_else(|| initial_value(name));
+        let keyword = |name: &str, default| match get(name) {
+            Some(Value::Keyword(k)) => k,
+            _ => default,
+        };
+        let color = |name: &str| match get(name) {
+            Some(Value::Color(c)) => Some(c),
+            _ => None,
+        };
+        let size = |name: &str| match get(name) {
+            Some(ref v) => length_percentage(v, lengths).map_or(Size::Auto, Size::Length),
+            None => Size::Auto,
+        };
+
+        let text_color = color("color").unwrap_or_else(|| Color::new(0.0, 0.0, 0.0, 1.0));
+        let border_style = Edges::new(|side| keyword(&format!("border-{}-style", side), Keyword::None));
+
+        ComputedStyle {
+            display: get("display").as_ref().and_then(Display::from_value).unwrap_or(Display::INLINE),
+            position: match keyword("position", Keyword::Static) {
+                Keyword::Relative => Position::Relative,
+                Keyword::Absolute => Position::Absolute,
+                Keyword::Fixed => Position::Fixed,
+                Keyword::Sticky => Position::Sticky,
+                _ => Position::Static,
+            },
+            width: size("width"),
+            height: size("height"),
+            min_width: size("min-width"),
+            min_height: size("min-height"),
+            max_width: get("max-width").and_then(|v| length_percentage(&v, lengths)),
+            max_height: get("max-height").and_then(|v| length_percentage(&v, lengths)),
+            margin: Edges::new(|side| size(&format!("margin-{}", side))),
+            padding: Edges::new(|side| {
+                get(&format!("padding-{}", side))
+                    .and_then(|v| length_percentage(&v, lengths))
+                    .unwrap_or(LengthPercentage::Px(0.0))
+            }),
+            border_width: Edges::new(|side| match keyword(&format!("border-{}-style", side), Keyword::None) {
+                Keyword::None | Keyword::Hidden => 0.0,
+                _ => get(&format!("border-{}-width", side))
+                    .and_then(|v| lengths.resolve(&v, None))
+                    .map_or(0.0, |w| w.max(0.0)),
+            }),
+            border_style,
+            // currentcolor and invalid colors use the text color
+            border_color: Edges::new(|side| {
+                color(&format!("border-{}-color", side)).unwrap_or_else(|| text_color.clone())
+            }),
+            background_color: color("background-color").unwrap_or_else(|| Color::new(0.0, 0.0, 0.0, 0.0)),
+            color: text_color.clone(),
+            font_size: lengths.font_size,
+            font_weight: match get("font-weight") {
+                Some(ref v) => font_weight(v, 400.0),
+                None => 400.0,
+            },
+            font_style: keyword("font-style", Keyword::Normal),
+            font_family: get("font-family").map_or_else(Vec::new, |v| font_family(&v)),
+            line_height: match get("line-height") {
+                Some(Value::Number(n)) => LineHeight::Number(n),
+                Some(Value::Calc(ref node)) if node.calc_type() == Some(CalcType::Number) => {
+                    node.evaluate(&|leaf| match *leaf {
+                        Value::Number(n) => Some(n),
+                        _ => None,
+                    }).map_or(LineHeight::Normal, LineHeight::Number)
+                },
+                Some(ref v) => {
+                    lengths.resolve(v, Some(lengths.font_size)).map_or(LineHeight::Normal, LineHeight::Px)
+                },
+                None => LineHeight::Normal,
+            },
+            visibility: keyword("visibility", Keyword::Visible),
+        }
+    }
+
+    /// Returns the computed value of each property of the style by name, with lengths in
+    /// pixels. Percentages that resolve against a size only known during layout remain.
+    pub fn to_values(&self) -> Vec<(String, Value)> {
+        let px = |px: f32| Value::Length(px, Unit::Px);
+        let length = |l: &LengthPercentage| match *l {
+            LengthPercentage::Px(n) => px(n),
+            LengthPercentage::Percentage(p) => Value::Percentage(p),
+            LengthPercentage::Calc(ref node) => Value::Calc(node.clone()),
+        };
+        let size = |s: &Size| match *s {
+            Size::Auto => Value::Keyword(Keyword::Auto),
+            Size::Length(ref l) => length(l),
+        };
+        let max = |m: &Option<LengthPercentage>| m.as_ref().map_or(Value::Keyword(Keyword::None), length);
+        let family = self.font_family.iter().map(|name| Value::Ident(name.clone())).collect::<Vec<Value>>();
+
+        let mut values = vec![
+            (String::from("display"), self.display.to_value()),
+            (String::from("position"), Value::Keyword(match self.position {
+                Position::Static => Keyword::Static,
+                Position::Relative => Keyword::Relative,
+                Position::Absolute => Keyword::Absolute,
+                Position::Fixed => Keyword::Fixed,
+                Position::Sticky => Keyword::Sticky,
+            })),
+            (String::from("width"), size(&self.width)),
+            (String::from("height"), size(&self.height)),
+            (String::from("min-width"), size(&self.min_width)),
+            (String::from("min-height"), size(&self.min_height)),
+            (String::from("max-width"), max(&self.max_width)),
+            (String::from("max-height"), max(&self.max_height)),
+            (String::from("color"), Value::Color(self.color.clone())),
+            (String::from("background-color"), Value::Color(self.background_color.clone())),
+            (String::from("font-size"), px(self.font_size)),
+            (String::from("font-weight"), Value::Number(self.font_weight)),
+            (String::from("font-style"), Value::Keyword(self.font_style)),
+            (String::from("font-family"), Value::List(family, Separator::Comma)),
+            (String::from("line-height"), match self.line_height {
+                LineHeight::Normal => Value::Keyword(Keyword::Normal),
+                LineHeight::Number(n) => Value::Number(n),
+                LineHeight::Px(n) => px(n),
+            }),
+            (String::from("visibility"), Value::Keyword(self.visibility)),
+        ];
+
+        for &(side, margin) in &self.margin.sides() {
+            values.push((format!("margin-{}", side), size(margin)));
+        }
+        for &(side, padding) in &self.padding.sides() {
+            values.push((format!("padding-{}", side), length(padding)));
+        }
+        for &(side, &width) in &self.border_width.sides() {
+            values.push((format!("border-{}-width", side), px(width)));
+        }
+        for &(side, &style) in &self.border_style.sides() {
+            values.push((format!("border-{}-style", side), Value::Keyword(style)));
+        }
+        for &(side, color) in &self.border_color.sides() {
+            values.push((format!("border-{}-color", side), Value::Color(color.clone())));
+        }
+
+        values
+    }
+}
+
+impl Default for ComputedStyle {
+    /// The style of a node with only initial values.
+    fn default() -> Self {
+        ComputedStyle::new(&HashMap::new(), &LengthContext::default())
+    }
+}
+
+impl LengthPercentage {
+    /// Resolves the length in pixels.
+    ///
+    /// percent_base: the length percentages are relative to, or None if they can't be resolved.
+    pub fn resolve(&self, percent_base: Option<f32>) -> Option<f32> {
+        match *self {
+            LengthPercentage::Px(px) => Some(px),
+            LengthPercentage::Percentage(p) => percent_base.map(|base| p * base / 100.0),
+            LengthPercentage::Calc(ref node) => node.evaluate(&|leaf| match *leaf {
+                Value::Number(n) | Value::Length(n, Unit::Px) => Some(n),
+                Value::Percentage(p) => percent_base.map(|base| p * base / 100.0),
+                _ => None,
+            }),
+        }
+    }
+}
+
+impl Size {
+    /// Resolves the size in pixels, or None if it is auto or can't be resolved.
+    ///
+    /// percent_base: the length percentages are relative to.
+    pub fn resolve(&self, percent_base: Option<f32>) -> Option<f32> {
+        match *self {
+            Size::Auto => None,
+            Size::Length(ref l) => l.resolve(percent_base),
+        }
+    }
+}
+
+impl<T> Edges<T> {
+    /// Constructs edges from the value of each side, by the side's name.
+    pub fn new<F>(side: F) -> Edges<T> where F : Fn(&str) -> T {
+        Edges {
+            top: side("top"),
+            right: side("right"),
+            bottom: side("bottom"),
+            left: side("left"),
+        }
+    }
+
+    /// Returns the value of each side with its name, in the order top, right, bottom, left.
+    pub fn sides(&self) -> [(&'static str, &T); 4] {
+        [("top", &self.top), ("right", &self.right), ("bottom", &self.bottom), ("left", &self.left)]
+    }
+}
+
+impl LengthContext {
+    /// Resolves a length, percentage or math function to pixels.
+    ///
+    /// percent_base: the length percentages are relative to, or None if they can't be resolved.
+    pub fn resolve(&self, value: &Value, percent_base: Option<f32>) -> Option<f32> {
+        match *value {
+            Value::Length(n, unit) => Some(n * self.unit_px(unit)),
+            Value::Percentage(p) => percent_base.map(|base| p * base / 100.0),
+            Value::Calc(ref node) => node.evaluate(&|leaf| match *leaf {
+                Value::Number(n) => Some(n),
+                ref v => self.resolve(v, percent_base),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Resolves a font-size in pixels, where relative sizes are relative to this context's
+    /// font size. This is the context of the parent of the element the size applies to.
+    pub fn font_size(&self, value: &Value) -> f32 {
+        let size = match *value {
+            Value::Keyword(k) => match k {
+                Keyword::XxSmall => 9.0,
+                Keyword::XSmall => 10.0,
+                Keyword::Small => 13.0,
+                Keyword::Medium => 16.0,
+                Keyword::Large => 18.0,
+                Keyword::XLarge => 24.0,
+                Keyword::XxLarge => 32.0,
+                Keyword::Larger => self.font_size * 1.2,
+                Keyword::Smaller => self.font_size / 1.2,
+                _ => self.font_size,
+            },
+            ref v => self.resolve(v, Some(self.font_size)).unwrap_or(self.font_size),
+        };
+
+        size.max(0.0)
+    }
+
+    /// Returns the number of pixels in a unit.
+    fn unit_px(&self, unit: Unit) -> f32 {
+        match unit.absolute_px() {
+            Some(px) => px,
+            None => match unit {
+                Unit::Rem => self.root_font_size,
+                Unit::Vw => self.viewport_width / 100.0,
+                Unit::Vh => self.viewport_height / 100.0,
+                Unit::Vmin => self.viewport_width.min(self.viewport_height) / 100.0,
+                Unit::Vmax => self.viewport_width.max(self.viewport_height) / 100.0,
+                // without font metrics the x-height and the width of "0" are half an em
+                Unit::Ex|Unit::Ch => self.font_size / 2.0,
+                _ => self.font_size,
+            },
+        }
+    }
+}
+impl Default for LengthContext {
+    fn default() -> Self {
+        LengthContext {
+            font_size: 16.0,
+            root_font_size: 16.0,
+            viewport_width: 0.0,
+            viewport_height: 0.0,
+        }
+    }
+}
+
+/// Resolves a font-weight to a number, where bolder and lighter are relative to the
+/// weight of the parent.
+///
+/// parent: the weight of the parent element.
+pub fn font_weight(value: &Value, parent: f32) -> f32 {
+    match *value {
+        Value::Number(n) => n,
+        Value::Keyword(Keyword::Bold) => 700.0,
+        Value::Keyword(Keyword::Bolder) if parent < 350.0 => 400.0,
+        Value::Keyword(Keyword::Bolder) if parent < 550.0 => 700.0,
+        Value::Keyword(Keyword::Bolder) => parent.max(900.0),
+        Value::Keyword(Keyword::Lighter) if parent < 550.0 => parent.min(100.0),
+        Value::Keyword(Keyword::Lighter) if parent < 750.0 => 400.0,
+        Value::Keyword(Keyword::Lighter) => 700.0,
+        _ => 400.0, // normal
+    }
+}
+
+/// Returns the family names of a font-family value, generic families by their keyword.
+fn font_family(value: &Value) -> Vec<String> {
+    let name = |v: &Value| match *v {
+        Value::Ident(ref name) | Value::Str(ref name) => name.clone(),
+        Value::Keyword(k) => String::from(k.as_str()),
+        Value::List(ref words, Separator::Space) => {
+            words.iter().map(|w| format!("{:?}", w)).collect::<Vec<String>>().join(" ")
+        },
+        ref v => format!("{:?}", v),
+    };
+
+    match *value {
+        Value::List(ref names, Separator::Comma) => names.iter().map(name).collect(),
+        ref v => vec![name(v)],
+    }
+}
+
+/// Converts a length, percentage or math function to its computed form, with lengths in
+/// pixels. Returns None for any other value.
+fn length_percentage(value: &Value, lengths: &LengthContext) -> Option<LengthPercentage> {
+    match *value {
+        Value::Length(..) => lengths.resolve(value, None).map(LengthPercentage::Px),
+        Value::Percentage(p) => Some(LengthPercentage::Percentage(p)),
+        // a math function without percentages resolves now, otherwise its lengths do
+        Value::Calc(ref node) => match lengths.resolve(value, None) {
+            Some(px) => Some(LengthPercentage::Px(px)),
+            None => Some(LengthPercentage::Calc(Box::new(node.map_leaves(&|leaf| match *leaf {
+                Value::Length(..) => {
+                    Value::Length(lengths.resolve(leaf, None).unwrap_or(0.0), Unit::Px)
+                },
+                ref v => v.clone(),
+            })))),
+        },
+        _ => None,
+    }
+}
+
+/// Tests ----------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lengths() -> LengthContext {
+        LengthContext {
+            font_size: 20.0,
+            root_font_size: 10.0,
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+        }
+    }
+
+    /// Test properties without a value take their initial value.
+    #[test]
+    fn computed_initial() {
+        let style = ComputedStyle::default();
+
+        assert_eq!(Display::INLINE, style.display);
+        assert_eq!(Position::Static, style.position);
+        assert_eq!(Size::Auto, style.width);
+        assert_eq!(None, style.max_width);
+        assert_eq!(Size::Length(LengthPercentage::Px(0.0)), style.margin.left);
+        assert_eq!(0.0, style.border_width.top);
+        assert_eq!(Color::new(0.0, 0.0, 0.0, 1.0), style.border_color.top);
+        assert_eq!(0.0, style.background_color.a);
+        assert_eq!(400.0, style.font_weight);
+        assert_eq!(vec![String::from("serif")], style.font_family);
+        assert_eq!(LineHeight::Normal, style.line_height);
+    }
+
+    /// Test lengths compute to pixels and percentages are kept for layout.
+    #[test]
+    fn computed_lengths() {
+        let mut values = HashMap::new();
+        values.insert(String::from("width"), Value::Length(2.0, Unit::Em));
+        values.insert(String::from("height"), Value::Percentage(50.0));
+        values.insert(String::from("margin-top"), Value::Keyword(Keyword::Auto));
+        values.insert(String::from("padding-left"), Value::Length(1.0, Unit::Rem));
+        values.insert(String::from("border-left-width"), Value::Length(10.0, Unit::Vw));
+        values.insert(String::from("border-left-style"), Value::Keyword(Keyword::Solid));
+        values.insert(String::from("border-top-width"), Value::Length(3.0, Unit::Px));
+        values.insert(String::from("line-height"), Value::Percentage(150.0));
+        let style = ComputedStyle::new(&values, &lengths());
+
+        assert_eq!(Size::Length(LengthPercentage::Px(40.0)), style.width);
+        assert_eq!(Some(100.0), style.height.resolve(Some(200.0)));
+        assert_eq!(None, style.height.resolve(None));
+        assert_eq!(Size::Auto, style.margin.top);
+        assert_eq!(Some(10.0), style.padding.left.resolve(None));
+        assert_eq!(80.0, style.border_width.left);
+        assert_eq!(0.0, style.border_width.top);
+        assert_eq!(LineHeight::Px(30.0), style.line_height);
+    }
+
+    /// Test math functions with percentages keep them, with their lengths in pixels.
+    #[test]
+    fn computed_calc() {
+        let calc = CalcNode::Sum(Box::new(CalcNode::Leaf(Value::Percentage(50.0))),
+            Box::new(CalcNode::Leaf(Value::Length(1.0, Unit::Em))));
+        let computed = length_percentage(&Value::Calc(Box::new(calc)), &lengths()).unwrap();
+
+        assert_eq!(Some(120.0), computed.resolve(Some(200.0)));
+        assert_eq!(None, computed.resolve(None));
+    }
+
+    /// Test font weights resolve keywords, relative to the parent's weight.
+    #[test]
+    fn computed_font_weight() {
+        assert_eq!(700.0, font_weight(&Value::Keyword(Keyword::Bold), 100.0));
+        assert_eq!(700.0, font_weight(&Value::Keyword(Keyword::Bolder), 400.0));
+        assert_eq!(900.0, font_weight(&Value::Keyword(Keyword::Bolder), 700.0));
+        assert_eq!(100.0, font_weight(&Value::Keyword(Keyword::Lighter), 400.0));
+        assert_eq!(400.0, font_weight(&Value::Keyword(Keyword::Lighter), 700.0));
+        assert_eq!(300.0, font_weight(&Value::Number(300.0), 700.0));
+    }
+}
@@ -0,0 +1,169 @@
+//! The `coverage` module finds the rules and selectors of stylesheets that match no element of
+//! a set of documents, and prunes them from the stylesheets.
+
+use css::{Rule, Selector, Stylesheet};
+use dom::{Node, NodeType};
+use matching::{Ancestors, RuleIndex};
+
+use std::collections::HashSet;
+use std::fmt;
+
+/// Which selectors of a set of stylesheets matched an element of the documents added so far.
+///
+/// Rules are matched whatever their @media conditions, so a rule that only applies to
+/// another medium counts as used if it would match.
+pub struct Coverage<'s> {
+    sheets: &'s [Stylesheet],
+    index: RuleIndex<'s>,
+    used: HashSet<*const Selector>, // the addresses of the selectors that matched
+    pub elements: usize, // the number of elements matched
+}
+
+/// A rule with selectors that matched no element.
+pub struct UnusedRule<'s> {
+    pub sheet: usize, // the index of the rule's stylesheet
+    pub rule: &'s Rule,
+    pub selectors: Vec<&'s Selector>, // the selectors that matched no element
+}
+
+impl<'s> Coverage<'s> {
+    /// Constructs the coverage of stylesheets no document was matched against yet.
+    ///
+    /// sheets: the stylesheets to report the coverage of.
+    pub fn new(sheets: &'s [Stylesheet]) -> Coverage<'s> {
+        Coverage {
+            sheets,
+            index: RuleIndex::all_media(sheets),
+            used: HashSet::new(),
+            elements: 0,
+        }
+    }
+
+    /// Matches the selectors of the stylesheets against every element of a document.
+    ///
+    /// root: the root node of the document.
+    pub fn add_document(&mut self, root: &Node) {
+        self.add_node(root, &mut Ancestors::new());
+    }
+
+    /// Matches the selectors against an element and its descendants.
+    fn add_node<'a>(&mut self, node: &'a Node, ancestors: &mut Ancestors<'a>) {
+        if let NodeType::Element(ref elem) = node.node_type {
+            for selector in self.index.matching_selectors(elem, ancestors) {
+                self.used.insert(selector);
+            }
+            self.elements += 1;
+
+            ancestors.push(elem);
+            for child in &node.children {
+                self.add_node(child, ancestors);
+            }
+            ancestors.pop();
+        }
+    }
+
+    /// Returns the rules with selectors that matched no element, in stylesheet order.
+    pub fn unused(&self) -> Vec<UnusedRule<'s>> {
+        let mut unused = Vec::new();
+
+        for (sheet, stylesheet) in self.sheets.iter().enumerate() {
+            for rule in &stylesheet.rules {
+                let selectors = rule.selectors.iter()
+                    .filter(|&selector| !self.used.contains(&(selector as *const Selector)))
+                    .collect::<Vec<&Selector>>();
+
+                // a rule without valid selectors matches nothing either
+                if !selectors.is_empty() || rule.selectors.is_empty() {
+                    unused.push(UnusedRule { sheet, rule, selectors });
+                }
+            }
+        }
+
+        unused
+    }
+
+    /// Returns copies of the stylesheets without the unused selectors, and without the rules
+    /// none of whose selectors were used.
+    pub fn pruned(&self) -> Vec<Stylesheet> {
+        self.sheets.iter().map(|stylesheet| {
+            let rules = stylesheet.rules.iter()
+                .map(|rule| Rule {
+                    selectors: rule.selectors.iter()
+                        .filter(|&selector| self.used.contains(&(selector as *const Selector)))
+                        .cloned()
+                        .collect(),
+                    ..rule.clone()
+                })
+                .filter(|rule| !rule.selectors.is_empty())
+                .collect();
+
+            Stylesheet {
+                rules,
+                imports: stylesheet.imports.clone(),
+                origin: stylesheet.origin,
+            }
+        }).collect()
+    }
+}
+
+impl<'s> UnusedRule<'s> {
+    /// Returns true if no selector of the rule matched, so the whole rule is unused.
+    pub fn is_unused(&self) -> bool {
+        self.selectors.len() == self.rule.selectors.len()
+    }
+}
+
+impl<'s> fmt::Debug for UnusedRule<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let all = join(self.rule.selectors.iter());
+
+        if self.is_unused() {
+            write!(f, "{}: unused rule {}", self.rule.location, all)
+        } else {
+            write!(f, "{}: unused selectors {} of rule {}", self.rule.location, join(self.selectors.iter().cloned()), all)
+        }
+    }
+}
+
+/// Returns a comma separated list of selectors.
+fn join<'a, I>(selectors: I) -> String where I: Iterator<Item = &'a Selector> {
+    selectors.map(|s| format!("{:?}", s)).collect::<Vec<String>>().join(", ")
+}
+
+/// Tests -----------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css_parse::CssParser;
+    use html_parse::HtmlParser;
+
+    /// Test the unused rules and selectors of several documents are reported with their
+    /// source locations.
+    #[test]
+    fn unused_rules() {
+        let sheets = vec![
+            CssParser::new("p { color: red; }\n.a, .b { color: red; }\ndiv .a { color: red; }").parse_stylesheet(),
+            CssParser::new("@media print { span { color: red; } }\n  #x > p, em { color: red; }").parse_stylesheet(),
+        ];
+        let mut coverage = Coverage::new(&sheets);
+        coverage.add_document(&HtmlParser::new("<div><p class=\"b\">x</p></div>").parse_nodes()[0]);
+        coverage.add_document(&HtmlParser::new("<html><span class=\"a\"></span></html>").parse_nodes()[0]);
+        let report = coverage.unused().iter().map(|u| format!("{:?}", u)).collect::<Vec<String>>();
+
+        assert_eq!(4, coverage.elements);
+        assert_eq!(vec!["3:1: unused rule div .a", "2:3: unused rule #x > p, em"], report);
+        assert_eq!(1, coverage.unused()[1].sheet);
+    }
+
+    /// Test partly used rules keep their used selectors when pruned.
+    #[test]
+    fn pruned_stylesheets() {
+        let sheets = vec![CssParser::new("p, .a { color: red; } .b { width: 1px; } div p { color: blue; }").parse_stylesheet()];
+        let mut coverage = Coverage::new(&sheets);
+        coverage.add_document(&HtmlParser::new("<div><p>x</p></div>").parse_nodes()[0]);
+
+        assert_eq!(vec!["1:1: unused selectors .a of rule p, .a", "1:23: unused rule .b"],
+            coverage.unused().iter().map(|u| format!("{:?}", u)).collect::<Vec<String>>());
+        assert_eq!(CssParser::new("p { color: red; } div p { color: blue; }").parse_stylesheet(), coverage.pruned()[0]);
+    }
+}
@@ -1,46 +1,263 @@
 //! The `css` module provides a stylesheet datastructure for the css parser to use.
 
+use media::MediaQueryList;
+
 use std::fmt;
 use std::default::Default;
 
 #[derive(PartialEq)]
 pub struct Stylesheet {
     pub rules: Vec<Rule>,
+    pub imports: Vec<Import>, // imports that haven't been loaded into the rules yet
+    pub origin: Origin,
 }
 
-#[derive(PartialEq)]
+/// An @import of another stylesheet, whose rules apply only when its media matches.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Import {
+    pub url: String,
+    pub media: MediaQueryList,
+}
+
+/// Where a stylesheet comes from, which decides its precedence in the cascade.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Origin {
+    UserAgent, // browser defaults
+    User, // user preferences, ex. a high contrast theme
+    Author, // the document's own styles
+}
+
+#[derive(Clone)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    pub media: Vec<MediaQueryList>, // the enclosing @media conditions, all have to match
+    pub location: SourceLocation,
+}
+
+/// Where a rule starts in the source of its stylesheet.
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct SourceLocation {
+    pub url: Option<String>, // the location the stylesheet was loaded from
+    pub line: usize, // counted from 1
+    pub column: usize, // counted from 1, in characters
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone)]
 pub struct Selector {
     pub simple: Vec<SimpleSelector>,
-    pub combinators: Vec<char>,
+    pub combinators: Vec<char>, // between each pair of compounds, ' ' for descendant or '>' for child
 }
 
-#[derive(PartialEq, Eq)]
+/// The (a, b, c) specificity of a selector: its id, class and tag name counts.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Debug)]
+pub struct Specificity(pub usize, pub usize, pub usize);
+
+#[derive(PartialEq, Eq, Clone)]
 pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub classes: Vec<String>,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 pub struct Declaration {
     pub property: String,
     pub value: Value,
+    pub important: bool,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 pub enum Value {
-    Color(Color),
+    Keyword(Keyword), // a css keyword, ex. auto, block or inherit
+    Ident(String), // an identifier that isn't a keyword, ex. a font family name
+    Number(f32),
+    Percentage(f32),
     Length(f32, Unit),
-    Other(String),
+    Dimension(f32, String), // a number with a unit that isn't a length, ex. 90deg or 2s
+    Color(Color),
+    Str(String),
+    Url(String),
+    Function(String, Vec<Value>), // name and comma separated arguments
+    List(Vec<Value>, Separator),
+    Other(String), // raw text of a value without a typed representation
+    // text with var() references and the property whose grammar it's parsed with once
+    // substituted, the declared longhand or shorthand
+    Var(String, String),
+    Calc(Box<CalcNode>), // a math function, ex. calc(100% - 2em) or clamp(1px, 50%, 9px)
 }
 
-#[derive(PartialEq)]
+/// The expression tree of a math function.
+#[derive(PartialEq, Clone)]
+pub enum CalcNode {
+    Leaf(Value), // a number, percentage or length
+    Sum(Box<CalcNode>, Box<CalcNode>),
+    Difference(Box<CalcNode>, Box<CalcNode>),
+    Product(Box<CalcNode>, Box<CalcNode>),
+    Quotient(Box<CalcNode>, Box<CalcNode>),
+    Min(Vec<CalcNode>),
+    Max(Vec<CalcNode>),
+    Clamp(Box<CalcNode>, Box<CalcNode>, Box<CalcNode>), // min, preferred and max
+}
+
+/// The type a math expression resolves to.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CalcType {
+    Number,
+    Length,
+    Percentage,
+    LengthPercentage, // a length that depends on what percentages resolve against
+}
+
+/// How the values of a multi-value list are separated.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Separator {
+    Space,
+    Comma,
+}
+
+/// Defines the keyword enum along with conversions to and from its css spelling.
+macro_rules! keywords {
+    ($($name:ident => $css:expr,)*) => {
+        #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+        pub enum Keyword {
+            $($name,)*
+        }
+
+        impl Keyword {
+            /// Returns the keyword for a lowercase identifier, if it is one.
+            pub fn from_ident(ident: &str) -> Option<Keyword> {
+                match ident {
+                    $($css => Some(Keyword::$name),)*
+                    _ => None,
+                }
+            }
+
+            /// Returns the css spelling of the keyword.
+            pub fn as_str(&self) -> &'static str {
+                match *self {
+                    $(Keyword::$name => $css,)*
+                }
+            }
+        }
+    }
+}
+
+keywords! {
+    // css-wide keywords
+    Inherit => "inherit",
+    Initial => "initial",
+    Unset => "unset",
+    Revert => "revert",
+    // common
+    Auto => "auto",
+    None => "none",
+    Normal => "normal",
+    CurrentColor => "currentcolor",
+    // display
+    Block => "block",
+    Inline => "inline",
+    InlineBlock => "inline-block",
+    ListItem => "list-item",
+    Flex => "flex",
+    InlineFlex => "inline-flex",
+    Grid => "grid",
+    InlineGrid => "inline-grid",
+    Table => "table",
+    InlineTable => "inline-table",
+    TableRowGroup => "table-row-group",
+    TableHeaderGroup => "table-header-group",
+    TableFooterGroup => "table-footer-group",
+    TableRow => "table-row",
+    TableCell => "table-cell",
+    TableColumnGroup => "table-column-group",
+    TableColumn => "table-column",
+    TableCaption => "table-caption",
+    Contents => "contents",
+    FlowRoot => "flow-root",
+    // position
+    Static => "static",
+    Relative => "relative",
+    Absolute => "absolute",
+    Fixed => "fixed",
+    Sticky => "sticky",
+    // borders
+    Thin => "thin",
+    Medium => "medium",
+    Thick => "thick",
+    Hidden => "hidden",
+    Dotted => "dotted",
+    Dashed => "dashed",
+    Solid => "solid",
+    Double => "double",
+    Groove => "groove",
+    Ridge => "ridge",
+    Inset => "inset",
+    Outset => "outset",
+    // fonts
+    Italic => "italic",
+    Oblique => "oblique",
+    SmallCaps => "small-caps",
+    Bold => "bold",
+    Bolder => "bolder",
+    Lighter => "lighter",
+    XxSmall => "xx-small",
+    XSmall => "x-small",
+    Small => "small",
+    Large => "large",
+    XLarge => "x-large",
+    XxLarge => "xx-large",
+    Larger => "larger",
+    Smaller => "smaller",
+    Serif => "serif",
+    SansSerif => "sans-serif",
+    Monospace => "monospace",
+    Cursive => "cursive",
+    Fantasy => "fantasy",
+    // backgrounds
+    Repeat => "repeat",
+    RepeatX => "repeat-x",
+    RepeatY => "repeat-y",
+    NoRepeat => "no-repeat",
+    Space => "space",
+    Round => "round",
+    Scroll => "scroll",
+    Local => "local",
+    // sides and alignment
+    Left => "left",
+    Right => "right",
+    Top => "top",
+    Bottom => "bottom",
+    Center => "center",
+    Justify => "justify",
+    Start => "start",
+    End => "end",
+    Both => "both",
+    // visibility and overflow
+    Visible => "visible",
+    Collapse => "collapse",
+    Clip => "clip",
+    // lists
+    Disc => "disc",
+    Circle => "circle",
+    Square => "square",
+    Decimal => "decimal",
+    LowerAlpha => "lower-alpha",
+    UpperAlpha => "upper-alpha",
+    LowerRoman => "lower-roman",
+    UpperRoman => "upper-roman",
+    Inside => "inside",
+    Outside => "outside",
+}
+
+impl Keyword {
+    /// Returns true for the keywords every property accepts.
+    pub fn is_css_wide(&self) -> bool {
+        matches!(*self, Keyword::Inherit | Keyword::Initial | Keyword::Unset | Keyword::Revert)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Unit {
     Em, // calculated or inherited font-size
     Ex, // height of the font's x character
@@ -57,7 +274,6 @@ pub enum Unit {
     In, // inch
     Pt, // 1/72th of an inch (point)
     Pc, // 12 points (pica)
-    Pct, // Percentage
 }
 
 #[derive(PartialEq, Clone)]
@@ -69,19 +285,33 @@ pub struct Color {
 }
 
 impl Stylesheet {
-    /// Constructs a new stylesheet.
+    /// Constructs a new author stylesheet.
     pub fn new(r: Vec<Rule>) -> Stylesheet {
-        Stylesheet { rules: r }
+        Stylesheet {
+            rules: r,
+            imports: Vec::new(),
+            origin: Origin::Author,
+        }
     }
 }
 impl Default for Stylesheet {
     fn default() -> Self {
-        Stylesheet { rules: Vec::new() }
+        Stylesheet {
+            rules: Vec::new(),
+            imports: Vec::new(),
+            origin: Origin::Author,
+        }
     }
 }
 impl fmt::Debug for Stylesheet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut rule_result = String::new();
+        for import in &self.imports {
+            if !rule_result.is_empty() {
+                rule_result.push_str("\n\n");
+            }
+            rule_result.push_str(&format!("@import {:?} {:?};", import.url, import.media));
+        }
         for rule in &self.rules {
             if rule_result.len() > 0 {
                 rule_result.push_str("\n\n");
@@ -101,6 +331,8 @@ impl Rule {
         Rule {
             selectors: s,
             declarations: d,
+            media: Vec::new(),
+            location: SourceLocation::default(),
         }
     }
 }
@@ -109,17 +341,29 @@ impl Default for Rule {
         Rule {
             selectors: Vec::new(),
             declarations: Vec::new(),
+            media: Vec::new(),
+            location: SourceLocation::default(),
         }
     }
 }
+// where a rule is written doesn't change what it does
+impl PartialEq for Rule {
+    fn eq(&self, other: &Rule) -> bool {
+        self.selectors == other.selectors && self.declarations == other.declarations && self.media == other.media
+    }
+}
 impl fmt::Debug for Rule {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut sel_result = String::new();
         let mut decl_result = String::new();
         let tab = "    ";
 
-        for selector in &self.selectors {
-            if sel_result.len() > 0 {
+        for media in &self.media {
+            sel_result.push_str(&format!("@media {:?}\n", media));
+        }
+
+        for (i, selector) in self.selectors.iter().enumerate() {
+            if i > 0 {
                 sel_result.push_str(", ");
             }
             sel_result.push_str(&format!("{:?}", selector));
@@ -135,6 +379,15 @@ impl fmt::Debug for Rule {
     }
 }
 
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.url {
+            Some(ref url) => write!(f, "{}:{}:{}", url, self.line, self.column),
+            None => write!(f, "{}:{}", self.line, self.column),
+        }
+    }
+}
+
 impl Selector {
     /// Constructs a new Selector.
     ///
@@ -145,6 +398,22 @@ impl Selector {
             combinators: c,
         }
     }
+
+    /// Returns the rightmost compound selector, the one that matches the element itself.
+    pub fn subject(&self) -> Option<&SimpleSelector> {
+        self.simple.last()
+    }
+
+    /// Returns the specificity of the selector.
+    ///
+    /// Ids, classes and tag names are counted across every simple selector.
+    pub fn specificity(&self) -> Specificity {
+        let ids = self.simple.iter().filter(|s| s.id.is_some()).count();
+        let classes = self.simple.iter().map(|s| s.classes.len()).sum();
+        let tags = self.simple.iter().filter(|s| s.tag_name.is_some()).count();
+
+        Specificity(ids, classes, tags)
+    }
 }
 impl Default for Selector {
     fn default() -> Self {
@@ -158,9 +427,12 @@ impl fmt::Debug for Selector {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut result = String::new();
 
-        for sel in &self.simple {
-            if result.len() > 0 {
-                result.push_str(", ");
+        for (i, sel) in self.simple.iter().enumerate() {
+            if i > 0 {
+                match self.combinators.get(i - 1) {
+                    Some(&'>') => result.push_str(" > "),
+                    _ => result.push(' '),
+                }
             }
             result.push_str(&format!("{:?}", sel));
         }
@@ -210,6 +482,10 @@ impl fmt::Debug for SimpleSelector {
             result.push_str(class);
         }
 
+        if result.is_empty() {
+            result.push('*');
+        }
+
         write!(f, "{}", result)
     }
 }
@@ -223,6 +499,7 @@ impl Declaration {
         Declaration {
             property: p,
             value: v,
+            important: false,
         }
     }
 }
@@ -231,21 +508,228 @@ impl Default for Declaration {
         Declaration {
             property: String::from(""),
             value: Value::Other(String::from("")),
+            important: false,
         }
     }
 }
 impl fmt::Debug for Declaration {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}: {:?}", self.property, self.value)
+        if self.important {
+            write!(f, "{}: {:?} !important", self.property, self.value)
+        } else {
+            write!(f, "{}: {:?}", self.property, self.value)
+        }
     }
 }
 
 impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Value::Keyword(k) => write!(f, "{}", k.as_str()),
+            Value::Ident(ref s) => write!(f, "{}", s),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Percentage(p) => write!(f, "{}%", p),
+            Value::Length(l, u) => write!(f, "{}{}", l, u.as_str()),
+            Value::Dimension(n, ref u) => write!(f, "{}{}", n, u),
             Value::Color(ref c) => write!(f, "{:?}", c),
-            Value::Length(l, _) => write!(f, "{:?}", l),
+            Value::Str(ref s) => write!(f, "{:?}", s),
+            Value::Url(ref s) => write!(f, "url({:?})", s),
+            Value::Function(ref name, ref args) => {
+                let args: Vec<String> = args.iter().map(|a| format!("{:?}", a)).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            },
+            Value::List(ref values, sep) => {
+                let values: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+                let sep = match sep {
+                    Separator::Space => " ",
+                    Separator::Comma => ", ",
+                };
+                write!(f, "{}", values.join(sep))
+            },
             Value::Other(ref s) => write!(f, "{:?}", s),
+            Value::Var(_, ref s) => write!(f, "{}", s),
+            Value::Calc(ref node) => match **node {
+                CalcNode::Min(_) | CalcNode::Max(_) | CalcNode::Clamp(..) => write!(f, "{:?}", node),
+                _ => write!(f, "calc({:?})", node),
+            },
+        }
+    }
+}
+
+impl CalcNode {
+    /// Returns the type the expression resolves to, or None if it mixes incompatible types,
+    /// ex. a length plus a number or a length times a length.
+    pub fn calc_type(&self) -> Option<CalcType> {
+        match *self {
+            CalcNode::Leaf(Value::Number(_)) => Some(CalcType::Number),
+            CalcNode::Leaf(Value::Length(..)) => Some(CalcType::Length),
+            CalcNode::Leaf(Value::Percentage(_)) => Some(CalcType::Percentage),
+            CalcNode::Leaf(_) => None,
+            CalcNode::Sum(ref a, ref b) | CalcNode::Difference(ref a, ref b) => {
+                CalcType::add(a.calc_type()?, b.calc_type()?)
+            },
+            CalcNode::Product(ref a, ref b) => match (a.calc_type()?, b.calc_type()?) {
+                (CalcType::Number, t) | (t, CalcType::Number) => Some(t),
+                _ => None,
+            },
+            CalcNode::Quotient(ref a, ref b) => match b.calc_type()? {
+                CalcType::Number => a.calc_type(),
+                _ => None,
+            },
+            CalcNode::Min(ref args) | CalcNode::Max(ref args) => {
+                let mut types = args.iter().map(CalcNode::calc_type);
+                let first = types.next()??;
+                types.try_fold(first, |t, next| CalcType::add(t, next?))
+            },
+            CalcNode::Clamp(ref min, ref value, ref max) => {
+                let t = CalcType::add(min.calc_type()?, value.calc_type()?)?;
+                CalcType::add(t, max.calc_type()?)
+            },
+        }
+    }
+
+    /// Evaluates the expression, returning None if a leaf can't be resolved or the result
+    /// isn't a finite number.
+    ///
+    /// leaf: resolves a number, percentage or length to the number it stands for.
+    pub fn evaluate<F>(&self, leaf: &F) -> Option<f32> where F : Fn(&Value) -> Option<f32> {
+        let result = match *self {
+            CalcNode::Leaf(ref v) => leaf(v)?,
+            CalcNode::Sum(ref a, ref b) => a.evaluate(leaf)? + b.evaluate(leaf)?,
+            CalcNode::Difference(ref a, ref b) => a.evaluate(leaf)? - b.evaluate(leaf)?,
+            CalcNode::Product(ref a, ref b) => a.evaluate(leaf)? * b.evaluate(leaf)?,
+            CalcNode::Quotient(ref a, ref b) => a.evaluate(leaf)? / b.evaluate(leaf)?,
+            CalcNode::Min(ref args) => args.iter()
+                .map(|a| a.evaluate(leaf))
+                .collect::<Option<Vec<f32>>>()?
+                .into_iter().fold(f32::INFINITY, f32::min),
+            CalcNode::Max(ref args) => args.iter()
+                .map(|a| a.evaluate(leaf))
+                .collect::<Option<Vec<f32>>>()?
+                .into_iter().fold(f32::NEG_INFINITY, f32::max),
+            // the minimum wins over the maximum when they overlap
+            CalcNode::Clamp(ref min, ref value, ref max) => {
+                value.evaluate(leaf)?.min(max.evaluate(leaf)?).max(min.evaluate(leaf)?)
+            },
+        };
+
+        if result.is_finite() {
+            Some(result)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a copy of the expression with each leaf value replaced, ex. to make its
+    /// lengths absolute.
+    pub fn map_leaves<F>(&self, leaf: &F) -> CalcNode where F : Fn(&Value) -> Value {
+        let map = |node: &CalcNode| Box::new(node.map_leaves(leaf));
+
+        match *self {
+            CalcNode::Leaf(ref v) => CalcNode::Leaf(leaf(v)),
+            CalcNode::Sum(ref a, ref b) => CalcNode::Sum(map(a), map(b)),
+            CalcNode::Difference(ref a, ref b) => CalcNode::Difference(map(a), map(b)),
+            CalcNode::Product(ref a, ref b) => CalcNode::Product(map(a), map(b)),
+            CalcNode::Quotient(ref a, ref b) => CalcNode::Quotient(map(a), map(b)),
+            CalcNode::Min(ref args) => CalcNode::Min(args.iter().map(|a| a.map_leaves(leaf)).collect()),
+            CalcNode::Max(ref args) => CalcNode::Max(args.iter().map(|a| a.map_leaves(leaf)).collect()),
+            CalcNode::Clamp(ref min, ref value, ref max) => CalcNode::Clamp(map(min), map(value), map(max)),
+        }
+    }
+}
+impl fmt::Debug for CalcNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // nested sums are parenthesized, the outermost expression is wrapped by its function
+        let operand = |node: &CalcNode| match *node {
+            CalcNode::Sum(..) | CalcNode::Difference(..) => format!("({:?})", node),
+            _ => format!("{:?}", node),
+        };
+        let list = |args: &[CalcNode]| {
+            args.iter().map(|a| format!("{:?}", a)).collect::<Vec<String>>().join(", ")
+        };
+
+        match *self {
+            CalcNode::Leaf(ref v) => write!(f, "{:?}", v),
+            CalcNode::Sum(ref a, ref b) => write!(f, "{:?} + {}", a, operand(b)),
+            CalcNode::Difference(ref a, ref b) => write!(f, "{:?} - {}", a, operand(b)),
+            CalcNode::Product(ref a, ref b) => write!(f, "{} * {}", operand(a), operand(b)),
+            CalcNode::Quotient(ref a, ref b) => write!(f, "{} / {}", operand(a), operand(b)),
+            CalcNode::Min(ref args) => write!(f, "min({})", list(args)),
+            CalcNode::Max(ref args) => write!(f, "max({})", list(args)),
+            CalcNode::Clamp(ref min, ref value, ref max) => {
+                write!(f, "clamp({:?}, {:?}, {:?})", min, value, max)
+            },
+        }
+    }
+}
+
+impl CalcType {
+    /// Returns the type of adding two types, or None if they can't be added.
+    fn add(a: CalcType, b: CalcType) -> Option<CalcType> {
+        match (a, b) {
+            _ if a == b => Some(a),
+            (CalcType::Number, _) | (_, CalcType::Number) => None,
+            _ => Some(CalcType::LengthPercentage),
+        }
+    }
+}
+
+impl Unit {
+    /// Returns a unit for its lowercase css spelling.
+    pub fn from_name(unit: &str) -> Option<Unit> {
+        match unit {
+            "em" => Some(Unit::Em),
+            "ex" => Some(Unit::Ex),
+            "ch" => Some(Unit::Ch),
+            "rem" => Some(Unit::Rem),
+            "vh" => Some(Unit::Vh),
+            "vw" => Some(Unit::Vw),
+            "vmin" => Some(Unit::Vmin),
+            "vmax" => Some(Unit::Vmax),
+            "px" => Some(Unit::Px),
+            "mm" => Some(Unit::Mm),
+            "q" => Some(Unit::Q),
+            "cm" => Some(Unit::Cm),
+            "in" => Some(Unit::In),
+            "pt" => Some(Unit::Pt),
+            "pc" => Some(Unit::Pc),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of pixels in an absolute unit, or None for a relative unit.
+    /// An inch is 96px.
+    pub fn absolute_px(&self) -> Option<f32> {
+        match *self {
+            Unit::Px => Some(1.0),
+            Unit::In => Some(96.0),
+            Unit::Cm => Some(96.0 / 2.54),
+            Unit::Mm => Some(96.0 / 25.4),
+            Unit::Q => Some(96.0 / 101.6),
+            Unit::Pt => Some(96.0 / 72.0),
+            Unit::Pc => Some(16.0),
+            _ => None,
+        }
+    }
+
+    /// Returns the css spelling of the unit.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Unit::Em => "em",
+            Unit::Ex => "ex",
+            Unit::Ch => "ch",
+            Unit::Rem => "rem",
+            Unit::Vh => "vh",
+            Unit::Vw => "vw",
+            Unit::Vmin => "vmin",
+            Unit::Vmax => "vmax",
+            Unit::Px => "px",
+            Unit::Mm => "mm",
+            Unit::Q => "q",
+            Unit::Cm => "cm",
+            Unit::In => "in",
+            Unit::Pt => "pt",
+            Unit::Pc => "pc",
         }
     }
 }
@@ -283,6 +767,8 @@ mod tests {
         let ss = Stylesheet::default();
 
         assert_eq!(ss.rules, vec![]);
+        assert_eq!(ss.imports, vec![]);
+        assert_eq!(ss.origin, Origin::Author);
     }
 
     /// Test a new rule is constructed correctly.
@@ -292,6 +778,7 @@ mod tests {
 
         assert_eq!(rule.selectors, vec![]);
         assert_eq!(rule.declarations, vec![]);
+        assert_eq!(rule.media, vec![]);
     }
 
     /// Test a new selector is constructed correctly.
@@ -303,6 +790,44 @@ mod tests {
         assert_eq!(sel.combinators, vec![]);
     }
 
+    /// Test the specificity of a selector counts ids, classes and tags.
+    #[test]
+    fn selector_specificity() {
+        let ss = SimpleSelector::new(Some(String::from("div")), Some(String::from("main")),
+            vec![String::from("a"), String::from("b")]);
+        let sel = Selector::new(vec![ss], vec![]);
+
+        assert_eq!(Specificity(1, 2, 1), sel.specificity());
+        assert_eq!(Specificity(0, 0, 0), Selector::default().specificity());
+    }
+
+    /// Test specificities compare ids before classes before tags.
+    #[test]
+    fn specificity_ordering() {
+        assert!(Specificity(1, 0, 0) > Specificity(0, 10, 10));
+        assert!(Specificity(0, 1, 0) > Specificity(0, 0, 10));
+        assert!(Specificity(0, 0, 2) > Specificity(0, 0, 1));
+    }
+
+    /// Test keywords convert to and from their css spelling.
+    #[test]
+    fn keyword_spelling() {
+        assert_eq!(Some(Keyword::InlineBlock), Keyword::from_ident("inline-block"));
+        assert_eq!(Some(Keyword::None), Keyword::from_ident("none"));
+        assert_eq!(None, Keyword::from_ident("georgia"));
+        assert_eq!("table-row-group", Keyword::TableRowGroup.as_str());
+        assert!(Keyword::Unset.is_css_wide());
+        assert!(!Keyword::Auto.is_css_wide());
+    }
+
+    /// Test units convert to and from their css spelling.
+    #[test]
+    fn unit_spelling() {
+        assert_eq!(Some(Unit::Vmin), Unit::from_name("vmin"));
+        assert_eq!(None, Unit::from_name("deg"));
+        assert_eq!("q", Unit::Q.as_str());
+    }
+
     /// Test a new simple selector is constructed correctly.
     #[test]
     fn new_simple() {
@@ -321,5 +846,58 @@ mod tests {
 
         assert_eq!(decl.property, "");
         assert_eq!(decl.value, Value::Other(String::from("")));
+        assert!(!decl.important);
+    }
+
+    /// Wrap a value in a leaf of a math expression.
+    fn leaf(v: Value) -> Box<CalcNode> {
+        Box::new(CalcNode::Leaf(v))
+    }
+
+    /// Test math expressions type check their operands.
+    #[test]
+    fn calc_types() {
+        let px = || leaf(Value::Length(1.0, Unit::Px));
+        let pct = || leaf(Value::Percentage(1.0));
+        let num = || leaf(Value::Number(2.0));
+
+        assert_eq!(Some(CalcType::LengthPercentage), CalcNode::Sum(pct(), px()).calc_type());
+        assert_eq!(Some(CalcType::Length), CalcNode::Product(num(), px()).calc_type());
+        assert_eq!(Some(CalcType::Length), CalcNode::Quotient(px(), num()).calc_type());
+        assert_eq!(None, CalcNode::Quotient(num(), px()).calc_type());
+        assert_eq!(None, CalcNode::Product(px(), px()).calc_type());
+        assert_eq!(None, CalcNode::Difference(px(), num()).calc_type());
+        assert_eq!(Some(CalcType::LengthPercentage), CalcNode::Clamp(px(), pct(), px()).calc_type());
+        assert_eq!(None, CalcNode::Min(vec![]).calc_type());
+    }
+
+    /// Test math expressions evaluate with the resolved values of their leaves.
+    #[test]
+    fn calc_evaluate() {
+        let resolve = |v: &Value| match *v {
+            Value::Length(n, Unit::Px) | Value::Number(n) => Some(n),
+            Value::Percentage(p) => Some(p * 2.0),
+            _ => None,
+        };
+        let px = |n| leaf(Value::Length(n, Unit::Px));
+
+        let sum = CalcNode::Difference(leaf(Value::Percentage(50.0)),
+            Box::new(CalcNode::Product(leaf(Value::Number(2.0)), px(16.0))));
+        assert_eq!(Some(68.0), sum.evaluate(&resolve));
+        assert_eq!(Some(1.0), CalcNode::Min(vec![*px(3.0), *px(1.0), *px(2.0)]).evaluate(&resolve));
+        assert_eq!(Some(5.0), CalcNode::Clamp(px(5.0), px(1.0), px(9.0)).evaluate(&resolve));
+        assert_eq!(Some(5.0), CalcNode::Clamp(px(5.0), px(7.0), px(1.0)).evaluate(&resolve));
+        assert_eq!(None, CalcNode::Quotient(px(1.0), leaf(Value::Number(0.0))).evaluate(&resolve));
+        assert_eq!(None, CalcNode::Sum(px(1.0), leaf(Value::Length(1.0, Unit::Em))).evaluate(&resolve));
+    }
+
+    /// Test absolute units convert to pixels and relative units don't.
+    #[test]
+    fn unit_absolute_px() {
+        assert_eq!(Some(96.0), Unit::In.absolute_px());
+        assert_eq!(Some(12.0), Unit::Pt.absolute_px().map(|px| px * 9.0));
+        assert_eq!(Some(96.0), Unit::Cm.absolute_px().map(|px| (px * 2.54).round()));
+        assert_eq!(None, Unit::Em.absolute_px());
+        assert_eq!(None, Unit::Vw.absolute_px());
     }
 }
@@ -1,12 +1,24 @@
 //! The `css_parse` module parses css stylesheets into css rule datastructures.
 
-use css::{Color, Declaration, Rule, Selector, SimpleSelector, Stylesheet, Unit, Value};
+use css::{CalcNode, Color, Declaration, Import, Keyword, Rule, Selector, Separator, SimpleSelector, SourceLocation,
+    Stylesheet, Unit, Value};
+use media::{ColorScheme, MediaFeature, MediaQuery, MediaQueryList, MediaType, Orientation, Range};
+use properties;
 
 use std::iter::Peekable;
 use std::str::Chars;
 
 pub struct CssParser<'a> {
+    chars: SourceChars<'a>,
+    imports: Vec<Import>,
+}
+
+/// The characters of the css being parsed, which keep track of the line and column of the
+/// next character.
+struct SourceChars<'a> {
     chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
 }
 
 impl<'a> CssParser<'a> {
@@ -14,23 +26,105 @@ impl<'a> CssParser<'a> {
     ///
     /// full_css: the complete css stylesheet to parse.
     pub fn new(full_css: &str) -> CssParser {
-        CssParser { chars: full_css.chars().peekable() }
+        CssParser {
+            chars: SourceChars { chars: full_css.chars().peekable(), line: 1, column: 1 },
+            imports: Vec::new(),
+        }
     }
 
     /// Entry point to parsing css, iterively parse css rules.
     pub fn parse_stylesheet(&mut self) -> Stylesheet {
-        let mut stylesheet = Stylesheet::default();
+        let mut stylesheet = Stylesheet::new(self.parse_rules(false));
+        stylesheet.imports.append(&mut self.imports);
+
+        stylesheet
+    }
 
-        while self.chars.peek().is_some() {
-            let selectors = self.parse_selectors();
-            let styles = self.parse_declarations();
-            let rule = Rule::new(selectors, styles);
+    /// Parse rules until the end of the css, or the end of the enclosing block if nested.
+    fn parse_rules(&mut self, nested: bool) -> Vec<Rule> {
+        let mut rules = Vec::new();
 
+        loop {
+            self.consume_while(char::is_whitespace);
 
-            stylesheet.rules.push(rule);
+            match self.chars.peek() {
+                None => break,
+                Some(&'}') => {
+                    // a stray closing brace at the top level is skipped
+                    self.chars.next();
+                    if nested {
+                        break;
+                    }
+                },
+                Some(&'@') => {
+                    // @import has to come before every style rule
+                    let imports_allowed = !nested && rules.is_empty();
+                    rules.append(&mut self.parse_at_rule(imports_allowed));
+                },
+                Some(_) => {
+                    let location = SourceLocation { url: None, line: self.chars.line, column: self.chars.column };
+                    let selectors = self.parse_selectors();
+                    let styles = self.parse_declarations();
+                    rules.push(Rule { location, ..Rule::new(selectors, styles) });
+                },
+            }
         }
 
-        stylesheet
+        rules
+    }
+
+    /// Parse an at-rule, returning the style rules it contains.
+    /// Imports are collected separately, unsupported at-rules are skipped.
+    ///
+    /// imports_allowed: whether an @import is valid at this position.
+    fn parse_at_rule(&mut self, imports_allowed: bool) -> Vec<Rule> {
+        self.chars.next();
+        let name = self.parse_identifier();
+        let prelude = self.consume_while(|c| c != '{' && c != ';');
+        let has_block = self.chars.next() == Some('{');
+
+        match &name[..] {
+            "media" if has_block => {
+                let media = parse_media_query_list(&prelude);
+                let mut rules = self.parse_rules(true);
+
+                // outer conditions come first for rules in nested @media blocks
+                for rule in &mut rules {
+                    rule.media.insert(0, media.clone());
+                }
+                rules
+            },
+            "import" if imports_allowed && !has_block => {
+                if let Some(import) = parse_import(&prelude) {
+                    self.imports.push(import);
+                }
+                Vec::new()
+            },
+            _ => {
+                if has_block {
+                    self.skip_block();
+                }
+                Vec::new()
+            },
+        }
+    }
+
+    /// Consumes the rest of a block whose opening brace was consumed, including nested blocks.
+    fn skip_block(&mut self) {
+        let mut depth = 1;
+
+        for c in self.chars.by_ref() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                },
+                _ => {},
+            }
+        }
     }
 
     /// Parse the selectors for a single rule.
@@ -55,47 +149,76 @@ impl<'a> CssParser<'a> {
     }
 
     /// Parse a single selector in a comma seperated list of selectors.
+    ///
+    /// Compound selectors are combined by the descendant (whitespace) and child (>)
+    /// combinators. An invalid selector is consumed and returned empty.
     fn parse_selector(&mut self) -> Selector {
-        let mut sselector = SimpleSelector::default();
         let mut selector = Selector::default();
 
         self.consume_while(char::is_whitespace);
 
-        sselector.tag_name = match self.chars.peek() {
-            Some(&c) if is_valid_start_ident(c) => Some(self.parse_identifier()),
-            _ => None,
+        while let Some(simple) = self.parse_simple_selector() {
+            selector.simple.push(simple);
+
+            let whitespace = !self.consume_while(char::is_whitespace).is_empty();
+
+            match self.chars.peek() {
+                None | Some(&',') | Some(&'{') => return selector,
+                Some(&'>') => {
+                    self.chars.next();
+                    self.consume_while(char::is_whitespace);
+                    selector.combinators.push('>');
+                },
+                _ if whitespace => selector.combinators.push(' '),
+                _ => break,
+            }
+        }
+
+        // consume invalid selector
+        self.consume_while(|c| c != ',' && c != '{');
+        Selector::default()
+    }
+
+    /// Parse a compound selector of a tag name or *, an id and classes, ex. "p#main.note".
+    /// Returns None if the compound is empty or invalid.
+    fn parse_simple_selector(&mut self) -> Option<SimpleSelector> {
+        let mut sselector = SimpleSelector::default();
+
+        // a lone * is the universal selector, which has no conditions
+        let mut parsed = match self.chars.peek() {
+            Some(&'*') => { self.chars.next(); true },
+            Some(&c) if is_valid_start_ident(c) => { sselector.tag_name = Some(self.parse_identifier()); true },
+            _ => false,
         };
 
-        let mut multiple_ids = false;
-        while self.chars.peek().map_or(false, |c| *c != ',' && *c != '{' && !(*c).is_whitespace()) {
+        loop {
             match self.chars.peek() {
-                Some(&c) if c =='#' =>  {
+                Some(&'#') => {
                     self.chars.next();
-                    if sselector.id.is_some() || multiple_ids {
-                        sselector.id = None;
-                        multiple_ids = true;
-                        self.parse_id();
-                    } else {
-                        sselector.id = self.parse_id();
+                    if sselector.id.is_some() {
+                        return None;
                     }
+                    sselector.id = Some(self.parse_id()?);
                 },
-                Some(&c) if c == '.' => {
+                Some(&'.') => {
                     self.chars.next();
                     let class_name = self.parse_identifier();
 
-                    if class_name != String::from("") {
-                        sselector.classes.push(class_name);
+                    if class_name.is_empty() {
+                        return None;
                     }
+                    sselector.classes.push(class_name);
                 },
-                _ => { self.consume_while(|c| c != ',' && c != '{'); }, // consume invalid selector
+                _ => break,
             }
+            parsed = true;
         }
 
-        if sselector != SimpleSelector::default() {
-            selector.simple.push(sselector);
+        if parsed {
+            Some(sselector)
+        } else {
+            None
         }
-
-        selector
     }
 
     /// Parse a css identifier.
@@ -122,7 +245,13 @@ impl<'a> CssParser<'a> {
         }
     }
 
+    /// Parse a declaration list that isn't part of a rule, ex. the style attribute of an element.
+    pub fn parse_declaration_list(&mut self) -> Vec<Declaration> {
+        self.parse_declarations()
+    }
+
     /// Parse all the declarations for a rule.
+    /// A block left open at the end of the css is closed.
     fn parse_declarations(&mut self) -> Vec<Declaration> {
         let mut declarations = Vec::<Declaration>::new();
 
@@ -131,29 +260,26 @@ impl<'a> CssParser<'a> {
 
             let property = self.consume_while(|x| x != ':').to_lowercase();
 
-            self.chars.next();
+            // a property without a value, ex. at the end of a style attribute, is invalid
+            let has_value = self.chars.next().is_some();
             self.consume_while(char::is_whitespace);
 
-            let value = self.consume_while(|x| x != ';' && x != '\n' && x != '}').to_lowercase();
-
-            let value_enum = match property.as_ref() {
-                "background-color"|"border-color"|"color" => Value::Color(translate_color(&value)),
-                "margin-right"|"margin-bottom"|"margin-left"|"margin-top"|
-                "padding-right"|"padding-bottom"|"padding-left"|"padding-top"|
-                "border-right-width"|"border-bottom-width"|"border-left-width"|"border-top-width"|
-                "height"|"width" => translate_length(&value),
-                _ => Value::Other(value),
-            };
+            let raw_value = lowercase_outside_strings(&self.consume_while(|x| x != ';' && x != '\n' && x != '}'));
+            let (value, important) = split_important(&raw_value);
+            let value = value.to_string();
 
-            let declaration = Declaration::new(property, value_enum);
+            let mut expanded = if has_value { expand_declaration(&property, &value) } else { Vec::new() };
+            for declaration in &mut expanded {
+                declaration.important = important;
+            }
 
             if self.chars.peek().map_or(false, |c| *c == ';') {
-                declarations.push(declaration);
+                declarations.append(&mut expanded);
                 self.chars.next();
             } else {
                 self.consume_while(char::is_whitespace);
-                if self.chars.peek().map_or(false, |c| *c == '}') {
-                    declarations.push(declaration);
+                if self.chars.peek().is_none_or(|c| *c == '}') {
+                    declarations.append(&mut expanded);
                 }
             }
             self.consume_while(char::is_whitespace);
@@ -176,234 +302,1217 @@ impl<'a> CssParser<'a> {
     }
 }
 
-fn translate_length(value: &str) -> Value {
-    let mut num_str = String::new();
-    let mut unit = String::new();
-    let mut parsing_num = true;
+impl<'a> SourceChars<'a> {
+    /// Returns the next character without consuming it.
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+}
+
+impl<'a> Iterator for SourceChars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+
+        match c {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            },
+            Some(_) => self.column += 1,
+            None => {},
+        }
+        c
+    }
+}
+
+/// Splits a trailing "!important" off of a declaration value.
+/// Returns the remaining value and whether the flag was present.
+fn split_important(value: &str) -> (&str, bool) {
+    let trimmed = value.trim_end();
+
+    if let Some(rest) = trimmed.strip_suffix("important") {
+        if let Some(rest) = rest.trim_end().strip_suffix('!') {
+            return (rest.trim_end(), true);
+        }
+    }
+
+    (trimmed, false)
+}
+
+/// Parses the prelude of an @import rule, ex. "url(print.css) print".
+/// Returns None if it doesn't start with a url or a string.
+fn parse_import(prelude: &str) -> Option<Import> {
+    let prelude = prelude.trim();
+    let end = split_components(prelude).first()?.len();
+
+    let url = match parse_component(&prelude[..end])? {
+        Value::Url(url) | Value::Str(url) => url,
+        _ => return None,
+    };
+
+    Some(Import { url, media: parse_media_query_list(&prelude[end..]) })
+}
+
+/// Parses the comma separated media queries of an @media or @import rule.
+/// Malformed queries are replaced with "not all", an empty list matches all media.
+pub fn parse_media_query_list(prelude: &str) -> MediaQueryList {
+    let prelude = prelude.trim().to_lowercase();
+
+    if prelude.is_empty() {
+        return MediaQueryList::default();
+    }
+
+    let queries = split_top_level(&prelude, |c| c == ',').iter()
+        .map(|query| parse_media_query(query).unwrap_or_else(MediaQuery::not_all))
+        .collect();
+
+    MediaQueryList::new(queries)
+}
+
+/// Parses a single media query, ex. "only screen and (min-width: 600px)".
+fn parse_media_query(query: &str) -> Option<MediaQuery> {
+    let parts = split_components(query);
+    let mut parts = parts.iter().map(|p| &p[..]).peekable();
+    let mut media_type = MediaType::All;
+    let mut features = Vec::new();
+
+    // "only" hides a query from old browsers and doesn't change its meaning
+    let negated = parts.peek() == Some(&"not");
+    if negated || parts.peek() == Some(&"only") {
+        parts.next();
+    }
+
+    match parts.next()? {
+        p if p.starts_with('(') => features.push(parse_media_feature(p)?),
+        p if is_identifier(p) && !matches!(p, "and" | "not" | "only" | "or") => {
+            media_type = MediaType::from_name(p);
+        },
+        _ => return None,
+    }
+
+    while let Some(part) = parts.next() {
+        if part != "and" {
+            return None;
+        }
+        features.push(parse_media_feature(parts.next()?)?);
+    }
+
+    Some(MediaQuery { negated, media_type, features })
+}
+
+/// Parses a parenthesized media feature, ex. "(max-width: 40em)" or "(orientation: portrait)".
+fn parse_media_feature(feature: &str) -> Option<MediaFeature> {
+    let inner = feature.strip_prefix('(')?.strip_suffix(')')?;
+    let colon = inner.find(':')?;
+    let name = inner[..colon].trim();
+    let value = parse_value(inner[colon + 1..].trim())?;
+
+    let length = match value {
+        Value::Length(..) => Some(value.clone()),
+        Value::Number(0.0) => Some(Value::Length(0.0, Unit::Px)),
+        _ => None,
+    };
+    let ident = match value {
+        Value::Ident(ref i) => &i[..],
+        _ => "",
+    };
+
+    match name {
+        "width" => Some(MediaFeature::Width(Range::Exact, length?)),
+        "min-width" => Some(MediaFeature::Width(Range::Min, length?)),
+        "max-width" => Some(MediaFeature::Width(Range::Max, length?)),
+        "height" => Some(MediaFeature::Height(Range::Exact, length?)),
+        "min-height" => Some(MediaFeature::Height(Range::Min, length?)),
+        "max-height" => Some(MediaFeature::Height(Range::Max, length?)),
+        "orientation" => match ident {
+            "portrait" => Some(MediaFeature::Orientation(Orientation::Portrait)),
+            "landscape" => Some(MediaFeature::Orientation(Orientation::Landscape)),
+            _ => None,
+        },
+        "prefers-color-scheme" => match ident {
+            "light" => Some(MediaFeature::PrefersColorScheme(ColorScheme::Light)),
+            "dark" => Some(MediaFeature::PrefersColorScheme(ColorScheme::Dark)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Translates the value of a longhand property into a typed value.
+/// Returns None if the value is invalid for the property.
+fn translate_value(property: &str, value: &str) -> Option<Value> {
+    match parse_value(value) {
+        Some(v) => properties::validate(property, v),
+        // keep values that can't be typed for properties without a grammar
+        None if !properties::is_known(property) => Some(Value::Other(value.to_string())),
+        None => None,
+    }
+}
+
+/// Parses a value into a single component or a list of components.
+/// Commas separate the outer list and whitespace separates the inner lists.
+/// Returns None if any component can't be parsed.
+pub fn parse_value(value: &str) -> Option<Value> {
+    let mut items = split_top_level(value, |c| c == ',').iter()
+        .map(|item| parse_space_list(item))
+        .collect::<Option<Vec<Value>>>()?;
+
+    if items.len() == 1 {
+        items.pop()
+    } else {
+        Some(Value::List(items, Separator::Comma))
+    }
+}
+
+/// Parses whitespace separated components, a single component isn't wrapped in a list.
+fn parse_space_list(value: &str) -> Option<Value> {
+    let mut parts = split_components(value).iter()
+        .map(|part| parse_component(part))
+        .collect::<Option<Vec<Value>>>()?;
+
+    match parts.len() {
+        0 => None,
+        1 => parts.pop(),
+        _ => Some(Value::List(parts, Separator::Space)),
+    }
+}
+
+/// Parses a single component of a value, ex. "12px", "auto", "'a string'" or "rgb(0 0 0)".
+fn parse_component(token: &str) -> Option<Value> {
+    let first = token.chars().next()?;
+
+    if first == '"' || first == '\'' {
+        return unquote(token).map(Value::Str);
+    }
+
+    if let Some(hex) = token.strip_prefix('#') {
+        return parse_hex_color(hex).map(Value::Color);
+    }
+
+    if let Some(open) = token.find('(') {
+        let name = &token[..open];
+        let args = token[open + 1..].strip_suffix(')')?;
+
+        if !is_identifier(name) {
+            return None;
+        }
+
+        return match name {
+            "url" => {
+                let url = args.trim();
+                Some(Value::Url(unquote(url).unwrap_or_else(|| url.to_string())))
+            },
+            "rgb"|"rgba"|"hsl"|"hsla" => parse_color(token).map(Value::Color),
+            "calc"|"min"|"max"|"clamp" => parse_math(name, args).map(|n| Value::Calc(Box::new(n))),
+            _ if args.trim().is_empty() => Some(Value::Function(name.to_string(), Vec::new())),
+            _ => {
+                let args = split_top_level(args, |c| c == ',').iter()
+                    .map(|arg| parse_space_list(arg))
+                    .collect::<Option<Vec<Value>>>()?;
+                Some(Value::Function(name.to_string(), args))
+            },
+        };
+    }
+
+    if is_numeric_start(token) {
+        let (number, unit) = parse_number(token)?;
+
+        return match unit {
+            "" => Some(Value::Number(number)),
+            "%" => Some(Value::Percentage(number)),
+            u if is_identifier(u) => match Unit::from_name(u) {
+                Some(length_unit) => Some(Value::Length(number, length_unit)),
+                None => Some(Value::Dimension(number, u.to_string())),
+            },
+            _ => None,
+        };
+    }
+
+    if is_identifier(token) {
+        return match Keyword::from_ident(token) {
+            Some(k) => Some(Value::Keyword(k)),
+            None => Some(Value::Ident(token.to_string())),
+        };
+    }
+
+    None
+}
+
+/// Parses the arguments of a math function into an expression tree.
+/// Returns None if the expression is malformed or mixes incompatible types.
+///
+/// name: the math function, calc, min, max or clamp.
+/// args: the text between the function's parentheses.
+fn parse_math(name: &str, args: &str) -> Option<CalcNode> {
+    let args = split_top_level(args, |c| c == ',').iter()
+        .map(|arg| parse_calc_sum(arg))
+        .collect::<Option<Vec<CalcNode>>>()?;
+
+    let node = match (name, args.len()) {
+        ("calc", 1) => args.into_iter().next()?,
+        ("min", _) => CalcNode::Min(args),
+        ("max", _) => CalcNode::Max(args),
+        ("clamp", 3) => {
+            let mut args = args.into_iter().map(Box::new);
+            CalcNode::Clamp(args.next()?, args.next()?, args.next()?)
+        },
+        _ => return None,
+    };
+
+    node.calc_type().map(|_| node)
+}
+
+/// Parses a sum of products, ex. "100% - 2 * 16px".
+/// Addition and subtraction have to be surrounded by whitespace.
+fn parse_calc_sum(expr: &str) -> Option<CalcNode> {
+    let mut sum: Option<CalcNode> = None;
+    let mut operator = None;
+    let mut product = Vec::new();
+
+    // the product before each operator is added once the operator is reached
+    for part in split_components(expr).into_iter().chain(Some(String::from("+"))) {
+        if part != "+" && part != "-" {
+            product.push(part);
+            continue;
+        }
+
+        let term = parse_calc_product(&product.join(" "))?;
+        product.clear();
+
+        sum = Some(match (sum, operator) {
+            (None, _) => term,
+            (Some(s), Some("+")) => CalcNode::Sum(Box::new(s), Box::new(term)),
+            (Some(s), _) => CalcNode::Difference(Box::new(s), Box::new(term)),
+        });
+        operator = Some(if part == "+" { "+" } else { "-" });
+    }
+
+    sum
+}
+
+/// Parses a product of values, ex. "2 * 16px" or "100%/3".
+fn parse_calc_product(expr: &str) -> Option<CalcNode> {
+    let mut factors = Vec::new();
+    let mut operator = '*';
+    let mut start = 0;
+    let mut depth = 0;
+
+    // each factor is paired with the operator before it
+    for (i, c) in expr.char_indices().chain(Some((expr.len(), '*'))) {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '*'|'/' if depth == 0 => {
+                factors.push((operator, parse_calc_value(expr[start..i].trim())?));
+                operator = c;
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+
+    let mut factors = factors.into_iter();
+    let first = factors.next()?.1;
+
+    Some(factors.fold(first, |product, (operator, factor)| match operator {
+        '*' => CalcNode::Product(Box::new(product), Box::new(factor)),
+        _ => CalcNode::Quotient(Box::new(product), Box::new(factor)),
+    }))
+}
+
+/// Parses a single value of a math expression: a number, percentage, length, nested math
+/// function or parenthesized sum.
+fn parse_calc_value(value: &str) -> Option<CalcNode> {
+    if let Some(inner) = value.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+        return parse_calc_sum(inner);
+    }
+
+    match parse_component(value)? {
+        v @ Value::Number(_) | v @ Value::Percentage(_) | v @ Value::Length(..) => Some(CalcNode::Leaf(v)),
+        Value::Calc(node) => Some(*node),
+        _ => None,
+    }
+}
+
+/// Removes the matching quotes around a string, or returns None if it isn't quoted.
+fn unquote(value: &str) -> Option<String> {
+    let quote = value.chars().next()?;
+
+    if (quote == '"' || quote == '\'') && value.len() >= 2 && value.ends_with(quote) {
+        Some(value[1..value.len() - 1].to_string())
+    } else {
+        None
+    }
+}
+
+/// Lowercases a value except for the contents of strings and urls.
+fn lowercase_outside_strings(value: &str) -> String {
+    let mut result = String::new();
+    let mut quote = None;
+    let mut in_url = false;
 
     for c in value.chars() {
-        if c.is_numeric() && parsing_num {
-            num_str.push(c);
+        match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                }
+                result.push(c);
+            },
+            None if in_url => {
+                if c == ')' {
+                    in_url = false;
+                }
+                result.push(c);
+            },
+            None => {
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                result.extend(c.to_lowercase());
+                in_url = result.ends_with("url(");
+            },
+        }
+    }
+
+    result
+}
+
+/// Expands a declaration into its longhand declarations.
+/// Invalid declarations, including shorthands with an invalid longhand, produce no
+/// declarations.
+///
+/// property: the property name, which may be a shorthand.
+/// value: the value of the property.
+fn expand_declaration(property: &str, value: &str) -> Vec<Declaration> {
+    if property.starts_with("--") {
+        return vec![Declaration::new(property.to_string(), custom_value(property, value))];
+    }
+
+    // values with var() references are parsed once they're substituted at computed-value time
+    if find_var(value).is_some() {
+        let pending = Value::Var(property.to_string(), value.to_string());
+        return match longhands(property) {
+            Some(names) => names.into_iter().map(|p| Declaration::new(p, pending.clone())).collect(),
+            None => vec![Declaration::new(property.to_string(), pending)],
+        };
+    }
+
+    let expanded = match property {
+        "margin" => expand_edges("margin-", "", value),
+        "padding" => expand_edges("padding-", "", value),
+        "border-width" => expand_edges("border-", "-width", value),
+        "border-style" => expand_edges("border-", "-style", value),
+        "border-color" => expand_edges("border-", "-color", value),
+        "border" => expand_border(value),
+        "background" => expand_background(value),
+        "font" => expand_font(value),
+        _ => Some(vec![(property.to_string(), value.to_string())]),
+    };
+
+    expanded.unwrap_or_default().into_iter()
+        .map(|(p, v)| translate_value(&p, &v).map(|value| Declaration::new(p, value)))
+        .collect::<Option<Vec<Declaration>>>()
+        .unwrap_or_default()
+}
+
+/// Expands a value of 1 to 4 components into the top, right, bottom and left edges.
+///
+/// One value applies to all edges, two to top/bottom and left/right, three to top,
+/// left/right and bottom, and four to each edge clockwise from the top.
+fn expand_edges(prefix: &str, suffix: &str, value: &str) -> Option<Vec<(String, String)>> {
+    let parts = split_components(value);
+
+    let (top, right, bottom, left) = match parts.len() {
+        1 => (0, 0, 0, 0),
+        2 => (0, 1, 0, 1),
+        3 => (0, 1, 2, 1),
+        4 => (0, 1, 2, 3),
+        _ => return None,
+    };
+
+    Some(vec![
+        (format!("{}top{}", prefix, suffix), parts[top].clone()),
+        (format!("{}right{}", prefix, suffix), parts[right].clone()),
+        (format!("{}bottom{}", prefix, suffix), parts[bottom].clone()),
+        (format!("{}left{}", prefix, suffix), parts[left].clone()),
+    ])
+}
+
+/// Returns the longhands a shorthand property sets, or None if the property isn't a shorthand.
+fn longhands(property: &str) -> Option<Vec<String>> {
+    let edges = |prefix: &str, suffix: &str| {
+        ["top", "right", "bottom", "left"].iter()
+            .map(|side| format!("{}{}{}", prefix, side, suffix))
+            .collect::<Vec<String>>()
+    };
+    let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<String>>();
+
+    let longhands = match property {
+        "margin" => edges("margin-", ""),
+        "padding" => edges("padding-", ""),
+        "border-width" => edges("border-", "-width"),
+        "border-style" => edges("border-", "-style"),
+        "border-color" => edges("border-", "-color"),
+        "border" => [edges("border-", "-width"), edges("border-", "-style"), edges("border-", "-color")].concat(),
+        "background" => names(&["background-color", "background-image", "background-repeat",
+            "background-attachment", "background-position"]),
+        "font" => names(&["font-style", "font-variant", "font-weight", "font-size", "line-height",
+            "font-family"]),
+        _ => return None,
+    };
+
+    Some(longhands)
+}
+
+/// Returns the value of a custom property, its trimmed text.
+/// Text with var() references is substituted at computed-value time.
+fn custom_value(property: &str, value: &str) -> Value {
+    let value = value.trim();
+
+    if find_var(value).is_some() {
+        Value::Var(property.to_string(), value.to_string())
+    } else {
+        Value::Other(value.to_string())
+    }
+}
+
+/// Parses the value of a longhand once the var() references of its declaration are substituted.
+/// Returns None if the value is invalid at computed-value time.
+///
+/// property: the longhand to return the value of.
+/// declared: the property the value was declared for, the longhand or one of its shorthands.
+/// value: the declared value with its references substituted.
+pub fn parse_substituted(property: &str, declared: &str, value: &str) -> Option<Value> {
+    if find_var(value).is_some() {
+        return None;
+    }
+
+    expand_declaration(declared, value).into_iter()
+        .find(|d| d.property == property)
+        .map(|d| d.value)
+}
+
+/// Replaces the var() references in a value with the values of the custom properties.
+/// A reference to a missing property uses its fallback. Returns None if a reference has
+/// neither a value nor a fallback.
+///
+/// value: the text to substitute references in.
+/// lookup: returns the value of a custom property by name.
+pub fn substitute_vars(value: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(start) = find_var(rest) {
+        let args_start = start + "var(".len();
+        let args_end = args_start + closing_paren(&rest[args_start..])?;
+        let args = &rest[args_start..args_end];
+
+        let (name, fallback) = match args.find(',') {
+            Some(comma) => (args[..comma].trim(), Some(args[comma + 1..].trim())),
+            None => (args.trim(), None),
+        };
+        if !name.starts_with("--") {
+            return None;
+        }
+
+        let replacement = match (lookup(name), fallback) {
+            (Some(v), _) => v,
+            (None, Some(f)) => substitute_vars(f, lookup)?,
+            (None, None) => return None,
+        };
+
+        result.push_str(&rest[..start]);
+        result.push_str(&replacement);
+        rest = &rest[args_end + 1..];
+    }
+
+    result.push_str(rest);
+    Some(result)
+}
+
+/// Returns the position of the parenthesis closing the one opened before the value.
+fn closing_paren(value: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+
+    for (i, c) in value.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None => match c {
+                '"'|'\'' => quote = Some(c),
+                '(' => depth += 1,
+                ')' if depth == 0 => return Some(i),
+                ')' => depth -= 1,
+                _ => {},
+            },
+        }
+    }
+
+    None
+}
+
+/// Returns the position of the first var() reference in a value outside of strings.
+fn find_var(value: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut previous = ' ';
+
+    for (i, c) in value.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => {
+                if value[i..].starts_with("var(") && !is_valid_ident(previous) {
+                    return Some(i);
+                }
+            },
+        }
+        previous = c;
+    }
+
+    None
+}
+
+/// Expands the border shorthand, ex. "1px solid red", into widths, styles and colors.
+/// The components can be in any order, missing components are reset to their initial value.
+fn expand_border(value: &str) -> Option<Vec<(String, String)>> {
+    let mut width = None;
+    let mut style = None;
+    let mut color = None;
+
+    for part in split_components(value) {
+        let slot = if is_numeric_start(&part) || is_border_width_keyword(&part) {
+            &mut width
+        } else if is_border_style_keyword(&part) {
+            &mut style
         } else {
-            unit.push(c);
-            parsing_num = false;
-        }
-    }
-
-    // TODO: make declaration invalid if the number doesn't parse correctly
-    let number = num_str.parse().unwrap_or(0.0);
-
-    match unit.as_ref() {
-        "em" => Value::Length(number, Unit::Em),
-        "ex" => Value::Length(number, Unit::Ex),
-        "ch" => Value::Length(number, Unit::Ch),
-        "rem" => Value::Length(number, Unit::Rem),
-        "vh" => Value::Length(number, Unit::Vh),
-        "vw" => Value::Length(number, Unit::Vw),
-        "vmin" => Value::Length(number, Unit::Vmin),
-        "vmax" => Value::Length(number, Unit::Vmax),
-        "px"|"" => Value::Length(number, Unit::Px),
-        "mm" => Value::Length(number, Unit::Mm),
-        "q" => Value::Length(number, Unit::Q),
-        "cm" => Value::Length(number, Unit::Cm),
-        "in" => Value::Length(number, Unit::In),
-        "pt" => Value::Length(number, Unit::Pt),
-        "pc" => Value::Length(number, Unit::Pc),
-        "%" => Value::Length(number, Unit::Pct),
-        _ => Value::Length(number, Unit::Px), // default to pixel 
-    }
-}
-
-/// Gets an rgba color struct from a string
-/// can be a keywor ex. "red", "blue"
-/// can be a 6 digit hex code #000000
-/// can be a 3 digit hex code #fff
-/// can be rgb() or rgba() functions (can have % of number)
-/// can be hsl() or hsla() functions
-fn translate_color(color: &str) -> Color {
-    if color.starts_with("#") {
-        if color.len() == 7 {
-            let red = match u8::from_str_radix(&color[1..3], 16) {
-                Ok(n) => n as f32 / 255.0,
-                Err(_) => 0.0,
-            };
-            let green = match u8::from_str_radix(&color[3..5], 16) {
-                Ok(n) => n as f32 / 255.0,
-                Err(_) => 0.0,
-            };
-            let blue = match u8::from_str_radix(&color[5..7], 16) {
-                Ok(n) => n as f32 / 255.0,
-                Err(_) => 0.0,
-            };
-            return Color::new(red, green, blue, 1.0);
-        } else if color.len() == 4 {
-            let red = match u8::from_str_radix(&color[1..2], 16) {
-                Ok(n) => n as f32 / 15.0,
-                Err(_) => 0.0,
-            };
-            let green = match u8::from_str_radix(&color[2..3], 16) {
-                Ok(n) => n as f32 / 15.0,
-                Err(_) => 0.0,
-            };
-            let blue = match u8::from_str_radix(&color[3..4], 16) {
-                Ok(n) => n as f32 / 15.0,
-                Err(_) => 0.0,
-            };
-            return Color::new(red, green, blue, 1.0);
+            &mut color
+        };
+
+        // each component can only be given once
+        if slot.is_some() {
+            return None;
+        }
+        *slot = Some(part);
+    }
+
+    let width = width.unwrap_or_else(|| String::from("medium"));
+    let style = style.unwrap_or_else(|| String::from("none"));
+    let color = color.unwrap_or_else(|| String::from("currentcolor"));
+
+    let mut longhands = Vec::new();
+    for &(suffix, ref v) in &[("-width", width), ("-style", style), ("-color", color)] {
+        longhands.append(&mut expand_edges("border-", suffix, v)?);
+    }
+
+    Some(longhands)
+}
+
+/// Expands the background shorthand, ex. "#fff url(bg.png) no-repeat".
+/// Any component that isn't an image, repeat or attachment keyword or position
+/// is taken as the background color.
+fn expand_background(value: &str) -> Option<Vec<(String, String)>> {
+    let mut color = None;
+    let mut image = None;
+    let mut repeat = Vec::new();
+    let mut attachment = None;
+    let mut position = Vec::new();
+
+    for part in split_components(value) {
+        if part == "none" || (part.contains('(') && parse_color(&part).is_none()) {
+            if image.is_some() {
+                return None;
+            }
+            image = Some(part);
+        } else if is_background_repeat_keyword(&part) {
+            repeat.push(part);
+        } else if part == "scroll" || part == "fixed" || part == "local" {
+            attachment = Some(part);
+        } else if is_numeric_start(&part) || is_position_keyword(&part) {
+            position.push(part);
+        } else {
+            if color.is_some() {
+                return None;
+            }
+            color = Some(part);
+        }
+    }
+
+    Some(vec![
+        (String::from("background-color"), color.unwrap_or_else(|| String::from("transparent"))),
+        (String::from("background-image"), image.unwrap_or_else(|| String::from("none"))),
+        (String::from("background-repeat"), join_or(&repeat, "repeat")),
+        (String::from("background-attachment"), attachment.unwrap_or_else(|| String::from("scroll"))),
+        (String::from("background-position"), join_or(&position, "0% 0%")),
+    ])
+}
+
+/// Expands the font shorthand, ex. "italic bold 12px/1.5 sans-serif".
+/// Style, variant and weight can precede the required size and family.
+fn expand_font(value: &str) -> Option<Vec<(String, String)>> {
+    let mut style = None;
+    let mut variant = None;
+    let mut weight = None;
+    let mut parts = split_components(value).into_iter();
+
+    let size_part = loop {
+        let part = parts.next()?;
+
+        if part == "normal" {
+            continue;
+        } else if part == "italic" || part == "oblique" {
+            style = Some(part);
+        } else if part == "small-caps" {
+            variant = Some(part);
+        } else if is_font_weight_keyword(&part) {
+            weight = Some(part);
         } else {
-            return Color::default();
-        }
-    } else if color.starts_with("rgb") {
-        // TODO rgb/rgba function parsing
-        return Color::default();
-    } else if color.starts_with("hsl") {
-        // TODO hsl/hsla function parsing
-        return Color::default();
+            break part;
+        }
+    };
+
+    // the line height follows the size after a slash, ex. "12px/1.5" or "12px / 1.5"
+    let mut rest: Vec<String> = parts.collect();
+    let (size, line_height) = if let Some(slash) = size_part.find('/') {
+        let height = &size_part[slash + 1..];
+        if height.is_empty() {
+            if rest.is_empty() {
+                return None;
+            }
+            (size_part[..slash].to_string(), rest.remove(0))
+        } else {
+            (size_part[..slash].to_string(), height.to_string())
+        }
+    } else if rest.first().is_some_and(|p| p.starts_with('/')) {
+        let slash = rest.remove(0);
+        if slash.len() > 1 {
+            (size_part, slash[1..].to_string())
+        } else if !rest.is_empty() {
+            (size_part, rest.remove(0))
+        } else {
+            return None;
+        }
     } else {
-        return match color {
-            "black" => Color::new(0.0, 0.0, 0.0, 1.0),
-            "silver" => Color::new(0.7529411764705882, 0.7529411764705882, 0.7529411764705882, 1.0),
-            "gray"|"grey" => Color::new(0.5019607843137255, 0.5019607843137255, 0.5019607843137255, 1.0),
-            "white" => Color::new(1.0, 1.0, 1.0, 1.0),
-            "maroon" => Color::new(0.5019607843137255, 0.0, 0.0, 1.0),
-            "red" => Color::new(1.0, 0.0, 0.0, 1.0),
-            "purple" => Color::new(0.5019607843137255, 0.0, 0.5019607843137255, 1.0),
-            "fuchsia" => Color::new(1.0, 0.0, 1.0, 1.0),
-            "green" => Color::new(0.0, 0.5019607843137255, 0.0, 1.0),
-            "lime" => Color::new(0.0, 1.0, 0.0, 1.0),
-            "olive" => Color::new(0.5019607843137255, 0.5019607843137255, 0.0, 1.0),
-            "yellow" => Color::new(1.0, 1.0, 0.0, 1.0),
-            "navy" => Color::new(0.0, 0.0, 0.5019607843137255, 1.0),
-            "blue" => Color::new(0.0, 0.0, 1.0, 1.0),
-            "teal" => Color::new(0.0, 0.5019607843137255, 0.5019607843137255, 1.0),
-            "aqua" => Color::new(0.0, 1.0, 1.0, 1.0),
-            "orange" => Color::new(1.0, 0.6470588235294118, 0.0, 1.0),
-            "aliceblue" => Color::new(0.9411764705882353, 0.9725490196078431, 1.0, 1.0),
-            "antiquewhite" => Color::new(0.9803921568627451, 0.9215686274509803, 0.8431372549019608, 1.0),
-            "aquamarine" => Color::new(0.4980392156862745, 1.0, 0.8313725490196079, 1.0),
-            "azure" => Color::new(0.9411764705882353, 1.0, 1.0, 1.0),
-            "beige" => Color::new(0.9607843137254902, 0.9607843137254902, 0.8627450980392157, 1.0),
-            "bisque" => Color::new(1.0, 0.8941176470588236, 0.7686274509803922, 1.0),
-            "blanchedalmond" => Color::new(1.0, 0.9215686274509803, 0.803921568627451, 1.0),
-            "blueviolet" => Color::new(0.5411764705882353, 0.16862745098039217, 0.8862745098039215, 1.0),
-            "brown" => Color::new(0.6470588235294118, 0.16470588235294117, 0.16470588235294117, 1.0),
-            "burlywood" => Color::new(0.8705882352941177, 0.7215686274509804, 0.5294117647058824, 1.0),
-            "cadetblue" => Color::new(0.37254901960784315, 0.6196078431372549, 0.6274509803921569, 1.0),
-            "chartreuse" => Color::new(0.4980392156862745, 1.0, 0.0, 1.0),
-            "chocolate" => Color::new(0.8235294117647058, 0.4117647058823529, 0.11764705882352941, 1.0),
-            "coral" => Color::new(1.0, 0.4980392156862745, 0.3137254901960784, 1.0),
-            "cornflowerblue" => Color::new(0.39215686274509803, 0.5843137254901961, 0.9294117647058824, 1.0),
-            "cornsilk" => Color::new(1.0, 0.9725490196078431, 0.8627450980392157, 1.0),
-            "crimson" => Color::new(0.8627450980392157, 0.0784313725490196, 0.23529411764705882, 1.0),
-            "darkblue" => Color::new(0.0, 0.0, 0.5450980392156862, 1.0),
-            "darkcyan" => Color::new(0.0, 0.5450980392156862, 0.5450980392156862, 1.0),
-            "darkgoldenrod" => Color::new(0.7215686274509804, 0.5254901960784314, 0.043137254901960784, 1.0),
-            "darkgray"|"darkgrey" => Color::new(0.6627450980392157, 0.6627450980392157, 0.6627450980392157, 1.0),
-            "darkgreen" => Color::new(0.0, 0.39215686274509803, 0.0, 1.0),
-            "darkkhaki" => Color::new(0.7411764705882353, 0.7176470588235294, 0.4196078431372549, 1.0),
-            "darkmagenta" => Color::new(0.5450980392156862, 0.0, 0.5450980392156862, 1.0),
-            "darkolivegreen" => Color::new(0.3333333333333333, 0.4196078431372549, 0.1843137254901961, 1.0),
-            "darkorange" => Color::new(1.0, 0.5490196078431373, 0.0, 1.0),
-            "darkorchid" => Color::new(0.6, 0.19607843137254902, 0.8, 1.0),
-            "darkred" => Color::new(0.5450980392156862, 0.0, 0.0, 1.0),
-            "darksalmon" => Color::new(0.9137254901960784, 0.5882352941176471, 0.47843137254901963, 1.0),
-            "darkseagreen" => Color::new(0.5607843137254902, 0.7372549019607844, 0.5607843137254902, 1.0),
-            "darkslateblue" => Color::new(0.2823529411764706, 0.23921568627450981, 0.5450980392156862, 1.0),
-            "darkslategray"|"darkslategrey" => Color::new(0.1843137254901961, 0.30980392156862746, 0.30980392156862746, 1.0),
-            "darkturquoise" => Color::new(0.0, 0.807843137254902, 0.8196078431372549, 1.0),
-            "darkviolet" => Color::new(0.5803921568627451, 0.0, 0.8274509803921568, 1.0),
-            "deeppink" => Color::new(1.0, 0.0784313725490196, 0.5764705882352941, 1.0),
-            "deepskyblue" => Color::new(0.0, 0.7490196078431373, 1.0, 1.0),
-            "dimgray"|"dimgrey" => Color::new(0.4117647058823529, 0.4117647058823529, 0.4117647058823529, 1.0),
-            "dodgerblue" => Color::new(0.11764705882352941, 0.5647058823529412, 1.0, 1.0),
-            "firebrick" => Color::new(0.6980392156862745, 0.13333333333333333, 0.13333333333333333, 1.0),
-            "floralwhite" => Color::new(1.0, 0.9803921568627451, 0.9411764705882353, 1.0),
-            "forestgreen" => Color::new(0.13333333333333333, 0.5450980392156862, 0.13333333333333333, 1.0),
-            "gainsboro" => Color::new(0.8627450980392157, 0.8627450980392157, 0.8627450980392157, 1.0),
-            "ghostwhite" => Color::new(0.9725490196078431, 0.9725490196078431, 1.0, 1.0),
-            "gold" => Color::new(1.0, 0.8431372549019608, 0.0, 1.0),
-            "goldenrod" => Color::new(0.8549019607843137, 0.6470588235294118, 0.12549019607843137, 1.0),
-            "greenyellow" => Color::new(0.6784313725490196, 1.0, 0.1843137254901961, 1.0),
-            "honeydew" => Color::new(0.9411764705882353, 1.0, 0.9411764705882353, 1.0),
-            "hotpink" => Color::new(1.0, 0.4117647058823529, 0.7058823529411765, 1.0),
-            "indianred" => Color::new(0.803921568627451, 0.3607843137254902, 0.3607843137254902, 1.0),
-            "indigo" => Color::new(0.29411764705882354, 0.0, 0.5098039215686274, 1.0),
-            "ivory" => Color::new(1.0, 1.0, 0.9411764705882353, 1.0),
-            "khaki" => Color::new(0.9411764705882353, 0.9019607843137255, 0.5490196078431373, 1.0),
-            "lavender" => Color::new(0.9019607843137255, 0.9019607843137255, 0.9803921568627451, 1.0),
-            "lavenderblush" => Color::new(1.0, 0.9411764705882353, 0.9607843137254902, 1.0),
-            "lawngreen" => Color::new(0.48627450980392156, 0.9882352941176471, 0.0, 1.0),
-            "lemonchiffon" => Color::new(1.0, 0.9803921568627451, 0.803921568627451, 1.0),
-            "lightblue" => Color::new(0.6784313725490196, 0.8470588235294118, 0.9019607843137255, 1.0),
-            "lightcoral" => Color::new(0.9411764705882353, 0.5019607843137255, 0.5019607843137255, 1.0),
-            "lightcyan" => Color::new(0.8784313725490196, 1.0, 1.0, 1.0),
-            "lightgoldenrodyellow" => Color::new(0.9803921568627451, 0.9803921568627451, 0.8235294117647058, 1.0),
-            "lightgray"|"lightgrey" => Color::new(0.8274509803921568, 0.8274509803921568, 0.8274509803921568, 1.0),
-            "lightgreen" => Color::new(0.5647058823529412, 0.9333333333333333, 0.5647058823529412, 1.0),
-            "lightpink" => Color::new(1.0, 0.7137254901960784, 0.7568627450980392, 1.0),
-            "lightsalmon" => Color::new(1.0, 0.6274509803921569, 0.47843137254901963, 1.0),
-            "lightseagreen" => Color::new(0.12549019607843137, 0.6980392156862745, 0.6666666666666666, 1.0),
-            "lightskyblue" => Color::new(0.5294117647058824, 0.807843137254902, 0.9803921568627451, 1.0),
-            "lightslategray"|"lightslategrey" => Color::new(0.4666666666666667, 0.5333333333333333, 0.6, 1.0),
-            "lightsteelblue" => Color::new(0.6901960784313725, 0.7686274509803922, 0.8705882352941177, 1.0),
-            "lightyellow" => Color::new(1.0, 1.0, 0.8784313725490196, 1.0),
-            "limegreen" => Color::new(0.19607843137254902, 0.803921568627451, 0.19607843137254902, 1.0),
-            "linen" => Color::new(0.9803921568627451, 0.9411764705882353, 0.9019607843137255, 1.0),
-            "mediumaquamarine" => Color::new(0.4, 0.803921568627451, 0.6666666666666666, 1.0),
-            "mediumblue" => Color::new(0.0, 0.0, 0.803921568627451, 1.0),
-            "mediumorchid" => Color::new(0.7294117647058823, 0.3333333333333333, 0.8274509803921568, 1.0),
-            "mediumpurple" => Color::new(0.5764705882352941, 0.4392156862745098, 0.8588235294117647, 1.0),
-            "mediumseagreen" => Color::new(0.23529411764705882, 0.7019607843137254, 0.44313725490196076, 1.0),
-            "mediumslateblue" => Color::new(0.4823529411764706, 0.40784313725490196, 0.9333333333333333, 1.0),
-            "mediumspringgreen" => Color::new(0.0, 0.9803921568627451, 0.6039215686274509, 1.0),
-            "mediumturquoise" => Color::new(0.2823529411764706, 0.8196078431372549, 0.8, 1.0),
-            "mediumvioletred" => Color::new(0.7803921568627451, 0.08235294117647059, 0.5215686274509804, 1.0),
-            "midnightblue" => Color::new(0.09803921568627451, 0.09803921568627451, 0.4392156862745098, 1.0),
-            "mintcream" => Color::new(0.9607843137254902, 1.0, 0.9803921568627451, 1.0),
-            "mistyrose" => Color::new(1.0, 0.8941176470588236, 0.8823529411764706, 1.0),
-            "moccasin" => Color::new(1.0, 0.8941176470588236, 0.7098039215686275, 1.0),
-            "navajowhite" => Color::new(1.0, 0.8705882352941177, 0.6784313725490196, 1.0),
-            "oldlace" => Color::new(0.9921568627450981, 0.9607843137254902, 0.9019607843137255, 1.0),
-            "olivedrab" => Color::new(0.4196078431372549, 0.5568627450980392, 0.13725490196078433, 1.0),
-            "orangered" => Color::new(1.0, 0.27058823529411763, 0.0, 1.0),
-            "orchid" => Color::new(0.8549019607843137, 0.4392156862745098, 0.8392156862745098, 1.0),
-            "palegoldenrod" => Color::new(0.9333333333333333, 0.9098039215686274, 0.6666666666666666, 1.0),
-            "palegreen" => Color::new(0.596078431372549, 0.984313725490196, 0.596078431372549, 1.0),
-            "paleturquoise" => Color::new(0.6862745098039216, 0.9333333333333333, 0.9333333333333333, 1.0),
-            "palevioletred" => Color::new(0.8588235294117647, 0.4392156862745098, 0.5764705882352941, 1.0),
-            "papayawhip" => Color::new(1.0, 0.9372549019607843, 0.8352941176470589, 1.0),
-            "peachpuff" => Color::new(1.0, 0.8549019607843137, 0.7254901960784313, 1.0),
-            "peru" => Color::new(0.803921568627451, 0.5215686274509804, 0.24705882352941178, 1.0),
-            "pink" => Color::new(1.0, 0.7529411764705882, 0.796078431372549, 1.0),
-            "plum" => Color::new(0.8666666666666667, 0.6274509803921569, 0.8666666666666667, 1.0),
-            "powderblue" => Color::new(0.6901960784313725, 0.8784313725490196, 0.9019607843137255, 1.0),
-            "rosybrown" => Color::new(0.7372549019607844, 0.5607843137254902, 0.5607843137254902, 1.0),
-            "royalblue" => Color::new(0.2549019607843137, 0.4117647058823529, 0.8823529411764706, 1.0),
-            "saddlebrown" => Color::new(0.5450980392156862, 0.27058823529411763, 0.07450980392156863, 1.0),
-            "salmon" => Color::new(0.9803921568627451, 0.5019607843137255, 0.4470588235294118, 1.0),
-            "sandybrown" => Color::new(0.9568627450980393, 0.6431372549019608, 0.3764705882352941, 1.0),
-            "seagreen" => Color::new(0.1803921568627451, 0.5450980392156862, 0.3411764705882353, 1.0),
-            "seashell" => Color::new(1.0, 0.9607843137254902, 0.9333333333333333, 1.0),
-            "sienna" => Color::new(0.6274509803921569, 0.3215686274509804, 0.17647058823529413, 1.0),
-            "skyblue" => Color::new(0.5294117647058824, 0.807843137254902, 0.9215686274509803, 1.0),
-            "slateblue" => Color::new(0.41568627450980394, 0.35294117647058826, 0.803921568627451, 1.0),
-            "slategray"|"slategrey" => Color::new(0.4392156862745098, 0.5019607843137255, 0.5647058823529412, 1.0),
-            "snow" => Color::new(1.0, 0.9803921568627451, 0.9803921568627451, 1.0),
-            "springgreen" => Color::new(0.0, 1.0, 0.4980392156862745, 1.0),
-            "steelblue" => Color::new(0.27450980392156865, 0.5098039215686274, 0.7058823529411765, 1.0),
-            "tan" => Color::new(0.8235294117647058, 0.7058823529411765, 0.5490196078431373, 1.0),
-            "thistle" => Color::new(0.8470588235294118, 0.7490196078431373, 0.8470588235294118, 1.0),
-            "tomato" => Color::new(1.0, 0.38823529411764707, 0.2784313725490196, 1.0),
-            "turquoise" => Color::new(0.25098039215686274, 0.8784313725490196, 0.8156862745098039, 1.0),
-            "violet" => Color::new(0.9333333333333333, 0.5098039215686274, 0.9333333333333333, 1.0),
-            "wheat" => Color::new(0.9607843137254902, 0.8705882352941177, 0.7019607843137254, 1.0),
-            "whitesmoke" => Color::new(0.9607843137254902, 0.9607843137254902, 0.9607843137254902, 1.0),
-            "yellowgreen" => Color::new(0.6039215686274509, 0.803921568627451, 0.19607843137254902, 1.0),
-            "rebeccapurple" => Color::new(0.4, 0.2, 0.6, 1.0),
-            _ => Color::new(0.0, 0.0, 0.0, 1.0),
-            // TODO: throw away invalid declaration instead of using some default
-        };
+        (size_part, String::from("normal"))
+    };
+
+    if !is_numeric_start(&size) && !is_font_size_keyword(&size) {
+        return None;
     }
+
+    let family = rest.join(" ");
+    if family.is_empty() {
+        return None;
+    }
+
+    Some(vec![
+        (String::from("font-style"), style.unwrap_or_else(|| String::from("normal"))),
+        (String::from("font-variant"), variant.unwrap_or_else(|| String::from("normal"))),
+        (String::from("font-weight"), weight.unwrap_or_else(|| String::from("normal"))),
+        (String::from("font-size"), size),
+        (String::from("line-height"), line_height),
+        (String::from("font-family"), family),
+    ])
+}
+
+/// Splits a value into its whitespace separated components.
+/// Whitespace inside of parentheses or strings, ex. "rgb(0, 0, 0)", doesn't split a component.
+fn split_components(value: &str) -> Vec<String> {
+    split_top_level(value, char::is_whitespace).into_iter()
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Splits a value on separators that aren't inside of parentheses or strings.
+/// The parts are trimmed of whitespace.
+fn split_top_level<F>(value: &str, is_separator: F) -> Vec<String> where F : Fn(char) -> bool {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+
+    for c in value.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None => match c {
+                '"'|'\'' => quote = Some(c),
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                c if depth == 0 && is_separator(c) => {
+                    parts.push(current.trim().to_string());
+                    current = String::new();
+                    continue;
+                },
+                _ => {},
+            },
+        }
+        current.push(c);
+    }
+
+    parts.push(current.trim().to_string());
+    parts
+}
+
+/// Joins components with a space, or returns the default if there are none.
+fn join_or(parts: &[String], default: &str) -> String {
+    if parts.is_empty() {
+        String::from(default)
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Returns true if the value starts like a number, ex. "1px", ".5em" or "-2px".
+fn is_numeric_start(value: &str) -> bool {
+    let unsigned = value.strip_prefix(|c| c == '+' || c == '-').unwrap_or(value);
+    let unsigned = unsigned.strip_prefix('.').unwrap_or(unsigned);
+
+    unsigned.chars().next().is_some_and(|c| c.is_ascii_digit())
+}
+
+/// Returns true if the value is a css identifier, which may start with dashes.
+fn is_identifier(value: &str) -> bool {
+    let rest = value.strip_prefix("--").or_else(|| value.strip_prefix('-')).unwrap_or(value);
+    let mut chars = rest.chars();
+
+    chars.next().is_some_and(is_valid_start_ident) && chars.all(is_valid_ident)
+}
+
+/// Returns true if the value is a keyword for a border width.
+fn is_border_width_keyword(value: &str) -> bool {
+    matches!(value, "thin" | "medium" | "thick")
+}
+
+/// Returns true if the value is a keyword for a border style.
+fn is_border_style_keyword(value: &str) -> bool {
+    matches!(value, "none" | "hidden" | "dotted" | "dashed" | "solid" | "double" |
+        "groove" | "ridge" | "inset" | "outset")
+}
+
+/// Returns true if the value is a keyword for background repetition.
+fn is_background_repeat_keyword(value: &str) -> bool {
+    matches!(value, "repeat" | "repeat-x" | "repeat-y" | "no-repeat" | "space" | "round")
+}
+
+/// Returns true if the value is a keyword for a background position.
+fn is_position_keyword(value: &str) -> bool {
+    matches!(value, "left" | "right" | "top" | "bottom" | "center")
+}
+
+/// Returns true if the value is a keyword or number for a font weight.
+fn is_font_weight_keyword(value: &str) -> bool {
+    matches!(value, "bold" | "bolder" | "lighter" |
+        "100" | "200" | "300" | "400" | "500" | "600" | "700" | "800" | "900")
+}
+
+/// Returns true if the value is an absolute or relative font size keyword.
+fn is_font_size_keyword(value: &str) -> bool {
+    matches!(value, "xx-small" | "x-small" | "small" | "medium" | "large" | "x-large" |
+        "xx-large" | "larger" | "smaller")
+}
+
+/// Parses a css number from the start of a value.
+/// Returns the number and the rest of the value, ex. its unit.
+///
+/// A number is an optional sign, digits with an optional fraction or just a fraction,
+/// and an optional exponent, ex. "-10", "1.5", ".5", "+2e3" or "1E-2".
+fn parse_number(value: &str) -> Option<(f32, &str)> {
+    let bytes = value.as_bytes();
+    let digits_from = |start: usize| {
+        bytes[start..].iter().take_while(|b| b.is_ascii_digit()).count()
+    };
+
+    let mut end = 0;
+    if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+        end += 1;
+    }
+
+    let integer = digits_from(end);
+    end += integer;
+
+    let mut fraction = 0;
+    if end < bytes.len() && bytes[end] == b'.' {
+        fraction = digits_from(end + 1);
+        if fraction > 0 {
+            end += 1 + fraction;
+        }
+    }
+
+    if integer == 0 && fraction == 0 {
+        return None;
+    }
+
+    // only an e followed by digits is an exponent, ex. "1em" has the unit "em"
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut exp = end + 1;
+        if exp < bytes.len() && (bytes[exp] == b'+' || bytes[exp] == b'-') {
+            exp += 1;
+        }
+
+        let exp_digits = digits_from(exp);
+        if exp_digits > 0 {
+            end = exp + exp_digits;
+        }
+    }
+
+    let number: f32 = value[..end].parse().ok()?;
+    if number.is_finite() {
+        Some((number, &value[end..]))
+    } else {
+        None
+    }
+}
+
+/// Parses a value that is only a css number.
+fn parse_plain_number(value: &str) -> Option<f32> {
+    match parse_number(value) {
+        Some((n, "")) => Some(n),
+        _ => None,
+    }
+}
+
+/// Parses a color, or returns None if the color is invalid.
+/// can be a keyword ex. "red", "blue" or "transparent"
+/// can be a 3, 4, 6 or 8 digit hex code ex. #fff, #000000, #ffffff80
+/// can be rgb() or rgba() functions (can have % or numbers)
+/// can be hsl() or hsla() functions
+/// functions can use the legacy comma syntax or the space and slash syntax
+fn parse_color(color: &str) -> Option<Color> {
+    if let Some(hex) = color.strip_prefix('#') {
+        parse_hex_color(hex)
+    } else if let Some(args) = function_args(color, &["rgb", "rgba"]) {
+        parse_rgb(args)
+    } else if let Some(args) = function_args(color, &["hsl", "hsla"]) {
+        parse_hsl(args)
+    } else {
+        named_color(color)
+    }
+}
+
+/// Returns the arguments of a function call if it's one of the given functions.
+/// ex. "rgb(1, 2, 3)" returns "1, 2, 3" for the "rgb" function.
+fn function_args<'a>(value: &'a str, names: &[&str]) -> Option<&'a str> {
+    let open = value.find('(')?;
+    let args = value[open + 1..].strip_suffix(')')?;
+
+    if names.contains(&value[..open].trim_end()) {
+        Some(args)
+    } else {
+        None
+    }
+}
+
+/// Parses the digits of a 3, 4, 6 or 8 digit hex color.
+/// The 4 and 8 digit forms end with the alpha channel.
+fn parse_hex_color(hex: &str) -> Option<Color> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |i: usize, len: usize| {
+        u8::from_str_radix(&hex[i * len..(i + 1) * len], 16).ok()
+            .map(|n| n as f32 / if len == 1 { 15.0 } else { 255.0 })
+    };
+
+    match hex.len() {
+        3 => Some(Color::new(channel(0, 1)?, channel(1, 1)?, channel(2, 1)?, 1.0)),
+        4 => Some(Color::new(channel(0, 1)?, channel(1, 1)?, channel(2, 1)?, channel(3, 1)?)),
+        6 => Some(Color::new(channel(0, 2)?, channel(1, 2)?, channel(2, 2)?, 1.0)),
+        8 => Some(Color::new(channel(0, 2)?, channel(1, 2)?, channel(2, 2)?, channel(3, 2)?)),
+        _ => None,
+    }
+}
+
+/// Splits the arguments of a color function into its channels and optional alpha.
+///
+/// Legacy syntax separates every argument with commas: "255, 0, 0, 0.5"
+/// Modern syntax separates channels with spaces and alpha with a slash: "255 0 0 / 50%"
+fn split_color_args(args: &str) -> Option<(Vec<&str>, Option<&str>)> {
+    let (mut channels, alpha): (Vec<&str>, Option<&str>) = if args.contains(',') {
+        if args.contains('/') {
+            return None;
+        }
+        let mut parts: Vec<&str> = args.split(',').map(str::trim).collect();
+        let alpha = if parts.len() == 4 { parts.pop() } else { None };
+        (parts, alpha)
+    } else {
+        let mut halves = args.splitn(2, '/');
+        let channels = halves.next()?.split_whitespace().collect();
+        (channels, halves.next().map(str::trim))
+    };
+
+    channels.retain(|c| !c.is_empty());
+    if channels.len() != 3 || alpha.is_some_and(str::is_empty) {
+        return None;
+    }
+
+    Some((channels, alpha))
+}
+
+/// Parses the arguments of an rgb() or rgba() function.
+/// Channels are numbers from 0 to 255 or percentages, the legacy syntax can't mix them.
+fn parse_rgb(args: &str) -> Option<Color> {
+    let (channels, alpha) = split_color_args(args)?;
+
+    if args.contains(',') {
+        let percentages = channels.iter().filter(|c| c.ends_with('%')).count();
+        if percentages != 0 && percentages != channels.len() {
+            return None;
+        }
+    }
+
+    let channel = |c: &str| match c.strip_suffix('%') {
+        Some(pct) => parse_color_number(pct).map(|n| clamp_unit(n / 100.0)),
+        None => parse_color_number(c).map(|n| clamp_unit(n / 255.0)),
+    };
+
+    Some(Color::new(
+        channel(channels[0])?,
+        channel(channels[1])?,
+        channel(channels[2])?,
+        parse_alpha(alpha)?,
+    ))
+}
+
+/// Parses the arguments of an hsl() or hsla() function.
+/// Hue is an angle, saturation and lightness are percentages.
+fn parse_hsl(args: &str) -> Option<Color> {
+    let (channels, alpha) = split_color_args(args)?;
+    let legacy = args.contains(',');
+
+    let hue = parse_hue(channels[0])?;
+    let percent = |c: &str| match c.strip_suffix('%') {
+        Some(pct) => parse_color_number(pct).map(|n| clamp_unit(n / 100.0)),
+        // the modern syntax also accepts plain numbers
+        None if !legacy => parse_color_number(c).map(|n| clamp_unit(n / 100.0)),
+        None => None,
+    };
+    let saturation = percent(channels[1])?;
+    let lightness = percent(channels[2])?;
+
+    let (r, g, b) = hsl_to_rgb(hue, saturation, lightness);
+    Some(Color::new(r, g, b, parse_alpha(alpha)?))
+}
+
+/// Parses a hue in degrees, or with a deg, rad, grad or turn unit.
+/// Returns the hue normalized into [0, 360).
+fn parse_hue(hue: &str) -> Option<f32> {
+    let degrees = if let Some(n) = hue.strip_suffix("deg") {
+        parse_color_number(n)?
+    } else if let Some(n) = hue.strip_suffix("grad") {
+        parse_color_number(n)? * 0.9
+    } else if let Some(n) = hue.strip_suffix("rad") {
+        parse_color_number(n)?.to_degrees()
+    } else if let Some(n) = hue.strip_suffix("turn") {
+        parse_color_number(n)? * 360.0
+    } else {
+        parse_color_number(hue)?
+    };
+
+    Some(degrees.rem_euclid(360.0))
+}
+
+/// Parses an optional alpha channel, a number from 0 to 1 or a percentage.
+/// A missing alpha channel is opaque.
+fn parse_alpha(alpha: Option<&str>) -> Option<f32> {
+    match alpha {
+        None => Some(1.0),
+        Some(a) => match a.strip_suffix('%') {
+            Some(pct) => parse_color_number(pct).map(|n| clamp_unit(n / 100.0)),
+            None => parse_color_number(a).map(clamp_unit),
+        },
+    }
+}
+
+/// Parses a number in a color function, "none" is treated as 0.
+fn parse_color_number(value: &str) -> Option<f32> {
+    match value {
+        "none" => Some(0.0),
+        _ => parse_plain_number(value),
+    }
+}
+
+/// Clamps a color channel into the range 0 to 1.
+fn clamp_unit(n: f32) -> f32 {
+    n.clamp(0.0, 1.0)
+}
+
+/// Converts hue in degrees, and saturation and lightness from 0 to 1 into rgb channels.
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> (f32, f32, f32) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let h = hue / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let m = lightness - chroma / 2.0;
+    (r + m, g + m, b + m)
+}
+
+/// Returns the color for a color keyword.
+pub fn named_color(color: &str) -> Option<Color> {
+    let c = match color {
+        "transparent" => Color::new(0.0, 0.0, 0.0, 0.0),
+        "black" => rgb(0, 0, 0),
+        "silver" => rgb(192, 192, 192),
+        "gray"|"grey" => rgb(128, 128, 128),
+        "white" => rgb(255, 255, 255),
+        "maroon" => rgb(128, 0, 0),
+        "red" => rgb(255, 0, 0),
+        "purple" => rgb(128, 0, 128),
+        "fuchsia"|"magenta" => rgb(255, 0, 255),
+        "green" => rgb(0, 128, 0),
+        "lime" => rgb(0, 255, 0),
+        "olive" => rgb(128, 128, 0),
+        "yellow" => rgb(255, 255, 0),
+        "navy" => rgb(0, 0, 128),
+        "blue" => rgb(0, 0, 255),
+        "teal" => rgb(0, 128, 128),
+        "aqua"|"cyan" => rgb(0, 255, 255),
+        "orange" => rgb(255, 165, 0),
+        "aliceblue" => rgb(240, 248, 255),
+        "antiquewhite" => rgb(250, 235, 215),
+        "aquamarine" => rgb(127, 255, 212),
+        "azure" => rgb(240, 255, 255),
+        "beige" => rgb(245, 245, 220),
+        "bisque" => rgb(255, 228, 196),
+        "blanchedalmond" => rgb(255, 235, 205),
+        "blueviolet" => rgb(138, 43, 226),
+        "brown" => rgb(165, 42, 42),
+        "burlywood" => rgb(222, 184, 135),
+        "cadetblue" => rgb(95, 158, 160),
+        "chartreuse" => rgb(127, 255, 0),
+        "chocolate" => rgb(210, 105, 30),
+        "coral" => rgb(255, 127, 80),
+        "cornflowerblue" => rgb(100, 149, 237),
+        "cornsilk" => rgb(255, 248, 220),
+        "crimson" => rgb(220, 20, 60),
+        "darkblue" => rgb(0, 0, 139),
+        "darkcyan" => rgb(0, 139, 139),
+        "darkgoldenrod" => rgb(184, 134, 11),
+        "darkgray"|"darkgrey" => rgb(169, 169, 169),
+        "darkgreen" => rgb(0, 100, 0),
+        "darkkhaki" => rgb(189, 183, 107),
+        "darkmagenta" => rgb(139, 0, 139),
+        "darkolivegreen" => rgb(85, 107, 47),
+        "darkorange" => rgb(255, 140, 0),
+        "darkorchid" => rgb(153, 50, 204),
+        "darkred" => rgb(139, 0, 0),
+        "darksalmon" => rgb(233, 150, 122),
+        "darkseagreen" => rgb(143, 188, 143),
+        "darkslateblue" => rgb(72, 61, 139),
+        "darkslategray"|"darkslategrey" => rgb(47, 79, 79),
+        "darkturquoise" => rgb(0, 206, 209),
+        "darkviolet" => rgb(148, 0, 211),
+        "deeppink" => rgb(255, 20, 147),
+        "deepskyblue" => rgb(0, 191, 255),
+        "dimgray"|"dimgrey" => rgb(105, 105, 105),
+        "dodgerblue" => rgb(30, 144, 255),
+        "firebrick" => rgb(178, 34, 34),
+        "floralwhite" => rgb(255, 250, 240),
+        "forestgreen" => rgb(34, 139, 34),
+        "gainsboro" => rgb(220, 220, 220),
+        "ghostwhite" => rgb(248, 248, 255),
+        "gold" => rgb(255, 215, 0),
+        "goldenrod" => rgb(218, 165, 32),
+        "greenyellow" => rgb(173, 255, 47),
+        "honeydew" => rgb(240, 255, 240),
+        "hotpink" => rgb(255, 105, 180),
+        "indianred" => rgb(205, 92, 92),
+        "indigo" => rgb(75, 0, 130),
+        "ivory" => rgb(255, 255, 240),
+        "khaki" => rgb(240, 230, 140),
+        "lavender" => rgb(230, 230, 250),
+        "lavenderblush" => rgb(255, 240, 245),
+        "lawngreen" => rgb(124, 252, 0),
+        "lemonchiffon" => rgb(255, 250, 205),
+        "lightblue" => rgb(173, 216, 230),
+        "lightcoral" => rgb(240, 128, 128),
+        "lightcyan" => rgb(224, 255, 255),
+        "lightgoldenrodyellow" => rgb(250, 250, 210),
+        "lightgray"|"lightgrey" => rgb(211, 211, 211),
+        "lightgreen" => rgb(144, 238, 144),
+        "lightpink" => rgb(255, 182, 193),
+        "lightsalmon" => rgb(255, 160, 122),
+        "lightseagreen" => rgb(32, 178, 170),
+        "lightskyblue" => rgb(135, 206, 250),
+        "lightslategray"|"lightslategrey" => rgb(119, 136, 153),
+        "lightsteelblue" => rgb(176, 196, 222),
+        "lightyellow" => rgb(255, 255, 224),
+        "limegreen" => rgb(50, 205, 50),
+        "linen" => rgb(250, 240, 230),
+        "mediumaquamarine" => rgb(102, 205, 170),
+        "mediumblue" => rgb(0, 0, 205),
+        "mediumorchid" => rgb(186, 85, 211),
+        "mediumpurple" => rgb(147, 112, 219),
+        "mediumseagreen" => rgb(60, 179, 113),
+        "mediumslateblue" => rgb(123, 104, 238),
+        "mediumspringgreen" => rgb(0, 250, 154),
+        "mediumturquoise" => rgb(72, 209, 204),
+        "mediumvioletred" => rgb(199, 21, 133),
+        "midnightblue" => rgb(25, 25, 112),
+        "mintcream" => rgb(245, 255, 250),
+        "mistyrose" => rgb(255, 228, 225),
+        "moccasin" => rgb(255, 228, 181),
+        "navajowhite" => rgb(255, 222, 173),
+        "oldlace" => rgb(253, 245, 230),
+        "olivedrab" => rgb(107, 142, 35),
+        "orangered" => rgb(255, 69, 0),
+        "orchid" => rgb(218, 112, 214),
+        "palegoldenrod" => rgb(238, 232, 170),
+        "palegreen" => rgb(152, 251, 152),
+        "paleturquoise" => rgb(175, 238, 238),
+        "palevioletred" => rgb(219, 112, 147),
+        "papayawhip" => rgb(255, 239, 213),
+        "peachpuff" => rgb(255, 218, 185),
+        "peru" => rgb(205, 133, 63),
+        "pink" => rgb(255, 192, 203),
+        "plum" => rgb(221, 160, 221),
+        "powderblue" => rgb(176, 224, 230),
+        "rosybrown" => rgb(188, 143, 143),
+        "royalblue" => rgb(65, 105, 225),
+        "saddlebrown" => rgb(139, 69, 19),
+        "salmon" => rgb(250, 128, 114),
+        "sandybrown" => rgb(244, 164, 96),
+        "seagreen" => rgb(46, 139, 87),
+        "seashell" => rgb(255, 245, 238),
+        "sienna" => rgb(160, 82, 45),
+        "skyblue" => rgb(135, 206, 235),
+        "slateblue" => rgb(106, 90, 205),
+        "slategray"|"slategrey" => rgb(112, 128, 144),
+        "snow" => rgb(255, 250, 250),
+        "springgreen" => rgb(0, 255, 127),
+        "steelblue" => rgb(70, 130, 180),
+        "tan" => rgb(210, 180, 140),
+        "thistle" => rgb(216, 191, 216),
+        "tomato" => rgb(255, 99, 71),
+        "turquoise" => rgb(64, 224, 208),
+        "violet" => rgb(238, 130, 238),
+        "wheat" => rgb(245, 222, 179),
+        "whitesmoke" => rgb(245, 245, 245),
+        "yellowgreen" => rgb(154, 205, 50),
+        "rebeccapurple" => rgb(102, 51, 153),
+        _ => return None,
+    };
+
+    Some(c)
+}
+
+/// Returns an opaque color from 8-bit channels.
+fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0)
 }
 
 /// Returns true if the char is a valid for a css identifier.
@@ -439,15 +1548,13 @@ fn is_non_ascii(c: char) -> bool {
 //TODO
 //  -deal with comments and escaping characters
 //  -complex selectors
-//  -cascade
-//  -specificity
 
 /// Tests ----------------------------------------------------------------------
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use css::{Color, Declaration, Rule, Selector, SimpleSelector, Stylesheet, Value};
+    use css::{Color, Declaration, Keyword, Rule, Selector, Separator, SimpleSelector, Stylesheet, Unit, Value};
 
     /// Test a parser is constructed correctly.
     #[test]
@@ -497,10 +1604,283 @@ mod tests {
              background-color: aqua
            }");
         let decl_col = Declaration::new(String::from("color"), Value::Color(Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0}));
-        let decl_bw = Declaration::new(String::from("border-width"), Value::Other(String::from("1px")));
         let decl_bg_col = Declaration::new(String::from("background-color"), Value::Color(Color { r: 0.0, g: 1.0, b: 1.0, a: 1.0 }));
 
-        let expected = vec![decl_col, decl_bw, decl_bg_col];
+        let mut expected = vec![decl_col];
+        expected.append(&mut edges("border-", "-width", Value::Length(1.0, Unit::Px)));
+        expected.push(decl_bg_col);
+        assert_eq!(expected, parser.parse_declarations());
+    }
+
+    /// Build the longhand declarations of a shorthand applied to all four edges.
+    fn edges(prefix: &str, suffix: &str, value: Value) -> Vec<Declaration> {
+        ["top", "right", "bottom", "left"].iter()
+            .map(|side| Declaration::new(format!("{}{}{}", prefix, side, suffix), value.clone()))
+            .collect()
+    }
+
+    /// Build a declaration with a keyword value.
+    fn keyword(property: &str, value: Keyword) -> Declaration {
+        Declaration::new(String::from(property), Value::Keyword(value))
+    }
+
+    /// Test the margin shorthand with 1 to 4 values
+    #[test]
+    fn shorthand_margin() {
+        let px = |n| Value::Length(n, Unit::Px);
+        let side = |s: &str, n| Declaration::new(format!("margin-{}", s), px(n));
+
+        let mut parser = CssParser::new("margin: 8px }");
+        assert_eq!(edges("margin-", "", px(8.0)), parser.parse_declarations());
+
+        let mut parser = CssParser::new("margin: 1px 2px }");
+        let expected = vec![side("top", 1.0), side("right", 2.0), side("bottom", 1.0), side("left", 2.0)];
+        assert_eq!(expected, parser.parse_declarations());
+
+        let mut parser = CssParser::new("margin: 1px 2px 3px }");
+        let expected = vec![side("top", 1.0), side("right", 2.0), side("bottom", 3.0), side("left", 2.0)];
+        assert_eq!(expected, parser.parse_declarations());
+
+        let mut parser = CssParser::new("margin: 1px 2px 3px 4px }");
+        let expected = vec![side("top", 1.0), side("right", 2.0), side("bottom", 3.0), side("left", 4.0)];
+        assert_eq!(expected, parser.parse_declarations());
+    }
+
+    /// Test a box edge shorthand with too many values is dropped
+    #[test]
+    fn shorthand_edges_invalid() {
+        let mut parser = CssParser::new("padding: 1px 2px 3px 4px 5px; width: 1px }");
+        let expected = vec![Declaration::new(String::from("width"), Value::Length(1.0, Unit::Px))];
+        assert_eq!(expected, parser.parse_declarations());
+    }
+
+    /// Test important applies to every longhand of a shorthand
+    #[test]
+    fn shorthand_important() {
+        let mut parser = CssParser::new("padding: 4px 8px !important }");
+        let declarations = parser.parse_declarations();
+
+        assert_eq!(4, declarations.len());
+        assert!(declarations.iter().all(|d| d.important));
+    }
+
+    /// Test the border shorthand resets missing components
+    #[test]
+    fn shorthand_border() {
+        let mut parser = CssParser::new("border: red 2px }");
+
+        let mut expected = edges("border-", "-width", Value::Length(2.0, Unit::Px));
+        expected.append(&mut edges("border-", "-style", Value::Keyword(Keyword::None)));
+        expected.append(&mut edges("border-", "-color", Value::Color(Color::new(1.0, 0.0, 0.0, 1.0))));
+        assert_eq!(expected, parser.parse_declarations());
+
+        let mut parser = CssParser::new("border: 1px 2px solid }");
+        assert_eq!(Vec::<Declaration>::new(), parser.parse_declarations());
+    }
+
+    /// Test the background shorthand
+    #[test]
+    fn shorthand_background() {
+        let mut parser = CssParser::new("background: #fff url(bg.png) no-repeat center }");
+        let expected = vec![
+            Declaration::new(String::from("background-color"), Value::Color(Color::new(1.0, 1.0, 1.0, 1.0))),
+            Declaration::new(String::from("background-image"), Value::Url(String::from("bg.png"))),
+            keyword("background-repeat", Keyword::NoRepeat),
+            keyword("background-attachment", Keyword::Scroll),
+            keyword("background-position", Keyword::Center),
+        ];
+        assert_eq!(expected, parser.parse_declarations());
+    }
+
+    /// Test the font shorthand
+    #[test]
+    fn shorthand_font() {
+        let mut parser = CssParser::new("font: 12px/1.5 sans-serif }");
+        let expected = vec![
+            keyword("font-style", Keyword::Normal),
+            keyword("font-variant", Keyword::Normal),
+            keyword("font-weight", Keyword::Normal),
+            Declaration::new(String::from("font-size"), Value::Length(12.0, Unit::Px)),
+            Declaration::new(String::from("line-height"), Value::Number(1.5)),
+            keyword("font-family", Keyword::SansSerif),
+        ];
+        assert_eq!(expected, parser.parse_declarations());
+
+        let mut parser = CssParser::new("font: italic bold large / 20px georgia, serif }");
+        let expected = vec![
+            keyword("font-style", Keyword::Italic),
+            keyword("font-variant", Keyword::Normal),
+            keyword("font-weight", Keyword::Bold),
+            keyword("font-size", Keyword::Large),
+            Declaration::new(String::from("line-height"), Value::Length(20.0, Unit::Px)),
+            Declaration::new(String::from("font-family"), Value::List(vec![
+                Value::Ident(String::from("georgia")),
+                Value::Keyword(Keyword::Serif),
+            ], Separator::Comma)),
+        ];
+        assert_eq!(expected, parser.parse_declarations());
+
+        let mut parser = CssParser::new("font: bold 12px }");
+        assert_eq!(Vec::<Declaration>::new(), parser.parse_declarations());
+    }
+
+    /// Parse a color value.
+    fn color(value: &str) -> Option<Color> {
+        parse_color(value)
+    }
+
+    /// Test hex colors, including alpha
+    #[test]
+    fn color_hex() {
+        assert_eq!(Some(Color::new(1.0, 0.0, 0.0, 1.0)), color("#f00"));
+        assert_eq!(Some(Color::new(1.0, 0.0, 0.0, 0.0)), color("#f000"));
+        assert_eq!(Some(Color::new(0.0, 1.0, 0.0, 1.0)), color("#00ff00"));
+        assert_eq!(Some(Color::new(0.0, 0.0, 1.0, 1.0)), color("#0000ffff"));
+        assert_eq!(None, color("#ff"));
+        assert_eq!(None, color("#ggg"));
+    }
+
+    /// Test rgb functions with the legacy and modern syntaxes
+    #[test]
+    fn color_rgb() {
+        let red = Some(Color::new(1.0, 0.0, 0.0, 1.0));
+        let half_red = Some(Color::new(1.0, 0.0, 0.0, 0.5));
+
+        assert_eq!(red, color("rgb(255, 0, 0)"));
+        assert_eq!(red, color("rgb(100%, 0%, 0%)"));
+        assert_eq!(red, color("rgb(255 0 0)"));
+        assert_eq!(red, color("rgb(300 -5 0)"));
+        assert_eq!(half_red, color("rgba(255, 0, 0, 0.5)"));
+        assert_eq!(half_red, color("rgb(255 0 0 / 50%)"));
+        assert_eq!(half_red, color("rgba(100% 0 0 / .5)"));
+        assert_eq!(None, color("rgb(255, 0%, 0)"));
+        assert_eq!(None, color("rgb(255, 0)"));
+        assert_eq!(None, color("rgb(255 0 0 0)"));
+        assert_eq!(None, color("rgb(255, 0, 0 / 1)"));
+        assert_eq!(None, color("rgb(red, 0, 0)"));
+    }
+
+    /// Test hsl functions with the legacy and modern syntaxes
+    #[test]
+    fn color_hsl() {
+        let blue = Some(Color::new(0.0, 0.0, 1.0, 1.0));
+
+        assert_eq!(Some(Color::new(1.0, 0.0, 0.0, 1.0)), color("hsl(0, 100%, 50%)"));
+        assert_eq!(Some(Color::new(0.0, 1.0, 0.0, 0.25)), color("hsla(120, 100%, 50%, 0.25)"));
+        assert_eq!(blue, color("hsl(240deg 100% 50%)"));
+        assert_eq!(blue, color("hsl(-120 100 50)"));
+        assert_eq!(blue, color("hsl(0.6666667turn 100% 50% / 1)"));
+        assert_eq!(Some(Color::new(1.0, 1.0, 1.0, 1.0)), color("hsl(0, 0%, 100%)"));
+        assert_eq!(None, color("hsl(0, 100, 50)"));
+    }
+
+    /// Test color keywords
+    #[test]
+    fn color_keywords() {
+        assert_eq!(Some(Color::new(0.0, 0.0, 0.0, 0.0)), color("transparent"));
+        assert_eq!(Some(Color::new(0.0, 1.0, 1.0, 1.0)), color("cyan"));
+        assert_eq!(Some(Value::Keyword(Keyword::CurrentColor)), translate_value("color", "currentcolor"));
+        assert_eq!(None, color("notacolor"));
+    }
+
+    /// Test an invalid color drops the declaration
+    #[test]
+    fn declarations_invalid_color() {
+        let mut parser = CssParser::new("color: rgb(1, 2); background-color: bogus; border: 1px solid nope }");
+        assert_eq!(Vec::<Declaration>::new(), parser.parse_declarations());
+    }
+
+    /// Test numbers with signs, decimals and exponents
+    #[test]
+    fn number_grammar() {
+        assert_eq!(Some((10.0, "")), parse_number("10"));
+        assert_eq!(Some((-10.0, "px")), parse_number("-10px"));
+        assert_eq!(Some((1.5, "em")), parse_number("1.5em"));
+        assert_eq!(Some((0.5, "in")), parse_number("+.5in"));
+        assert_eq!(Some((100.0, "px")), parse_number("1e2px"));
+        assert_eq!(Some((0.1, "%")), parse_number("1E-1%"));
+        assert_eq!(Some((1.0, "em")), parse_number("1em"));
+        assert_eq!(Some((1.0, ".px")), parse_number("1.px"));
+        assert_eq!(None, parse_number("."));
+        assert_eq!(None, parse_number("-"));
+        assert_eq!(None, parse_number("px"));
+    }
+
+    /// Test lengths with valid and invalid units
+    #[test]
+    fn length_units() {
+        assert_eq!(Some(Value::Length(1.5, Unit::Em)), translate_value("width", "1.5em"));
+        assert_eq!(Some(Value::Length(-10.0, Unit::Px)), translate_value("margin-top", "-10px"));
+        assert_eq!(Some(Value::Percentage(50.0)), translate_value("width", "50%"));
+        assert_eq!(Some(Value::Length(2.0, Unit::Vmin)), translate_value("width", "2vmin"));
+        assert_eq!(Some(Value::Length(0.0, Unit::Px)), translate_value("width", "0"));
+        assert_eq!(None, translate_value("width", "10"));
+        assert_eq!(None, translate_value("width", "10foo"));
+        assert_eq!(None, translate_value("width", "1.5.5px"));
+    }
+
+    /// Test values are parsed into typed components
+    #[test]
+    fn value_components() {
+        assert_eq!(Some(Value::Keyword(Keyword::Auto)), parse_value("auto"));
+        assert_eq!(Some(Value::Ident(String::from("georgia"))), parse_value("georgia"));
+        assert_eq!(Some(Value::Number(1.5)), parse_value("1.5"));
+        assert_eq!(Some(Value::Percentage(-5.0)), parse_value("-5%"));
+        assert_eq!(Some(Value::Dimension(90.0, String::from("deg"))), parse_value("90deg"));
+        assert_eq!(Some(Value::Str(String::from("a, b"))), parse_value("'a, b'"));
+        assert_eq!(Some(Value::Url(String::from("a b.png"))), parse_value("url(\"a b.png\")"));
+        assert_eq!(Some(Value::Color(Color::new(1.0, 1.0, 1.0, 1.0))), parse_value("#fff"));
+        assert_eq!(None, parse_value("#nothex"));
+        assert_eq!(None, parse_value("@"));
+    }
+
+    /// Test functions and lists are parsed with their arguments and items
+    #[test]
+    fn value_functions_lists() {
+        let gradient = Value::Function(String::from("linear-gradient"), vec![
+            Value::List(vec![Value::Keyword(Keyword::Top), Value::Keyword(Keyword::Left)], Separator::Space),
+            Value::Color(Color::new(1.0, 0.0, 0.0, 1.0)),
+            Value::Ident(String::from("blue")),
+        ]);
+        assert_eq!(Some(gradient), parse_value("linear-gradient(top left, rgb(255, 0, 0), blue)"));
+
+        let list = Value::List(vec![
+            Value::List(vec![Value::Length(1.0, Unit::Px), Value::Length(2.0, Unit::Px)], Separator::Space),
+            Value::Number(3.0),
+        ], Separator::Comma);
+        assert_eq!(Some(list), parse_value("1px 2px, 3"));
+        assert_eq!(None, parse_value("1px,,2px"));
+    }
+
+    /// Test values are lowercased except for strings and urls
+    #[test]
+    fn declarations_case() {
+        let mut parser = CssParser::new("FONT-FAMILY: 'Open Sans', SERIF; background-image: URL(Img.PNG) }");
+        let families = Value::List(vec![Value::Str(String::from("Open Sans")), Value::Keyword(Keyword::Serif)], Separator::Comma);
+        let expected = vec![
+            Declaration::new(String::from("font-family"), families),
+            Declaration::new(String::from("background-image"), Value::Url(String::from("Img.PNG"))),
+        ];
+        assert_eq!(expected, parser.parse_declarations());
+    }
+
+    /// Test values a property's grammar rejects are dropped, unknown properties keep any value
+    #[test]
+    fn declarations_grammar() {
+        let mut parser = CssParser::new("display: blocky; position: 10px; grid-area: 1 / 2; transition: color 2s }");
+        let transition = Value::List(vec![Value::Ident(String::from("color")), Value::Dimension(2.0, String::from("s"))], Separator::Space);
+        let expected = vec![
+            Declaration::new(String::from("grid-area"), Value::Other(String::from("1 / 2"))),
+            Declaration::new(String::from("transition"), transition),
+        ];
+        assert_eq!(expected, parser.parse_declarations());
+    }
+
+    /// Test an invalid length drops the declaration
+    #[test]
+    fn declarations_invalid_length() {
+        let mut parser = CssParser::new("width: 10qq; margin: 1px 2; height: -1px; margin-top: -1.5e1px }");
+        let expected = vec![Declaration::new(String::from("margin-top"), Value::Length(-15.0, Unit::Px))];
         assert_eq!(expected, parser.parse_declarations());
     }
 
@@ -519,6 +1899,33 @@ mod tests {
         assert_eq!(expected, parser.parse_declarations());
     }
 
+    /// Test the !important flag is parsed off of declaration values
+    #[test]
+    fn declarations_important() {
+        let mut parser = CssParser::new(
+            "color: red !important;
+             width: 10px! IMPORTANT;
+             display: block
+           }");
+        let mut decl_col = Declaration::new(String::from("color"), Value::Color(Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0 }));
+        decl_col.important = true;
+        let mut decl_width = Declaration::new(String::from("width"), Value::Length(10.0, Unit::Px));
+        decl_width.important = true;
+        let decl_display = Declaration::new(String::from("display"), Value::Keyword(Keyword::Block));
+
+        let expected = vec![decl_col, decl_width, decl_display];
+        assert_eq!(expected, parser.parse_declarations());
+    }
+
+    /// Test "important" without a bang is left in the value
+    #[test]
+    fn declarations_not_important() {
+        let mut parser = CssParser::new("font-family: important }");
+        let decl = Declaration::new(String::from("font-family"), Value::Ident(String::from("important")));
+
+        assert_eq!(vec![decl], parser.parse_declarations());
+    }
+
     /// Test empty identifier
     #[test]
     fn identifier_empty() {
@@ -616,6 +2023,20 @@ mod tests {
         assert_eq!(Selector::default(), parser.parse_selector());
     }
 
+    /// Test descendant and child combinators and the universal selector.
+    #[test]
+    fn selector_combinators() {
+        let mut parser = CssParser::new("div  .a>p * > #b, ul li + li, div >, * {");
+        let tag = |t: &str| SimpleSelector::new(Some(String::from(t)), None, vec![]);
+        let expected = Selector::new(vec![tag("div"), SimpleSelector::new(None, None, vec![String::from("a")]), tag("p"),
+            SimpleSelector::default(), SimpleSelector::new(None, Some(String::from("b")), vec![])],
+            vec![' ', '>', ' ', '>']);
+        let universal = Selector::new(vec![SimpleSelector::default()], vec![]);
+
+        assert_eq!(vec![expected, universal], parser.parse_selectors());
+        assert_eq!("div .a > p * > #b", format!("{:?}", CssParser::new("div .a>p * > #b").parse_selector()));
+    }
+
     /// Test selectors parsing (comma seperated list)
     #[test]
     fn selectors_empty() {
@@ -704,10 +2125,188 @@ mod tests {
         let body1 = Selector::new(vec![body_ss1], vec![]);
         let body_ss2 = SimpleSelector::new(None, None, vec![String::from("class2"), String::from("class3"), String::from("class4")]);
         let body2 = Selector::new(vec![body_ss2], vec![]);
-        let body_decl1 = Declaration::new(String::from("border"), Value::Other(String::from("solid black 1px")));
-        let body_decl2 = Declaration::new(String::from("background-color"), Value::Color(Color { r: 0.0, g: 1.0, b: 1.0, a: 1.0 }));
-        let rule2 = Rule::new(vec![body1, body2], vec![body_decl1, body_decl2]);
+        let mut body_decls = edges("border-", "-width", Value::Length(1.0, Unit::Px));
+        body_decls.append(&mut edges("border-", "-style", Value::Keyword(Keyword::Solid)));
+        body_decls.append(&mut edges("border-", "-color", Value::Color(Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 })));
+        body_decls.push(Declaration::new(String::from("background-color"), Value::Color(Color { r: 0.0, g: 1.0, b: 1.0, a: 1.0 })));
+        let rule2 = Rule::new(vec![body1, body2], body_decls);
 
         assert_eq!(Stylesheet::new(vec![rule1, rule2]), parser.parse_stylesheet())
     }
+
+    /// Test rules record the line and column they start at, also inside @media blocks.
+    #[test]
+    fn stylesheet_locations() {
+        let mut parser = CssParser::new("p { color: red; }\n\n  @media print {\n\tdiv, a { color: red; } }  b { color: red; }");
+        let locations = parser.parse_stylesheet().rules.iter()
+            .map(|r| (r.location.line, r.location.column))
+            .collect::<Vec<(usize, usize)>>();
+
+        assert_eq!(vec![(1, 1), (4, 2), (4, 28)], locations);
+    }
+
+    /// Test media query lists parse types, qualifiers and features.
+    #[test]
+    fn media_query_lists() {
+        let list = parse_media_query_list("only Screen and (min-width: 600px) and (orientation: landscape), not print");
+        let screen = MediaQuery {
+            negated: false,
+            media_type: MediaType::Screen,
+            features: vec![
+                MediaFeature::Width(Range::Min, Value::Length(600.0, Unit::Px)),
+                MediaFeature::Orientation(Orientation::Landscape),
+            ],
+        };
+        let print = MediaQuery { negated: true, media_type: MediaType::Print, features: vec![] };
+        assert_eq!(MediaQueryList::new(vec![screen, print]), list);
+
+        let dark = MediaQuery {
+            negated: false,
+            media_type: MediaType::All,
+            features: vec![MediaFeature::PrefersColorScheme(ColorScheme::Dark)],
+        };
+        assert_eq!(MediaQueryList::new(vec![dark]), parse_media_query_list("(prefers-color-scheme: dark)"));
+        assert_eq!(MediaQueryList::default(), parse_media_query_list("  "));
+    }
+
+    /// Test malformed media queries become "not all" without affecting the rest of the list.
+    #[test]
+    fn media_query_invalid() {
+        let list = parse_media_query_list("screen and (min-width: red), (hover: hover), screen or print, all");
+        let all = MediaQuery { negated: false, media_type: MediaType::All, features: vec![] };
+
+        assert_eq!(MediaQueryList::new(vec![MediaQuery::not_all(), MediaQuery::not_all(),
+            MediaQuery::not_all(), all]), list);
+    }
+
+    /// Test rules in @media blocks carry their media conditions, including nested blocks.
+    #[test]
+    fn stylesheet_media() {
+        let mut parser = CssParser::new(
+            "p { width: 1px; }
+             @media screen {
+                 p { width: 2px; }
+                 @media (max-width: 40em) { p { width: 3px; } }
+             }
+             @font-face { font-family: x; src: url(x.woff) }
+             @charset \"utf-8\";
+             div { width: 4px; }");
+        let ss = parser.parse_stylesheet();
+        let screen = parse_media_query_list("screen");
+        let narrow = parse_media_query_list("(max-width: 40em)");
+
+        assert_eq!(4, ss.rules.len());
+        assert_eq!(Vec::<MediaQueryList>::new(), ss.rules[0].media);
+        assert_eq!(vec![screen.clone()], ss.rules[1].media);
+        assert_eq!(vec![screen, narrow], ss.rules[2].media);
+        assert_eq!(Vec::<MediaQueryList>::new(), ss.rules[3].media);
+        assert_eq!(vec![Declaration::new(String::from("width"), Value::Length(4.0, Unit::Px))],
+            ss.rules[3].declarations);
+    }
+
+    /// Test @import rules are collected with their media, and ignored after style rules.
+    #[test]
+    fn stylesheet_imports() {
+        let mut parser = CssParser::new(
+            "@charset \"utf-8\";
+             @import url(\"Base.css\");
+             @import 'print.css' print, (max-width: 600px);
+             @import nothing;
+             p { width: 1px; }
+             @import url(late.css);");
+        let ss = parser.parse_stylesheet();
+
+        assert_eq!(vec![
+            Import { url: String::from("Base.css"), media: MediaQueryList::default() },
+            Import { url: String::from("print.css"), media: parse_media_query_list("print, (max-width: 600px)") },
+        ], ss.imports);
+        assert_eq!(1, ss.rules.len());
+    }
+
+    /// Test custom properties keep their text and var() values wait for substitution.
+    #[test]
+    fn declarations_custom() {
+        let mut parser = CssParser::new("--brand:  #00F ; --gap: var(--x); width: var(--w, 1px); padding: 0 var(--p);}");
+        let pending = Value::Var(String::from("padding"), String::from("0 var(--p)"));
+        let mut expected = vec![
+            Declaration::new(String::from("--brand"), Value::Other(String::from("#00f"))),
+            Declaration::new(String::from("--gap"), Value::Var(String::from("--gap"), String::from("var(--x)"))),
+            Declaration::new(String::from("width"), Value::Var(String::from("width"), String::from("var(--w, 1px)"))),
+        ];
+        expected.append(&mut edges("padding-", "", pending));
+
+        assert_eq!(expected, parser.parse_declarations());
+    }
+
+    /// Test var() references are replaced with values or fallbacks.
+    #[test]
+    fn var_substitution() {
+        let mut lookup = |name: &str| match name {
+            "--a" => Some(String::from("10px")),
+            "--b" => Some(String::from("red")),
+            _ => None,
+        };
+
+        assert_eq!(Some(String::from("10px solid red")),
+            substitute_vars("var(--a) solid var( --b )", &mut lookup));
+        assert_eq!(Some(String::from("1px 2px")), substitute_vars("var(--x, 1px 2px)", &mut lookup));
+        assert_eq!(Some(String::from("10px")), substitute_vars("var(--x, var(--a))", &mut lookup));
+        assert_eq!(Some(String::from("rgb(0, 0, 0)")), substitute_vars("rgb(var(--x, 0, 0), 0)", &mut lookup));
+        assert_eq!(Some(String::from("")), substitute_vars("var(--x,)", &mut lookup));
+        assert_eq!(None, substitute_vars("var(--x)", &mut lookup));
+        assert_eq!(None, substitute_vars("var(a)", &mut lookup));
+        assert_eq!(Some(String::from("'var(--x)' somevar(1)")), substitute_vars("'var(--x)' somevar(1)", &mut lookup));
+    }
+
+    /// Test substituted values are parsed against the grammar of the declared property.
+    #[test]
+    fn var_parse_substituted() {
+        assert_eq!(Some(Value::Length(2.0, Unit::Px)), parse_substituted("margin-right", "margin", "1px 2px"));
+        assert_eq!(Some(Value::Length(1.0, Unit::Px)), parse_substituted("width", "width", "1px"));
+        assert_eq!(None, parse_substituted("width", "width", "red"));
+        assert_eq!(None, parse_substituted("margin-top", "margin", "1px 2px 3px 4px 5px"));
+    }
+
+    /// Test math functions parse into expression trees with the usual precedence.
+    #[test]
+    fn value_math() {
+        let calc = |v: &str| match parse_value(v) {
+            Some(v) => format!("{:?}", v),
+            None => String::from("invalid"),
+        };
+
+        assert_eq!("calc(100% - 2 * 16px)", calc("calc(100% - 2 * 16px)"));
+        assert_eq!("calc(1px + 2px * 3 / 4)", calc("calc(1px + 2px*3/4)"));
+        assert_eq!("calc((1px + 2px) * 3)", calc("calc((1px + 2px) * 3)"));
+        assert_eq!("calc(1px - (2px - 3px))", calc("calc(1px - calc(2px - 3px))"));
+        assert_eq!("clamp(200px, 50%, 600px)", calc("clamp(200px, 50%, 600px)"));
+        assert_eq!("min(10px, max(1%, 2em))", calc("min(10px, max(1%, 2em))"));
+        assert_eq!("invalid", calc("calc(1px -2px)"));
+        assert_eq!("invalid", calc("calc(1px + 2)"));
+        assert_eq!("invalid", calc("calc(1px * 2px)"));
+        assert_eq!("invalid", calc("clamp(1px, 2px)"));
+        assert_eq!("invalid", calc("calc(red)"));
+    }
+
+    /// Test math functions are validated against the property's type.
+    #[test]
+    fn declarations_math() {
+        let mut parser = CssParser::new("width: calc(100% - 10px); height: calc(2); line-height: calc(1 + 0.5); opacity: calc(10px);}");
+        let decls = parser.parse_declarations();
+
+        assert_eq!(vec!["width", "line-height"], decls.iter().map(|d| &d.property[..]).collect::<Vec<&str>>());
+    }
+
+    /// Test declaration lists outside of rules, like style attributes, end at the end of the css.
+    #[test]
+    fn declaration_list() {
+        let px = |n| Value::Length(n, Unit::Px);
+
+        assert_eq!(vec![Declaration::new(String::from("width"), px(1.0)),
+            Declaration::new(String::from("height"), px(2.0))],
+            CssParser::new("width: 1px; height: 2px").parse_declaration_list());
+        assert_eq!(vec![Declaration::new(String::from("width"), px(1.0))],
+            CssParser::new("width: 1px; height").parse_declaration_list());
+        assert_eq!(Vec::<Declaration>::new(), CssParser::new("  ").parse_declaration_list());
+    }
 }
@@ -54,11 +54,31 @@ impl ElementData {
         }
     }
 
+    /// Returns the value of one of an element's attributes
+    pub fn get_attribute(&self, name: &str) -> Option<&String> {
+        self.attributes.get(name)
+    }
+
     /// Returns an element's id
     pub fn get_id(&self) -> Option<&String> {
         self.attributes.get("id")
     }
 
+    /// Sets one of an element's attributes, replacing its old value.
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.attributes.insert(String::from(name), String::from(value));
+    }
+
+    /// Removes one of an element's attributes, returning its old value.
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        self.attributes.remove(name)
+    }
+
+    /// Returns an element's inline style attribute
+    pub fn get_style(&self) -> Option<&String> {
+        self.attributes.get("style")
+    }
+
     /// Returns an element's classes
     pub fn get_classes(&self) -> HashSet<&str> {
         match self.attributes.get("class") {
@@ -152,6 +172,18 @@ mod tests {
         assert_eq!(expected, elem.get_id());
     }
 
+    /// Test ElementData.set_attribute() and remove_attribute() change the attributes.
+    #[test]
+    fn elemdata_set_attribute() {
+        let mut elem = ElementData::new(String::from("p"), HashMap::new());
+
+        elem.set_attribute("class", "a");
+        elem.set_attribute("class", "b c");
+        assert_eq!(vec!["b", "c"].into_iter().collect::<HashSet<&str>>(), elem.get_classes());
+        assert_eq!(Some(String::from("b c")), elem.remove_attribute("class"));
+        assert_eq!(None, elem.get_attribute("class"));
+    }
+
     /// Test ElementData.get_id() returns none if there is no id.
     #[test]
     fn elemdata_get_id_empty() {
@@ -226,4 +258,16 @@ mod tests {
 
         assert_eq!(expected, elem.get_classes());
     }
+
+    /// Test an element's inline style is returned.
+    #[test]
+    fn elemdata_get_style() {
+        let mut attrs = HashMap::new();
+        attrs.insert("style".to_string(), "color: red".to_string());
+        let elem = ElementData::new("div".to_string(), attrs);
+
+        assert_eq!(Some(&"color: red".to_string()), elem.get_style());
+        assert_eq!(elem.get_style(), elem.get_attribute("style"));
+        assert_eq!(None, ElementData::new("div".to_string(), HashMap::new()).get_style());
+    }
 }
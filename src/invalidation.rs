@@ -0,0 +1,182 @@
+//! The `invalidation` module works out which styled nodes a change to the dom or to the
+//! stylesheets could affect, so a restyle only recomputes their styles.
+
+use css::{Origin, Rule, Selector, Stylesheet};
+use dom::ElementData;
+
+use std::collections::HashMap;
+
+/// What a change requires to be restyled, the dirty bits of a styled node.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub struct RestyleHint {
+    pub element: bool, // the node itself
+    pub subtree: bool, // the node's descendants
+    pub siblings: bool, // the node's later siblings and their descendants
+}
+
+/// The ids and classes the selectors of a set of stylesheets depend on, and what changing
+/// them on an element could restyle.
+///
+/// A class in the rightmost compound of a selector restyles the element, and one in another
+/// compound restyles the descendants, which the descendant and child combinators relate it to.
+#[derive(Default)]
+pub struct Dependencies {
+    ids: HashMap<String, RestyleHint>,
+    classes: HashMap<String, RestyleHint>,
+}
+
+impl RestyleHint {
+    /// Returns a hint that restyles everything this hint or another one does.
+    pub fn union(self, other: RestyleHint) -> RestyleHint {
+        RestyleHint {
+            element: self.element || other.element,
+            subtree: self.subtree || other.subtree,
+            siblings: self.siblings || other.siblings,
+        }
+    }
+
+    /// Returns true if the hint restyles nothing.
+    pub fn is_empty(&self) -> bool {
+        *self == RestyleHint::default()
+    }
+}
+
+impl Dependencies {
+    /// Constructs the dependencies of the rules of a set of stylesheets, whatever their media.
+    pub fn new<'s, I>(sheets: I) -> Dependencies
+        where I: IntoIterator<Item = &'s Stylesheet> {
+        let mut dependencies = Dependencies::default();
+
+        for rule in sheets.into_iter().flat_map(|ss| &ss.rules) {
+            for selector in &rule.selectors {
+                dependencies.add(selector);
+            }
+        }
+
+        dependencies
+    }
+
+    /// Adds the ids and classes of a selector.
+    fn add(&mut self, selector: &Selector) {
+        for (i, simple) in selector.simple.iter().enumerate() {
+            let hint = match selector.combinators.get(i) {
+                None => RestyleHint { element: true, ..RestyleHint::default() },
+                Some(_) => RestyleHint { subtree: true, ..RestyleHint::default() },
+            };
+
+            if let Some(ref id) = simple.id {
+                let entry = self.ids.entry(id.clone()).or_default();
+                *entry = entry.union(hint);
+            }
+            for class in &simple.classes {
+                let entry = self.classes.entry(class.clone()).or_default();
+                *entry = entry.union(hint);
+            }
+        }
+    }
+
+    /// Returns what changing the attributes of an element could restyle.
+    ///
+    /// Selectors can't match other attributes than the id and classes, so only those and the
+    /// style attribute are compared. A changed tag name restyles everything around the element.
+    ///
+    /// old: The element before the change.
+    /// new: The element after the change.
+    pub fn hint(&self, old: &ElementData, new: &ElementData) -> RestyleHint {
+        if old.tag_name != new.tag_name {
+            return RestyleHint { element: true, subtree: true, siblings: true };
+        }
+
+        let mut hint = RestyleHint {
+            element: old.get_style() != new.get_style(),
+            ..RestyleHint::default()
+        };
+
+        if old.get_id() != new.get_id() {
+            for id in old.get_id().into_iter().chain(new.get_id()) {
+                hint = hint.union(self.ids.get(id).cloned().unwrap_or_default());
+            }
+        }
+
+        let (old_classes, new_classes) = (old.get_classes(), new.get_classes());
+        for class in old_classes.symmetric_difference(&new_classes) {
+            hint = hint.union(self.classes.get(*class).cloned().unwrap_or_default());
+        }
+
+        hint
+    }
+}
+
+/// Returns the rules that differ between two versions of a set of stylesheets: those between
+/// the rules both versions start and end with. Any element these match may be styled
+/// differently, including by a rule that only moved in the cascade order.
+///
+/// old: The stylesheets before the change.
+/// new: The stylesheets after the change.
+pub fn changed_rules<'s>(old: &'s [Stylesheet], new: &'s [Stylesheet]) -> Vec<&'s Rule> {
+    let flatten = |sheets: &'s [Stylesheet]| sheets.iter()
+        .flat_map(|ss| ss.rules.iter().map(move |r| (ss.origin, r)))
+        .collect::<Vec<(Origin, &Rule)>>();
+    let (old, new) = (flatten(old), flatten(new));
+
+    let prefix = old.iter().zip(&new).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|&(a, b)| a == b).count();
+
+    old[prefix..old.len() - suffix].iter()
+        .chain(&new[prefix..new.len() - suffix])
+        .map(|&(_, rule)| rule)
+        .collect()
+}
+
+/// Tests -----------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css_parse::CssParser;
+
+    /// Build an element with an id, classes and a style attribute.
+    fn elem(id: Option<&str>, classes: &str, style: Option<&str>) -> ElementData {
+        let mut attrs = HashMap::new();
+        attrs.insert(String::from("class"), String::from(classes));
+        if let Some(id) = id {
+            attrs.insert(String::from("id"), String::from(id));
+        }
+        if let Some(style) = style {
+            attrs.insert(String::from("style"), String::from(style));
+        }
+        ElementData::new(String::from("div"), attrs)
+    }
+
+    /// Test changed ids, classes and style attributes restyle what their selectors affect.
+    #[test]
+    fn dependency_hints() {
+        let sheets = vec![CssParser::new(".a { color: red; } .b p { color: red; } div > .c { color: red; }
+            #x .a { color: red; } #y { color: red; }").parse_stylesheet()];
+        let deps = Dependencies::new(&sheets);
+        let element = RestyleHint { element: true, ..RestyleHint::default() };
+        let subtree = RestyleHint { subtree: true, ..RestyleHint::default() };
+
+        assert_eq!(element, deps.hint(&elem(None, "", None), &elem(None, "a", None)));
+        assert_eq!(subtree, deps.hint(&elem(None, "b a", None), &elem(None, "a", None)));
+        assert_eq!(element.union(subtree), deps.hint(&elem(None, "a", None), &elem(None, "b", None)));
+        assert!(deps.hint(&elem(None, "c", None), &elem(None, "c d", None)).is_empty());
+        assert_eq!(subtree, deps.hint(&elem(Some("x"), "", None), &elem(None, "", None)));
+        assert_eq!(element.union(subtree), deps.hint(&elem(Some("x"), "", None), &elem(Some("y"), "", None)));
+        assert_eq!(element, deps.hint(&elem(None, "", None), &elem(None, "", Some("width: 1px"))));
+    }
+
+    /// Test the changed rules are those between the common start and end of the rules.
+    #[test]
+    fn changed_rules_between() {
+        let sheet = |css: &str| vec![CssParser::new(css).parse_stylesheet()];
+        let old = sheet("a { width: 1px; } b { width: 2px; } c { width: 3px; } d { width: 4px; }");
+        let new = sheet("a { width: 1px; } c { width: 3px; } b { width: 2px; } d { width: 4px; }");
+        let changed = changed_rules(&old, &new).iter()
+            .map(|r| format!("{:?}", r.selectors))
+            .collect::<Vec<String>>();
+
+        assert_eq!(vec!["[b]", "[c]", "[c]", "[b]"], changed);
+        assert!(changed_rules(&old, &old).is_empty());
+        assert_eq!(4, changed_rules(&old, &[]).len());
+    }
+}
@@ -1,13 +1,13 @@
 //! The `layout` module takes a style tree and creates a layout of boxes.
 
-use css::{Unit, Value};
-use style::{Display, StyledNode};
+use computed::{Display, DisplayInside, DisplayInternal, DisplayOutside, LineHeight, Size};
+use style::StyledNode;
 use std::fmt;
 
 pub struct LayoutBox<'a> {
     pub dimensions: Dimensions,
     box_type: BoxType,
-    pub styled_node: &'a StyledNode<'a>,
+    pub styled_node: &'a StyledNode,
     pub children: Vec<LayoutBox<'a>>,
 }
 
@@ -16,7 +16,14 @@ pub enum BoxType {
     Block,
     Inline,
     InlineBlock,
-    Anonymous,
+    FlowRoot, // a block that contains its floats and margins
+    ListItem(DisplayOutside),
+    Flex(DisplayOutside),
+    Grid(DisplayOutside),
+    Table(DisplayOutside),
+    TablePart(DisplayInternal), // a row, cell, caption or group of a table
+    TextRun(String), // text with its white space collapsed
+    Anonymous, // a block wrapping a run of inline-level boxes among block-level ones
 }
 
 #[derive(Clone, Copy, Default)]
@@ -57,15 +64,22 @@ impl<'a> LayoutBox<'a> {
         }
     }
 
+    /// Returns the type of the box.
+    pub fn box_type(&self) -> &BoxType {
+        &self.box_type
+    }
+
     /// Lays out the current box, including recursively laying out children boxes.
     ///
     /// b_box: the parent bounding box.
     fn layout(&mut self, b_box: Dimensions) {
+        // TODO lay out flex, grid and table boxes by their own algorithms, not as flow
         match self.box_type {
-            BoxType::Block => self.layout_block(b_box),
-            BoxType::Inline => self.layout_block(b_box), // TODO
-            BoxType::InlineBlock => self.layout_inline_block(b_box),
-            BoxType::Anonymous => {}, // TODO
+            BoxType::Inline => self.layout_inline(b_box),
+            BoxType::TextRun(_) => self.layout_text(b_box),
+            BoxType::Anonymous => self.layout_anonymous(b_box),
+            ref t if t.is_block_level() => self.layout_block(b_box),
+            _ => self.layout_inline_block(b_box),
         }
     }
 
@@ -76,30 +90,187 @@ impl<'a> LayoutBox<'a> {
         self.calculate_height();
     }
 
+    /// Places an inline box on the current line of its container, as wide as the line content
+    /// of its children, which continue the container's line.
+    ///
+    /// b_box: the parent bounding box.
+    fn layout_inline(&mut self, b_box: Dimensions) {
+        {
+            let d = &mut self.dimensions;
+            d.content.x = b_box.content.x + b_box.current.x;
+            d.content.y = b_box.content.y + b_box.content.height;
+            // the children break onto a new line where the container's line ends
+            d.content.width = (b_box.content.width - b_box.current.x).max(0.0);
+        }
+
+        self.layout_children();
+
+        let d = &mut self.dimensions;
+        d.content.width = self.children.iter()
+            .map(|child| {
+                let margin_box = child.dimensions.margin_box();
+                margin_box.x + margin_box.width - d.content.x
+            })
+            .fold(0.0, f32::max);
+    }
+
+    /// Lays out an anonymous block, which has no margins, borders or padding and is as wide as
+    /// its container.
+    ///
+    /// b_box: the parent bounding box.
+    fn layout_anonymous(&mut self, b_box: Dimensions) {
+        let d = &mut self.dimensions;
+        d.content.width = b_box.content.width;
+        d.content.x = b_box.content.x;
+        d.content.y = b_box.content.y + b_box.content.height;
+
+        self.layout_children();
+    }
+
+    /// Wraps each run of inline-level children in an anonymous block if the box is a block
+    /// container with block-level children, so its children are all block-level.
+    fn wrap_inline_runs(&mut self) {
+        if !self.box_type.is_block_container() || !self.children.iter().any(|c| c.box_type.is_block_level()) {
+            return;
+        }
+
+        let styled_node = self.styled_node;
+        let mut children: Vec<LayoutBox<'a>> = Vec::new();
+
+        for child in self.children.drain(..) {
+            if child.box_type.is_block_level() {
+                children.push(child);
+                continue;
+            }
+
+            // continue the run of the previous child, or start a new one
+            match children.last_mut() {
+                Some(last) if matches!(last.box_type, BoxType::Anonymous) => last.children.push(child),
+                _ => {
+                    let mut anonymous = LayoutBox::new(BoxType::Anonymous, styled_node);
+                    anonymous.children.push(child);
+                    children.push(anonymous);
+                },
+            }
+        }
+
+        self.children = children;
+    }
+
+    /// Collapses the white space of the line of inline-level boxes the box's children make up,
+    /// or of each anonymous block's line. Runs of spaces between text runs collapse to one
+    /// space, and the line's leading and trailing spaces are removed.
+    fn collapse_white_space(&mut self) {
+        for child in &mut self.children {
+            if let BoxType::Anonymous = child.box_type {
+                child.collapse_white_space();
+            }
+        }
+
+        self.collapse_spaces(&mut true);
+        self.trim_end();
+        self.children.retain(|child| match child.box_type {
+            BoxType::Anonymous => !child.children.is_empty(),
+            _ => true,
+        });
+    }
+
+    /// Removes the spaces that follow another space on the line, the text runs left empty.
+    /// Inline boxes are part of their container's line, so their text runs are included.
+    ///
+    /// space_before: whether the line is empty or ends with a space so far.
+    fn collapse_spaces(&mut self, space_before: &mut bool) {
+        for child in &mut self.children {
+            match child.box_type {
+                BoxType::TextRun(ref mut text) => {
+                    if *space_before && text.starts_with(' ') {
+                        text.remove(0);
+                    }
+                    if !text.is_empty() {
+                        *space_before = text.ends_with(' ');
+                    }
+                },
+                BoxType::Inline => child.collapse_spaces(space_before),
+                ref t if t.is_block_level() => *space_before = true,
+                _ => *space_before = false,
+            }
+        }
+
+        self.children.retain(|child| !matches!(child.box_type, BoxType::TextRun(ref text) if text.is_empty()));
+    }
+
+    /// Removes the trailing spaces of the line, returning true once content other than spaces
+    /// ends it.
+    fn trim_end(&mut self) -> bool {
+        let mut trimmed = false;
+
+        for child in self.children.iter_mut().rev() {
+            trimmed = match child.box_type {
+                BoxType::TextRun(ref mut text) => {
+                    let len = text.trim_end_matches(' ').len();
+                    text.truncate(len);
+                    !text.is_empty()
+                },
+                BoxType::Inline => child.trim_end(),
+                _ => true,
+            };
+            if trimmed {
+                break;
+            }
+        }
+
+        self.children.retain(|child| !matches!(child.box_type, BoxType::TextRun(ref text) if text.is_empty()));
+        trimmed
+    }
+
+    /// Places a run of text on the current line of its container.
+    ///
+    /// b_box: the parent bounding box.
+    fn layout_text(&mut self, b_box: Dimensions) {
+        let style = self.styled_node.computed();
+        let chars = match self.box_type {
+            BoxType::TextRun(ref text) => text.chars().count(),
+            _ => 0,
+        };
+        let d = &mut self.dimensions;
+
+        // without font metrics every character is half an em wide, like the ch unit
+        d.content.width = chars as f32 * style.font_size / 2.0;
+        d.content.height = match style.line_height {
+            LineHeight::Normal => style.font_size * 1.2,
+            LineHeight::Number(n) => style.font_size * n,
+            LineHeight::Px(px) => px,
+        };
+        d.content.x = b_box.content.x + b_box.current.x;
+        d.content.y = b_box.content.y + b_box.content.height;
+    }
+
     fn calculate_inline_width(&mut self, b_box: Dimensions) {
-        let s = self.styled_node;
+        let s = self.styled_node.computed();
+        let base = Some(b_box.content.width);
         let d = &mut self.dimensions;
 
-        d.content.width = get_absolute_num(s, b_box, "width").unwrap_or(0.0);
-        d.margin.left = s.num_or("margin-left", 0.0);
-        d.margin.right = s.num_or("margin-right", 0.0);
-        d.padding.left = s.num_or("padding-left", 0.0);
-        d.padding.right = s.num_or("padding-right", 0.0);
-        d.border.left = s.num_or("border-left-width", 0.0);
-        d.border.right = s.num_or("border-right-width", 0.0);
+        d.content.width = s.width.resolve(base).unwrap_or(0.0).max(0.0);
+        d.margin.left = s.margin.left.resolve(base).unwrap_or(0.0);
+        d.margin.right = s.margin.right.resolve(base).unwrap_or(0.0);
+        d.padding.left = s.padding.left.resolve(base).unwrap_or(0.0);
+        d.padding.right = s.padding.right.resolve(base).unwrap_or(0.0);
+        d.border.left = s.border_width.left;
+        d.border.right = s.border_width.right;
     }
 
     /// Position current box below previous boxes in container by updating height
     fn calculate_inline_position(&mut self, b_box: Dimensions) {
-        let style = self.styled_node;
+        let style = self.styled_node.computed();
+        let base = Some(b_box.content.width);
         let d = &mut self.dimensions;
 
-        d.margin.top = style.num_or("margin-top", 0.0);
-        d.margin.bottom = style.num_or("margin-bottom", 0.0);
-        d.border.top = style.num_or("border-top-width", 0.0);
-        d.border.bottom = style.num_or("border-bottom-width", 0.0);
-        d.padding.top = style.num_or("padding-top", 0.0);
-        d.padding.bottom = style.num_or("padding-bottom", 0.0);
+        d.margin.top = style.margin.top.resolve(base).unwrap_or(0.0);
+        d.margin.bottom = style.margin.bottom.resolve(base).unwrap_or(0.0);
+        d.border.top = style.border_width.top;
+        d.border.bottom = style.border_width.bottom;
+        d.padding.top = style.padding.top.resolve(base).unwrap_or(0.0);
+        d.padding.bottom = style.padding.bottom.resolve(base).unwrap_or(0.0);
 
         d.content.x = b_box.content.x + b_box.current.x + d.margin.left + d.border.left + d.padding.left;
         d.content.y = b_box.content.height + b_box.content.y + d.margin.top + d.border.top
@@ -120,41 +291,32 @@ impl<'a> LayoutBox<'a> {
     ///
     /// b_box: the parent bounding box.
     fn calculate_width(&mut self, b_box: Dimensions) {
-        let style = self.styled_node;
+        let style = self.styled_node.computed();
+        let base = Some(b_box.content.width);
         let d = &mut self.dimensions;
 
-        let width = get_absolute_num(style, b_box, "width").unwrap_or(0.0);
-        let margin_l = style.value("margin-left");
-        let margin_r = style.value("margin-right");
+        // a math function can compute a negative width, which is clamped to 0
+        let width = style.width.resolve(base).unwrap_or(0.0).max(0.0);
+        let width_auto = style.width == Size::Auto;
+        let margin_l_auto = style.margin.left == Size::Auto;
+        let margin_r_auto = style.margin.right == Size::Auto;
 
-        let margin_l_num = match margin_l {
-            Some(m) => match **m {
-                Value::Other(ref s) => s.parse().unwrap_or(0.0),
-                _ => 0.0,
-            },
-            None => 0.0,
-        };
-        let margin_r_num = match margin_r {
-            Some(m) => match **m {
-                Value::Other(ref s) => s.parse().unwrap_or(0.0),
-                _ => 0.0,
-            },
-            None => 0.0,
-        };
+        let margin_l_num = style.margin.left.resolve(base).unwrap_or(0.0);
+        let margin_r_num = style.margin.right.resolve(base).unwrap_or(0.0);
 
-        d.border.left = style.num_or("border-left-width", 0.0);
-        d.border.right = style.num_or("border-right-width", 0.0);
-        d.padding.left = style.num_or("padding-left", 0.0);
-        d.padding.right = style.num_or("padding-right", 0.0);
+        d.border.left = style.border_width.left;
+        d.border.right = style.border_width.right;
+        d.padding.left = style.padding.left.resolve(base).unwrap_or(0.0);
+        d.padding.right = style.padding.right.resolve(base).unwrap_or(0.0);
 
         let total = width + margin_l_num + margin_r_num + d.border.left + d.border.right + d.padding.left
             + d.padding.right;
 
         let underflow = b_box.content.width - total;
 
-        match (width, margin_l, margin_r) {
+        match (width_auto, margin_l_auto, margin_r_auto) {
             // width is auto
-            (0.0, _, _) => {
+            (true, _, _) => {
                 if underflow >= 0.0 {
                     d.content.width = underflow;
                     d.margin.right = margin_r_num;
@@ -166,22 +328,22 @@ impl<'a> LayoutBox<'a> {
                 d.margin.left = margin_l_num;
             },
             // left margin is auto
-            (w, None, Some(_)) if w != 0.0 => {
+            (false, true, false) => {
                 d.margin.left = underflow;
                 d.margin.right = margin_r_num;
-                d.content.width = w;
+                d.content.width = width;
             },
             // right margin is auto
-            (w, Some(_), None) if w != 0.0 => {
+            (false, false, true) => {
                 d.margin.right = underflow;
                 d.margin.left = margin_l_num;
-                d.content.width = w;
+                d.content.width = width;
             },
             // left/right margin are auto
-            (w, None, None) if w != 0.0 => {
+            (false, true, true) => {
                 d.margin.left = underflow / 2.0;
                 d.margin.right = underflow / 2.0;
-                d.content.width = w;
+                d.content.width = width;
             },
             // values are overconstrained, calculate margin_right.
             (_, _, _) => {
@@ -194,15 +356,16 @@ impl<'a> LayoutBox<'a> {
 
     /// Position current box below previous boxes in container by updating height
     fn calculate_position(&mut self, b_box: Dimensions) {
-        let style = self.styled_node;
+        let style = self.styled_node.computed();
+        let base = Some(b_box.content.width);
         let d = &mut self.dimensions;
 
-        d.margin.top = style.num_or("margin-top", 0.0);
-        d.margin.bottom = style.num_or("margin-bottom", 0.0);
-        d.border.top = style.num_or("border-top-width", 0.0);
-        d.border.bottom = style.num_or("border-bottom-width", 0.0);
-        d.padding.top = style.num_or("padding-top", 0.0);
-        d.padding.bottom = style.num_or("padding-bottom", 0.0);
+        d.margin.top = style.margin.top.resolve(base).unwrap_or(0.0);
+        d.margin.bottom = style.margin.bottom.resolve(base).unwrap_or(0.0);
+        d.border.top = style.border_width.top;
+        d.border.bottom = style.border_width.bottom;
+        d.padding.top = style.padding.top.resolve(base).unwrap_or(0.0);
+        d.padding.bottom = style.padding.bottom.resolve(base).unwrap_or(0.0);
 
         d.content.x = b_box.content.x + d.margin.left + d.border.left + d.padding.left;
         d.content.y = b_box.content.height + b_box.content.y + d.margin.top + d.border.top
@@ -211,12 +374,12 @@ impl<'a> LayoutBox<'a> {
 
     /// Use a style node's height value if it exists
     fn calculate_height(&mut self) {
-        self.styled_node.value("height").map_or((), |h| {
-            match **h {
-                Value::Length(n, _) => self.dimensions.content.height = n,
-                _ => {},
-            }
-        })
+        // TODO: resolve percentages against the containing block's height
+        let height = self.styled_node.computed().height.resolve(None);
+
+        if let Some(h) = height {
+            self.dimensions.content.height = h.max(0.0);
+        }
     }
 
     /// Layout the current nodes children and adjust it's height.
@@ -227,15 +390,10 @@ impl<'a> LayoutBox<'a> {
         let mut prevBoxType = BoxType::Block;
 
         for child in &mut self.children {
-            match prevBoxType {
-                BoxType::InlineBlock => match child.box_type {
-                    BoxType::Block => {
-                        d.content.height += max_child_height;
-                        d.current.x = 0.0;  
-                    },
-                    _ => {},
-                },
-                _ => {},
+            // a block-level child ends the line of the inline-level ones before it
+            if !prevBoxType.is_block_level() && child.box_type.is_block_level() {
+                d.content.height += max_child_height;
+                d.current.x = 0.0;
             }
 
             child.layout(*d);
@@ -245,23 +403,26 @@ impl<'a> LayoutBox<'a> {
                 max_child_height = new_height;
             }
 
-            match child.box_type {
-                BoxType::Block => d.content.height += child.dimensions.margin_box().height,
-                BoxType::InlineBlock => {
-                    d.current.x += child.dimensions.margin_box().width;
+            if child.box_type.is_block_level() {
+                d.content.height += child.dimensions.margin_box().height;
+            } else {
+                d.current.x += child.dimensions.margin_box().width;
 
-                    if d.current.x > d.content.width {
-                        d.content.height += max_child_height;
-                        d.current.x = 0.0;
-                        child.layout(*d); // relayout child
-                        d.current.x += child.dimensions.margin_box().width;
-                    }
-                },
-                _ => {},
+                if d.current.x > d.content.width {
+                    d.content.height += max_child_height;
+                    d.current.x = 0.0;
+                    child.layout(*d); // relayout child
+                    d.current.x += child.dimensions.margin_box().width;
+                }
             }
 
             prevBoxType = child.box_type.clone();
         }
+
+        // the last line of inline-level boxes, inline boxes and text included
+        if !prevBoxType.is_block_level() {
+            d.content.height += max_child_height;
+        }
     }
 }
 impl<'a> fmt::Debug for LayoutBox<'a> {
@@ -320,12 +481,87 @@ impl fmt::Debug for EdgeSizes {
     }
 }
 
+impl BoxType {
+    /// Returns the box type an element of a display type generates, or None if it generates
+    /// no box of its own.
+    ///
+    /// display: the computed display of the element.
+    pub fn from_display(display: Display) -> Option<BoxType> {
+        match display {
+            Display::Normal { outside, list_item: true, .. } => Some(BoxType::ListItem(outside)),
+            Display::Normal { outside, inside, .. } => Some(match (outside, inside) {
+                (DisplayOutside::Block, DisplayInside::Flow) => BoxType::Block,
+                (DisplayOutside::Block, DisplayInside::FlowRoot) => BoxType::FlowRoot,
+                (DisplayOutside::Inline, DisplayInside::Flow) => BoxType::Inline,
+                (DisplayOutside::Inline, DisplayInside::FlowRoot) => BoxType::InlineBlock,
+                (outside, DisplayInside::Flex) => BoxType::Flex(outside),
+                (outside, DisplayInside::Grid) => BoxType::Grid(outside),
+                (outside, DisplayInside::Table) => BoxType::Table(outside),
+            }),
+            Display::Internal(internal) => Some(BoxType::TablePart(internal)),
+            Display::Contents | Display::None => None,
+        }
+    }
+
+    /// Returns true if boxes of the type are stacked vertically in their container.
+    pub fn is_block_level(&self) -> bool {
+        match *self {
+            BoxType::Block | BoxType::FlowRoot | BoxType::TablePart(_) | BoxType::Anonymous => true,
+            BoxType::ListItem(outside) | BoxType::Flex(outside) | BoxType::Grid(outside)
+                | BoxType::Table(outside) => outside == DisplayOutside::Block,
+            _ => false,
+        }
+    }
+
+    /// Returns true if boxes of the type lay out their children in flow, as blocks or lines.
+    ///
+    /// Flex and grid items and table parts are laid out by their containers, and an inline
+    /// box's block-level children would split it.
+    pub fn is_block_container(&self) -> bool {
+        // TODO split inline boxes around their block-level children
+        match *self {
+            BoxType::Block | BoxType::FlowRoot | BoxType::ListItem(_) | BoxType::InlineBlock
+                | BoxType::Anonymous => true,
+            BoxType::TablePart(internal) =>
+                internal == DisplayInternal::TableCell || internal == DisplayInternal::TableCaption,
+            _ => false,
+        }
+    }
+
+    /// Returns true if boxes of the type are placed whole on the current line of their
+    /// container.
+    pub fn is_atomic_inline(&self) -> bool {
+        match *self {
+            BoxType::InlineBlock | BoxType::TextRun(_) => true,
+            BoxType::ListItem(outside) | BoxType::Flex(outside) | BoxType::Grid(outside)
+                | BoxType::Table(outside) => outside == DisplayOutside::Inline,
+            _ => false,
+        }
+    }
+}
+
 impl fmt::Debug for BoxType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inline = |outside: DisplayOutside, block: &'static str, inline: &'static str|
+            if outside == DisplayOutside::Block { block } else { inline };
         let display_type = match *self {
             BoxType::Block => "block",
             BoxType::Inline => "inline",
             BoxType::InlineBlock => "inline-block",
+            BoxType::FlowRoot => "flow-root",
+            BoxType::ListItem(outside) => inline(outside, "list-item", "inline list-item"),
+            BoxType::Flex(outside) => inline(outside, "flex", "inline-flex"),
+            BoxType::Grid(outside) => inline(outside, "grid", "inline-grid"),
+            BoxType::Table(outside) => inline(outside, "table", "inline-table"),
+            BoxType::TablePart(DisplayInternal::TableRowGroup) => "table-row-group",
+            BoxType::TablePart(DisplayInternal::TableHeaderGroup) => "table-header-group",
+            BoxType::TablePart(DisplayInternal::TableFooterGroup) => "table-footer-group",
+            BoxType::TablePart(DisplayInternal::TableRow) => "table-row",
+            BoxType::TablePart(DisplayInternal::TableCell) => "table-cell",
+            BoxType::TablePart(DisplayInternal::TableColumnGroup) => "table-column-group",
+            BoxType::TablePart(DisplayInternal::TableColumn) => "table-column",
+            BoxType::TablePart(DisplayInternal::TableCaption) => "table-caption",
+            BoxType::TextRun(_) => "text",
             BoxType::Anonymous => "anonymous"
         };
 
@@ -333,54 +569,73 @@ impl fmt::Debug for BoxType {
     }
 }
 
-fn get_absolute_num(s_node: &StyledNode, b_box: Dimensions, prop: &str) -> Option<f32> {
-    match s_node.value(prop) {
-        Some(ref v) => match ***v {
-            Value::Length(l, ref u) => match *u {
-                Unit::Px => Some(l),
-                Unit::Pct => Some(l * b_box.content.width / 100.0),
-                _ => panic!("Unimplemented css length unit"),
-            },
-            _ => None,
-        },
-        None => None,
-    }
-}
-
-/// Entry point to create a layout tree.
+/// Entry point to create a layout tree, None if the root has display: none.
 ///
 /// root: The root of the style tree to layout.
 /// containing_block: The window or viewport.
-pub fn layout_tree<'a>(root: &'a StyledNode<'a>, mut containing_block: Dimensions) -> LayoutBox<'a> {
+pub fn layout_tree<'a>(root: &'a StyledNode, mut containing_block: Dimensions) -> Option<LayoutBox<'a>> {
     // The layout algorithm expects the container height to start at 0.
     // TODO: Save the initial containing block height, for calculating percent heights.
     containing_block.content.height = 0.0;
 
-    let mut root_box = build_layout_tree(root);
+    // the root's display: contents computes to block
+    let box_type = match root.computed().display {
+        Display::None => return None,
+        Display::Contents => BoxType::Block,
+        display => BoxType::from_display(display)?,
+    };
+
+    let mut root_box = build_layout_box(box_type, root);
     root_box.layout(containing_block);
-    return root_box;
+    Some(root_box)
 }
 
 /// Recursively builds the layout tree.
 ///
+/// box_type: The type of the node's box.
 /// node: The current style node being laid out.
-fn build_layout_tree<'a>(node: &'a StyledNode) -> LayoutBox<'a> {
-    let mut layout_node = LayoutBox::new(match node.get_display() {
-        Display::Block => BoxType::Block,
-        Display::Inline => BoxType::Inline,
-        Display::InlineBlock => BoxType::InlineBlock,
-        Display::None => BoxType::Anonymous,
-    }, node);
+fn build_layout_box<'a>(box_type: BoxType, node: &'a StyledNode) -> LayoutBox<'a> {
+    let mut layout_node = LayoutBox::new(box_type, node);
+
+    build_children(&mut layout_node, node);
+    layout_node.wrap_inline_runs();
+    // an inline box's white space collapses as part of its container's line
+    if !matches!(layout_node.box_type, BoxType::Inline) {
+        layout_node.collapse_white_space();
+    }
+    layout_node
+}
 
+/// Adds the boxes of the children of a style node to a layout box.
+///
+/// layout_node: The box the children's boxes are added to.
+/// node: The style node whose children are laid out.
+fn build_children<'a>(layout_node: &mut LayoutBox<'a>, node: &'a StyledNode) {
     for child in &node.children {
-        match child.get_display() {
-            Display::Block => layout_node.children.push(build_layout_tree(child)),
-            Display::Inline => layout_node.children.push(build_layout_tree(child)),
-            Display::InlineBlock => layout_node.children.push(build_layout_tree(child)),
-            Display::None => {}
+        if let Some(text) = child.text() {
+            // white space collapses to single spaces here, and across the line once it's built
+            let mut collapsed = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+            if text.starts_with(char::is_whitespace) {
+                collapsed.insert(0, ' ');
+            }
+            if text.ends_with(char::is_whitespace) && collapsed != " " {
+                collapsed.push(' ');
+            }
+            let text = collapsed;
+            if !text.is_empty() {
+                layout_node.children.push(LayoutBox::new(BoxType::TextRun(text), child));
+            }
+            continue;
+        }
+
+        match child.computed().display {
+            // the children of display: contents take the place of its box
+            Display::Contents => build_children(layout_node, child),
+            display => if let Some(box_type) = BoxType::from_display(display) {
+                layout_node.children.push(build_layout_box(box_type, child));
+            },
         }
     }
-    layout_node
 }
 
 /// Print a layout node and it's descendents
@@ -398,10 +653,243 @@ pub fn pretty_print<'a>(n: &'a LayoutBox, level: usize) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use css_parse::CssParser;
+    use dom::{ElementData, Node, NodeType};
+    use html_parse::HtmlParser;
+    use media::MediaEnvironment;
+    use std::collections::HashMap;
 
     /// Test
     #[test]
     fn it_works() {
 
     }
+
+    /// Lays out a div styled by the css in a viewport of the given width.
+    fn layout_div(css: &str, width: f32) -> Dimensions {
+        let elem = ElementData::new(String::from("div"), HashMap::new());
+        let node = Node::new(NodeType::Element(elem), vec![]);
+        let sheets = vec![CssParser::new(css).parse_stylesheet()];
+        let mut viewport = Dimensions::default();
+        viewport.content.width = width;
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::new(&viewport));
+
+        layout_tree(&styled, viewport).unwrap().dimensions
+    }
+
+    /// Test math functions resolve against the containing block.
+    #[test]
+    fn width_math() {
+        let width = |w: &str| layout_div(&format!("div {{ display: block; width: {}; }}", w), 400.0).content.width;
+
+        assert_eq!(368.0, width("calc(100% - 2 * 16px)"));
+        assert_eq!(200.0, width("clamp(100px, 50%, 600px)"));
+        assert_eq!(150.0, width("min(150px, 50%)"));
+        // a negative width clamps to 0 rather than becoming auto, like an explicit 0
+        assert_eq!(0.0, width("calc(10px - 50px)"));
+        assert_eq!(0.0, width("0"));
+    }
+
+    /// Lays out a div in a section, both styled by the css, in a 1000x500 viewport.
+    /// Returns the dimensions of the section and the div, and the div's font size.
+    fn layout_nested(css: &str) -> (Dimensions, Dimensions, f32) {
+        let div = Node::new(NodeType::Element(ElementData::new(String::from("div"), HashMap::new())), vec![]);
+        let section = ElementData::new(String::from("section"), HashMap::new());
+        let node = Node::new(NodeType::Element(section), vec![div]);
+        let sheets = vec![CssParser::new(css).parse_stylesheet()];
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 1000.0;
+        viewport.content.height = 500.0;
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::new(&viewport));
+
+        let root = layout_tree(&styled, viewport).unwrap();
+        (root.dimensions, root.children[0].dimensions, root.children[0].styled_node.computed().font_size)
+    }
+
+    /// Test font relative units resolve against the box's and the root's font size.
+    #[test]
+    fn units_font_relative() {
+        let (root, d, font_size) = layout_nested("section { display: block; font-size: 20px; width: 10em; }
+            div { display: block; font-size: 1.5em; width: 10em; height: 2rem; padding-left: 1ex; margin-top: 1ch; }");
+
+        assert_eq!(200.0, root.content.width);
+        assert_eq!(30.0, font_size);
+        assert_eq!(300.0, d.content.width);
+        assert_eq!(40.0, d.content.height);
+        assert_eq!(15.0, d.padding.left);
+        assert_eq!(15.0, d.margin.top);
+    }
+
+    /// Test viewport and absolute units, and font size keywords and percentages.
+    #[test]
+    fn units_viewport_absolute() {
+        let (root, d, font_size) = layout_nested("section { display: block; font-size: large; width: 50vw; height: 10vh; }
+            div { display: block; font-size: 50%; width: 1in; height: 12pt; margin-left: 1vmin;
+                margin-top: 1vmax; border-top-width: 6pc; border-top-style: solid; padding-top: 10mm; padding-bottom: 4q; }");
+
+        assert_eq!(500.0, root.content.width);
+        assert_eq!(50.0, root.content.height);
+        assert_eq!(9.0, font_size);
+        assert_eq!(96.0, d.content.width);
+        assert_eq!(16.0, d.content.height);
+        assert_eq!(5.0, d.margin.left);
+        assert_eq!(10.0, d.margin.top);
+        assert_eq!(96.0, d.border.top);
+        assert_eq!(37.8, (d.padding.top * 10.0).round() / 10.0);
+        assert_eq!(3.8, (d.padding.bottom * 10.0).round() / 10.0);
+    }
+
+    /// Test text nodes become runs of text laid out on the current line, with white space
+    /// collapsed across the nodes, and text that is only collapsed white space has no box.
+    #[test]
+    fn text_runs() {
+        let text = |t: &str| Node::new(NodeType::Text(String::from(t)), vec![]);
+        let elem = ElementData::new(String::from("div"), HashMap::new());
+        let node = Node::new(NodeType::Element(elem), vec![text("  hello \n  world "), text(" \n "), text("again")]);
+        let sheets = vec![CssParser::new("div { display: block; font-size: 10px; }").parse_stylesheet()];
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 400.0;
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::new(&viewport));
+        let root = layout_tree(&styled, viewport).unwrap();
+
+        assert_eq!(2, root.children.len());
+        assert!(matches!(*root.children[0].box_type(), BoxType::TextRun(ref t) if t == "hello world "));
+        assert!(matches!(*root.children[1].box_type(), BoxType::TextRun(ref t) if t == "again"));
+        assert_eq!(60.0, root.children[0].dimensions.content.width);
+        assert_eq!(12.0, root.children[0].dimensions.content.height);
+        assert_eq!(60.0, root.children[1].dimensions.content.x);
+        assert_eq!(0.0, root.children[1].dimensions.content.y);
+    }
+
+    /// Test elements generate the box of their display type, display: contents replaces its
+    /// box with its children's boxes, and display: none generates no boxes.
+    #[test]
+    fn display_box_types() {
+        let elem = |tag: &str, children| Node::new(NodeType::Element(ElementData::new(String::from(tag), HashMap::new())), children);
+        let text = Node::new(NodeType::Text(String::from("x")), vec![]);
+        let node = elem("div", vec![
+            elem("li", vec![]),
+            elem("span", vec![elem("em", vec![]), text]),
+            elem("table", vec![elem("td", vec![])]),
+            elem("section", vec![elem("p", vec![])]),
+            elem("b", vec![]),
+            elem("i", vec![]),
+        ]);
+        let sheets = vec![CssParser::new("div { display: block; } li { display: list-item; }
+            span { display: contents; } em { display: inline-flex; } table { display: table; }
+            td { display: table-cell; } section { display: none; } b { display: inline grid; }
+            i { display: flow-root; }").parse_stylesheet()];
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 400.0;
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::new(&viewport));
+        let root = layout_tree(&styled, viewport).unwrap();
+        let types = |b: &LayoutBox| b.children.iter().map(|c| format!("{:?}", c.box_type())).collect::<Vec<String>>();
+
+        assert_eq!(vec!["list-item", "anonymous", "table", "anonymous", "flow-root"], types(&root));
+        assert_eq!(vec!["inline-flex", "text"], types(&root.children[1]));
+        assert_eq!(vec!["inline-grid"], types(&root.children[3]));
+        assert_eq!(vec!["table-cell"], types(&root.children[2]));
+        assert!(root.children[1].children[0].box_type().is_atomic_inline());
+        assert!(root.children[2].box_type().is_block_level());
+    }
+
+    /// Test runs of inline-level children among block-level ones are wrapped in anonymous
+    /// blocks stacked with the blocks, and a root with display: none has no box.
+    #[test]
+    fn anonymous_blocks() {
+        let elem = |tag: &str, children| Node::new(NodeType::Element(ElementData::new(String::from(tag), HashMap::new())), children);
+        let text = |t: &str| Node::new(NodeType::Text(String::from(t)), vec![]);
+        let node = elem("div", vec![text("ab"), elem("span", vec![]), elem("p", vec![]), text("cd"), elem("em", vec![text("ef")])]);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 400.0;
+        let media = MediaEnvironment::new(&viewport);
+        let sheets = vec![CssParser::new("div { display: block; font-size: 10px; } span { display: inline-block; width: 30px; }
+            p { display: block; height: 20px; } em { display: inline; }").parse_stylesheet()];
+        let styled = StyledNode::new(&node, &sheets, &media);
+        let root = layout_tree(&styled, viewport).unwrap();
+        let types = |b: &LayoutBox| b.children.iter().map(|c| format!("{:?}", c.box_type())).collect::<Vec<String>>();
+
+        assert_eq!(vec!["anonymous", "block", "anonymous"], types(&root));
+        assert_eq!(vec!["text", "inline-block"], types(&root.children[0]));
+        assert_eq!(vec!["text", "inline"], types(&root.children[2]));
+        assert_eq!(400.0, root.children[0].dimensions.content.width);
+        assert_eq!(12.0, root.children[0].dimensions.content.height);
+        assert_eq!(10.0, root.children[0].children[1].dimensions.content.x);
+        assert_eq!(22.0, root.children[1].dimensions.content.y);
+        assert_eq!(52.0, root.children[2].dimensions.content.y);
+
+        let inline_only = elem("div", vec![text("ab"), elem("span", vec![])]);
+        let styled = StyledNode::new(&inline_only, &sheets, &media);
+        assert_eq!(vec!["text", "inline-block"], types(&layout_tree(&styled, viewport).unwrap()));
+
+        let sheets = vec![CssParser::new("div { display: none; }").parse_stylesheet()];
+        assert!(layout_tree(&StyledNode::new(&node, &sheets, &media), viewport).is_none());
+    }
+
+    /// Test a block whose last child is an inline box is as tall as its line, so the next
+    /// block is placed below the text.
+    #[test]
+    fn inline_line_height() {
+        let node = HtmlParser::new("<div><p>hello <b>world</b></p><p>again</p></div>").parse_nodes().remove(0);
+        let sheets = vec![CssParser::new("div, p { display: block; } p { margin-top: 0; margin-bottom: 0; }
+            b { display: inline; font-size: 20px; }").parse_stylesheet()];
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 400.0;
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::new(&viewport));
+        let root = layout_tree(&styled, viewport).unwrap();
+
+        // the b's 20px text is 24px high, taller than the p's own text
+        assert_eq!(24.0, root.children[0].dimensions.content.height);
+        assert_eq!(24.0, root.children[1].dimensions.content.y - root.dimensions.content.y);
+        assert_eq!(19.2, root.children[1].dimensions.content.height);
+    }
+
+    /// Test the space between text and an inline element is kept once, the line's leading
+    /// and trailing spaces are removed, and inline boxes continue the line.
+    #[test]
+    fn text_white_space_inline() {
+        fn text(b: &LayoutBox) -> String {
+            match *b.box_type() {
+                BoxType::TextRun(ref t) => t.clone(),
+                _ => b.children.iter().map(text).collect(),
+            }
+        }
+        let elem = |tag: &str, children| Node::new(NodeType::Element(ElementData::new(String::from(tag), HashMap::new())), children);
+        let t = |t: &str| Node::new(NodeType::Text(String::from(t)), vec![]);
+        let node = elem("p", vec![t(" hello "), elem("b", vec![t(" world")]), t(" "), elem("i", vec![t(" again ")]), t(" ")]);
+        let sheets = vec![CssParser::new("p { display: block; } b, i { display: inline; }").parse_stylesheet()];
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 400.0;
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::new(&viewport));
+        let root = layout_tree(&styled, viewport).unwrap();
+
+        let x = |b: &LayoutBox| b.dimensions.content.x - root.dimensions.content.x;
+
+        assert_eq!("hello world again", text(&root));
+        assert_eq!(4, root.children.len());
+        // 16px text is 8px per character
+        assert_eq!(48.0, x(&root.children[1]));
+        assert_eq!(48.0, x(&root.children[1].children[0]));
+        assert_eq!(40.0, root.children[1].dimensions.content.width);
+        assert_eq!(88.0, x(&root.children[2]));
+        assert_eq!(96.0, x(&root.children[3].children[0]));
+
+        let node = HtmlParser::new("<p>hello <b>world</b></p>").parse_nodes().remove(0);
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::new(&viewport));
+        let root = layout_tree(&styled, viewport).unwrap();
+
+        assert_eq!("hello world", text(&root));
+        assert_eq!(48.0, root.children[1].children[0].dimensions.content.x - root.dimensions.content.x);
+    }
+
+    /// Test percentages of box edges resolve against the containing block's width.
+    #[test]
+    fn units_percentages() {
+        let (_, d, _) = layout_nested("section { display: block; width: 400px; }
+            div { display: block; padding-left: 10%; margin-top: 5%; width: calc(50% + 1em); }");
+
+        assert_eq!(40.0, d.padding.left);
+        assert_eq!(20.0, d.margin.top);
+        assert_eq!(216.0, d.content.width);
+    }
 }
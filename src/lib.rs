@@ -8,6 +8,14 @@ pub mod dom;
 pub mod html_parse;
 pub mod css;
 pub mod css_parse;
+pub mod properties;
+pub mod computed;
+pub mod media;
+pub mod loader;
+pub mod matching;
+pub mod invalidation;
+pub mod coverage;
+pub mod pool;
 pub mod style;
 pub mod layout;
 pub mod render;
@@ -0,0 +1,307 @@
+//! The `loader` module fetches stylesheets and other resources a document refers to.
+
+use css::Stylesheet;
+use css_parse::{parse_media_query_list, CssParser};
+use dom::{Node, NodeType};
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Resolves and fetches resources, ex. from the filesystem.
+pub trait ResourceLoader {
+    /// Returns the location of a url relative to the location of the resource referring to it.
+    fn resolve(&self, url: &str, base: &str) -> String;
+
+    /// Returns the contents of the resource at a resolved location.
+    fn load(&self, location: &str) -> io::Result<String>;
+}
+
+/// Loads resources from the filesystem, urls are paths relative to the referring file.
+pub struct FileLoader;
+
+impl ResourceLoader for FileLoader {
+    fn resolve(&self, url: &str, base: &str) -> String {
+        let dir = Path::new(base).parent().unwrap_or_else(|| Path::new(""));
+        dir.join(url).to_string_lossy().into_owned()
+    }
+
+    fn load(&self, location: &str) -> io::Result<String> {
+        let mut contents = String::new();
+        File::open(location)?.read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+}
+
+/// Loads and parses the stylesheet at a location, splicing in the rules of its imports.
+///
+/// location: the resolved location of the stylesheet.
+/// loader: the loader used for the stylesheet and its imports.
+pub fn load_stylesheet(location: &str, loader: &dyn ResourceLoader) -> io::Result<Stylesheet> {
+    let css = loader.load(location)?;
+    let mut stylesheet = CssParser::new(&css).parse_stylesheet();
+
+    set_url(&mut stylesheet, location);
+    resolve_imports(&mut stylesheet, location, loader);
+    Ok(stylesheet)
+}
+
+/// Replaces the imports of a stylesheet with the rules of the imported stylesheets.
+///
+/// Imported rules come before the stylesheet's own rules, in import order, and only apply
+/// when the import's media matches. Imports that fail to load or that import a stylesheet
+/// currently being imported are ignored.
+///
+/// stylesheet: the stylesheet to resolve the imports of.
+/// location: the location of the stylesheet, imports are relative to it.
+/// loader: the loader used for the imports.
+pub fn resolve_imports(stylesheet: &mut Stylesheet, location: &str, loader: &dyn ResourceLoader) {
+    let mut stack = vec![String::from(location)];
+    splice_imports(stylesheet, loader, &mut stack);
+}
+
+/// Recursively splices imports, the stack holds the locations of the stylesheets being imported.
+fn splice_imports(stylesheet: &mut Stylesheet, loader: &dyn ResourceLoader, stack: &mut Vec<String>) {
+    let mut rules = Vec::new();
+    let base = stack[stack.len() - 1].clone();
+
+    for import in stylesheet.imports.drain(..) {
+        let location = loader.resolve(&import.url, &base);
+
+        // an import cycle would never finish loading
+        if stack.contains(&location) {
+            continue;
+        }
+
+        let css = match loader.load(&location) {
+            Ok(css) => css,
+            Err(_) => continue,
+        };
+        let mut imported = CssParser::new(&css).parse_stylesheet();
+        set_url(&mut imported, &location);
+
+        stack.push(location);
+        splice_imports(&mut imported, loader, stack);
+        stack.pop();
+
+        for mut rule in imported.rules {
+            if !import.media.queries.is_empty() {
+                rule.media.insert(0, import.media.clone());
+            }
+            rules.push(rule);
+        }
+    }
+
+    rules.append(&mut stylesheet.rules);
+    stylesheet.rules = rules;
+}
+
+/// Collects the author stylesheets of a document from its style elements and the stylesheets
+/// its link elements refer to, in document order.
+///
+/// A media attribute limits a stylesheet to the media it matches. Linked stylesheets that
+/// fail to load are skipped.
+///
+/// root: the root node of the document.
+/// location: the location of the document, links and imports are relative to it.
+/// loader: the loader used for linked and imported stylesheets.
+pub fn collect_stylesheets(root: &Node, location: &str, loader: &dyn ResourceLoader) -> Vec<Stylesheet> {
+    let mut stylesheets = Vec::new();
+    collect_node(root, location, loader, &mut stylesheets);
+    stylesheets
+}
+
+/// Collects the stylesheets of a node and its descendants.
+fn collect_node(node: &Node, location: &str, loader: &dyn ResourceLoader, stylesheets: &mut Vec<Stylesheet>) {
+    if let NodeType::Element(ref elem) = node.node_type {
+        let stylesheet = match &elem.tag_name[..] {
+            "style" => {
+                let css = node.children.iter()
+                    .filter_map(|child| match child.node_type {
+                        NodeType::Text(ref text) => Some(&text[..]),
+                        _ => None,
+                    })
+                    .collect::<String>();
+                let mut stylesheet = CssParser::new(&css).parse_stylesheet();
+
+                // imports in a style element are relative to the document
+                set_url(&mut stylesheet, location);
+                resolve_imports(&mut stylesheet, location, loader);
+                Some(stylesheet)
+            },
+            "link" if is_stylesheet_link(elem.get_attribute("rel")) => {
+                elem.get_attribute("href")
+                    .and_then(|href| load_stylesheet(&loader.resolve(href, location), loader).ok())
+            },
+            _ => None,
+        };
+
+        if let Some(mut stylesheet) = stylesheet {
+            if let Some(media) = elem.get_attribute("media") {
+                let media = parse_media_query_list(media);
+                if !media.queries.is_empty() {
+                    for rule in &mut stylesheet.rules {
+                        rule.media.insert(0, media.clone());
+                    }
+                }
+            }
+            stylesheets.push(stylesheet);
+        }
+    }
+
+    for child in &node.children {
+        collect_node(child, location, loader, stylesheets);
+    }
+}
+
+/// Records the location a stylesheet was loaded from in the source locations of its rules.
+/// The lines of a style element's rules count from the start of the element's text.
+fn set_url(stylesheet: &mut Stylesheet, location: &str) {
+    for rule in &mut stylesheet.rules {
+        rule.location.url = Some(String::from(location));
+    }
+}
+
+/// Returns true if a link's rel attribute makes it a stylesheet link, alternate stylesheets
+/// aren't applied.
+fn is_stylesheet_link(rel: Option<&String>) -> bool {
+    let rel = rel.map_or_else(String::new, |r| r.to_lowercase());
+    let mut types = rel.split_whitespace();
+
+    types.clone().any(|t| t == "stylesheet") && !types.any(|t| t == "alternate")
+}
+
+/// Tests ----------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css::{Declaration, Unit, Value};
+    use html_parse::HtmlParser;
+    use std::collections::HashMap;
+
+    /// Serves stylesheets from memory, locations are the urls themselves.
+    struct MemoryLoader(HashMap<&'static str, &'static str>);
+
+    impl ResourceLoader for MemoryLoader {
+        fn resolve(&self, url: &str, _base: &str) -> String {
+            String::from(url)
+        }
+
+        fn load(&self, location: &str) -> io::Result<String> {
+            match self.0.get(location) {
+                Some(css) => Ok(String::from(*css)),
+                None => Err(io::Error::new(io::ErrorKind::NotFound, location.to_string())),
+            }
+        }
+    }
+
+    fn width(stylesheet: &Stylesheet) -> Vec<f32> {
+        stylesheet.rules.iter()
+            .map(|r| match r.declarations[0].value {
+                Value::Length(n, _) => n,
+                _ => -1.0,
+            })
+            .collect()
+    }
+
+    /// Test imported rules come first, in import order, with the import's media.
+    #[test]
+    fn import_order() {
+        let mut files = HashMap::new();
+        files.insert("main.css", "@import url(a.css); @import 'b.css' print; p { width: 3px; }");
+        files.insert("a.css", "@import \"c.css\"; p { width: 1px; }");
+        files.insert("b.css", "p { width: 2px; }");
+        files.insert("c.css", "p { width: 0px; }");
+        let ss = load_stylesheet("main.css", &MemoryLoader(files)).unwrap();
+
+        assert_eq!(vec![0.0, 1.0, 2.0, 3.0], width(&ss));
+        assert!(ss.imports.is_empty());
+        assert!(ss.rules[0].media.is_empty());
+        assert_eq!(vec![parse_media_query_list("print")], ss.rules[2].media);
+        assert_eq!(vec![Declaration::new(String::from("width"), Value::Length(3.0, Unit::Px))],
+            ss.rules[3].declarations);
+    }
+
+    /// Test rules record the stylesheet they were loaded from.
+    #[test]
+    fn import_locations() {
+        let mut files = HashMap::new();
+        files.insert("main.css", "@import 'a.css';\np { width: 1px; }");
+        files.insert("a.css", "\n  p { width: 0px; }");
+        let ss = load_stylesheet("main.css", &MemoryLoader(files)).unwrap();
+        let locations = ss.rules.iter().map(|r| r.location.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(vec!["a.css:2:3", "main.css:2:1"], locations);
+    }
+
+    /// Test import cycles and missing stylesheets are skipped.
+    #[test]
+    fn import_cycles() {
+        let mut files = HashMap::new();
+        files.insert("main.css", "@import 'a.css'; @import 'missing.css'; p { width: 2px; }");
+        files.insert("a.css", "@import 'main.css'; @import 'a.css'; p { width: 1px; }");
+        let ss = load_stylesheet("main.css", &MemoryLoader(files)).unwrap();
+
+        assert_eq!(vec![1.0, 2.0], width(&ss));
+    }
+
+    /// Test the same stylesheet can be imported twice without being a cycle.
+    #[test]
+    fn import_twice() {
+        let mut files = HashMap::new();
+        files.insert("main.css", "@import 'a.css'; @import 'b.css';");
+        files.insert("a.css", "@import 'c.css';");
+        files.insert("b.css", "@import 'c.css';");
+        files.insert("c.css", "p { width: 1px; }");
+        let ss = load_stylesheet("main.css", &MemoryLoader(files)).unwrap();
+
+        assert_eq!(vec![1.0, 1.0], width(&ss));
+    }
+
+    /// Test file urls resolve relative to the importing file.
+    #[test]
+    fn file_resolve() {
+        assert_eq!(Path::new("css/base/reset.css").to_string_lossy(),
+            FileLoader.resolve("base/reset.css", "css/main.css"));
+        assert_eq!(Path::new("reset.css").to_string_lossy(), FileLoader.resolve("reset.css", "main.css"));
+        assert!(FileLoader.load("tests/parserTestFiles/blockTypeTest.css").is_ok());
+    }
+
+    /// Test style and stylesheet link elements are collected in document order.
+    #[test]
+    fn collect_order() {
+        let mut files = HashMap::new();
+        files.insert("a.css", "p { width: 1px; }");
+        files.insert("b.css", "@import 'c.css'; p { width: 3px; }");
+        files.insert("c.css", "p { width: 2px; }");
+        files.insert("d.css", "p { width: 9px; }");
+        let html = "<html><head>
+            <link rel=\"stylesheet\" href=\"a.css\"></link>
+            <link rel=\"alternate stylesheet\" href=\"d.css\"></link>
+            <link rel=\"icon\" href=\"d.css\"></link>
+            <link rel=\"stylesheet\" href=\"missing.css\"></link>
+            <style>@import 'c.css'; p { width: 4px; }</style>
+          </head><body>
+            <link rel=\"Stylesheet\" href=\"b.css\" media=\"print\"></link>
+          </body></html>";
+        let nodes = HtmlParser::new(html).parse_nodes();
+        let sheets = collect_stylesheets(&nodes[0], "index.html", &MemoryLoader(files));
+
+        assert_eq!(3, sheets.len());
+        assert_eq!(vec![1.0], width(&sheets[0]));
+        assert_eq!(vec![2.0, 4.0], width(&sheets[1]));
+        assert_eq!(vec![2.0, 3.0], width(&sheets[2]));
+        assert!(sheets[1].rules[1].media.is_empty());
+        assert_eq!(vec![parse_media_query_list("print")], sheets[2].rules[1].media);
+    }
+
+    /// Test linked stylesheets resolve relative to the document on the filesystem.
+    #[test]
+    fn collect_files() {
+        let nodes = HtmlParser::new("<link rel=\"stylesheet\" href=\"blockTypeTest.css\"></link>").parse_nodes();
+        let sheets = collect_stylesheets(&nodes[0], "tests/parserTestFiles/blockTypeTest.html", &FileLoader);
+
+        assert_eq!(1, sheets.len());
+        assert!(!sheets[0].rules.is_empty());
+    }
+}
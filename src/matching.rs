@@ -0,0 +1,409 @@
+//! The `matching` module finds the rules whose selectors match an element, using an index of
+//! the rules and a filter of the element's ancestors to skip rules that can't match.
+
+use css::{Origin, Rule, Selector, SimpleSelector, Specificity, Stylesheet};
+use dom::ElementData;
+use media::MediaEnvironment;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// The number of counters in the ancestor filter.
+const FILTER_SIZE: usize = 4096;
+
+/// The rules of a set of stylesheets that apply to a medium, bucketed by the id, class or
+/// tag name of each selector's rightmost compound, so an element is only matched against
+/// the rules that could apply to it.
+pub struct RuleIndex<'s> {
+    ids: HashMap<&'s str, Vec<IndexedSelector<'s>>>,
+    classes: HashMap<&'s str, Vec<IndexedSelector<'s>>>,
+    tags: HashMap<&'s str, Vec<IndexedSelector<'s>>>,
+    universal: Vec<IndexedSelector<'s>>,
+}
+
+/// A selector of an indexed rule.
+struct IndexedSelector<'s> {
+    selector: &'s Selector,
+    rule: &'s Rule,
+    origin: Origin,
+    order: usize, // the position of the rule in the cascade
+    specificity: Specificity,
+    ancestor_hashes: Vec<u32>, // the tag names, ids and classes the ancestors need
+}
+
+/// A rule that matched an element, with its matching selector of the highest specificity.
+pub struct MatchedRule<'s> {
+    pub rule: &'s Rule,
+    pub selector: &'s Selector,
+    pub origin: Origin,
+    pub order: usize,
+    pub specificity: Specificity,
+}
+
+/// The ancestors of the element being matched, from the root down, with a counting bloom
+/// filter of their tag names, ids and classes. The filter rules out most selectors with
+/// ancestors that aren't there without walking the ancestors.
+#[derive(Clone)]
+pub struct Ancestors<'a> {
+    elements: Vec<(&'a ElementData, Vec<u32>)>, // and the hashes added to the filter
+    filter: Vec<u8>,
+}
+
+impl<'s> RuleIndex<'s> {
+    /// Constructs the index of the rules that apply to a medium, in cascade order.
+    ///
+    /// sheets: the stylesheets, in the order they cascade in.
+    /// media: the device @media rules are evaluated against.
+    pub fn new<I>(sheets: I, media: &MediaEnvironment) -> RuleIndex<'s>
+        where I : IntoIterator<Item = &'s Stylesheet> {
+        RuleIndex::from_rules(flatten(sheets).filter(|&(_, r)| r.media.iter().all(|m| m.matches(media))))
+    }
+
+    /// Constructs the index of every rule, whatever its @media conditions, in cascade order.
+    ///
+    /// sheets: the stylesheets, in the order they cascade in.
+    pub fn all_media<I>(sheets: I) -> RuleIndex<'s>
+        where I : IntoIterator<Item = &'s Stylesheet> {
+        RuleIndex::from_rules(flatten(sheets))
+    }
+
+    /// Constructs the index of rules in cascade order, with the origin of their stylesheet.
+    fn from_rules<I>(rules: I) -> RuleIndex<'s>
+        where I : Iterator<Item = (Origin, &'s Rule)> {
+        let mut index = RuleIndex {
+            ids: HashMap::new(),
+            classes: HashMap::new(),
+            tags: HashMap::new(),
+            universal: Vec::new(),
+        };
+
+        for (order, (origin, rule)) in rules.enumerate() {
+            for selector in &rule.selectors {
+                let subject = match selector.subject() {
+                    Some(subject) => subject,
+                    None => continue,
+                };
+                let indexed = IndexedSelector {
+                    selector,
+                    rule,
+                    origin,
+                    order,
+                    specificity: selector.specificity(),
+                    ancestor_hashes: selector.simple[..selector.simple.len() - 1].iter()
+                        .flat_map(compound_hashes)
+                        .collect(),
+                };
+
+                // any one condition of the subject is enough to find the selector
+                if let Some(ref id) = subject.id {
+                    index.ids.entry(id).or_default().push(indexed);
+                } else if let Some(class) = subject.classes.first() {
+                    index.classes.entry(class).or_default().push(indexed);
+                } else if let Some(ref tag) = subject.tag_name {
+                    index.tags.entry(tag).or_default().push(indexed);
+                } else {
+                    index.universal.push(indexed);
+                }
+            }
+        }
+
+        index
+    }
+
+    /// Returns the rules that match an element, in cascade order.
+    ///
+    /// elem: the element to match.
+    /// ancestors: the ancestors of the element.
+    pub fn matching_rules(&self, elem: &ElementData, ancestors: &Ancestors) -> Vec<MatchedRule<'s>> {
+        let mut matched: Vec<MatchedRule<'s>> = Vec::new();
+
+        for candidate in self.matches(elem, ancestors) {
+            // a rule applies with the highest specificity of its matching selectors
+            match matched.iter_mut().find(|m| m.order == candidate.order) {
+                Some(m) => if candidate.specificity > m.specificity {
+                    m.selector = candidate.selector;
+                    m.specificity = candidate.specificity;
+                },
+                None => matched.push(MatchedRule {
+                    rule: candidate.rule,
+                    selector: candidate.selector,
+                    origin: candidate.origin,
+                    order: candidate.order,
+                    specificity: candidate.specificity,
+                }),
+            }
+        }
+
+        matched.sort_by_key(|m| m.order);
+        matched
+    }
+
+    /// Returns the selectors that match an element, in no particular order.
+    ///
+    /// elem: the element to match.
+    /// ancestors: the ancestors of the element.
+    pub fn matching_selectors(&self, elem: &ElementData, ancestors: &Ancestors) -> Vec<&'s Selector> {
+        self.matches(elem, ancestors).map(|indexed| indexed.selector).collect()
+    }
+
+    /// Returns the indexed selectors that match an element.
+    fn matches<'i>(&'i self, elem: &'i ElementData, ancestors: &'i Ancestors)
+        -> impl Iterator<Item = &'i IndexedSelector<'s>> {
+        self.candidates(elem).filter(move |candidate| {
+            candidate.ancestor_hashes.iter().all(|&h| ancestors.may_contain(h)) &&
+                selector_matches(elem, candidate.selector, &ancestors.elements)
+        })
+    }
+
+    /// Returns the selectors in the buckets of an element's id, classes and tag name, and
+    /// the universal selectors.
+    fn candidates<'i>(&'i self, elem: &ElementData) -> impl Iterator<Item = &'i IndexedSelector<'s>> {
+        let ids = elem.get_id().and_then(|id| self.ids.get(&id[..]));
+        let classes = elem.get_classes().into_iter()
+            .filter_map(|class| self.classes.get(class))
+            .collect::<Vec<_>>();
+        let tags = self.tags.get(&elem.tag_name[..]);
+
+        ids.into_iter().chain(classes).chain(tags)
+            .flat_map(|bucket| bucket.iter())
+            .chain(self.universal.iter())
+    }
+}
+
+impl<'a> Ancestors<'a> {
+    /// Constructs the ancestors of the root, which has none.
+    pub fn new() -> Ancestors<'a> {
+        Ancestors {
+            elements: Vec::new(),
+            filter: vec![0; FILTER_SIZE],
+        }
+    }
+
+    /// Adds an element below the current ancestors, before matching its children.
+    pub fn push(&mut self, elem: &'a ElementData) {
+        let hashes = element_hashes(elem);
+
+        for &hash in &hashes {
+            for i in filter_indices(hash) {
+                self.filter[i] = self.filter[i].saturating_add(1);
+            }
+        }
+        self.elements.push((elem, hashes));
+    }
+
+    /// Removes the last element pushed, after matching its children.
+    pub fn pop(&mut self) {
+        if let Some((_, hashes)) = self.elements.pop() {
+            for &hash in &hashes {
+                for i in filter_indices(hash) {
+                    // a saturated counter no longer knows its count, so it stays set
+                    if self.filter[i] != u8::MAX {
+                        self.filter[i] -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns false if no ancestor has the hashed tag name, id or class. A true result
+    /// can be a false positive.
+    fn may_contain(&self, hash: u32) -> bool {
+        filter_indices(hash).iter().all(|&i| self.filter[i] > 0)
+    }
+}
+impl<'a> Default for Ancestors<'a> {
+    fn default() -> Self {
+        Ancestors::new()
+    }
+}
+
+/// Returns the rules of stylesheets in cascade order, with the origin of their stylesheet.
+fn flatten<'s, I>(sheets: I) -> impl Iterator<Item = (Origin, &'s Rule)>
+    where I : IntoIterator<Item = &'s Stylesheet> {
+    sheets.into_iter().flat_map(|ss| ss.rules.iter().map(move |r| (ss.origin, r)))
+}
+
+/// Returns true if a selector matches an element with the given ancestors.
+///
+/// elem: The element data of the dom node to match.
+/// sel: The selector to match.
+/// ancestors: The ancestors of the element, from the root down.
+fn selector_matches(elem: &ElementData, sel: &Selector, ancestors: &[(&ElementData, Vec<u32>)]) -> bool {
+    match sel.subject() {
+        Some(subject) => simple_matches(elem, subject) && ancestors_match(sel, sel.simple.len() - 1, ancestors),
+        None => false,
+    }
+}
+
+/// Returns true if the compounds of a selector left of the given one match the ancestors.
+/// A descendant combinator tries every ancestor that matches, nearest first.
+fn ancestors_match(sel: &Selector, matched: usize, ancestors: &[(&ElementData, Vec<u32>)]) -> bool {
+    if matched == 0 {
+        return true;
+    }
+
+    let simple = &sel.simple[matched - 1];
+
+    match sel.combinators.get(matched - 1) {
+        Some(&'>') => match ancestors.split_last() {
+            Some((&(parent, _), rest)) => simple_matches(parent, simple) && ancestors_match(sel, matched - 1, rest),
+            None => false,
+        },
+        _ => (0..ancestors.len()).rev().any(|i| {
+            simple_matches(ancestors[i].0, simple) && ancestors_match(sel, matched - 1, &ancestors[..i])
+        }),
+    }
+}
+
+/// Returns true if a compound selector matches an element on its own.
+pub fn simple_matches(elem: &ElementData, simple: &SimpleSelector) -> bool {
+    let classes = elem.get_classes();
+
+    simple.tag_name.as_ref().is_none_or(|t| *t == elem.tag_name)
+        && simple.id.as_ref().is_none_or(|id| elem.get_id() == Some(id))
+        && simple.classes.iter().all(|class| classes.contains(&class[..]))
+}
+
+/// Returns the hashes of an element's tag name, id and classes.
+fn element_hashes(elem: &ElementData) -> Vec<u32> {
+    let mut hashes = vec![hash('t', &elem.tag_name)];
+
+    if let Some(id) = elem.get_id() {
+        hashes.push(hash('#', id));
+    }
+    hashes.extend(elem.get_classes().into_iter().map(|class| hash('.', class)));
+    hashes
+}
+
+/// Returns the hashes of the tag name, id and classes a compound selector requires.
+fn compound_hashes(simple: &SimpleSelector) -> Vec<u32> {
+    let mut hashes = Vec::new();
+
+    if let Some(ref tag) = simple.tag_name {
+        hashes.push(hash('t', tag));
+    }
+    if let Some(ref id) = simple.id {
+        hashes.push(hash('#', id));
+    }
+    hashes.extend(simple.classes.iter().map(|class| hash('.', class)));
+    hashes
+}
+
+/// Hashes a tag name, id or class, told apart by their kind.
+fn hash(kind: char, name: &str) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    kind.hash(&mut hasher);
+    name.hash(&mut hasher);
+    hasher.finish() as u32
+}
+
+/// Returns the counters of the ancestor filter a hash sets.
+fn filter_indices(hash: u32) -> [usize; 2] {
+    [hash as usize % FILTER_SIZE, (hash >> 16) as usize % FILTER_SIZE]
+}
+
+/// Tests ----------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css_parse::CssParser;
+    use std::collections::HashMap as AttrMap;
+
+    fn elem(tag: &str, id: Option<&str>, classes: &str) -> ElementData {
+        let mut attrs = AttrMap::new();
+        if let Some(id) = id {
+            attrs.insert(String::from("id"), String::from(id));
+        }
+        attrs.insert(String::from("class"), String::from(classes));
+        ElementData::new(String::from(tag), attrs)
+    }
+
+    /// The orders of the rules matching an element with the given ancestors.
+    fn matches(css: &str, target: &ElementData, ancestors: &[&ElementData]) -> Vec<usize> {
+        let sheets = vec![CssParser::new(css).parse_stylesheet()];
+        let index = RuleIndex::new(&sheets, &MediaEnvironment::default());
+        let mut stack = Ancestors::new();
+        for &a in ancestors {
+            stack.push(a);
+        }
+
+        index.matching_rules(target, &stack).iter().map(|m| m.order).collect()
+    }
+
+    /// Test selectors are bucketed by one condition of their rightmost compound.
+    #[test]
+    fn index_buckets() {
+        let sheets = vec![CssParser::new("#a.b p { color: red; } .b.c, p { color: red; } div > p.c { color: red; } * { color: red; } .d { color: red; }").parse_stylesheet()];
+        let index = RuleIndex::new(&sheets, &MediaEnvironment::default());
+
+        assert!(index.ids.is_empty());
+        assert_eq!(1, index.classes["b"].len());
+        assert_eq!(1, index.classes["c"].len());
+        assert_eq!(2, index.tags["p"].len());
+        assert_eq!(1, index.universal.len());
+        assert_eq!(2, index.tags["p"][0].ancestor_hashes.len());
+        assert_eq!(1, index.classes["c"][0].ancestor_hashes.len());
+        assert_eq!(3, index.candidates(&elem("p", None, "x")).count());
+        assert_eq!(4, index.candidates(&elem("p", None, "c")).count());
+        assert_eq!(1, index.candidates(&elem("div", None, "")).count());
+    }
+
+    /// Test compound, descendant and child selectors against an element's ancestors.
+    #[test]
+    fn match_combinators() {
+        let html = elem("html", None, "");
+        let main = elem("div", Some("main"), "page");
+        let section = elem("section", None, "");
+        let p = elem("p", None, "note");
+        let css = "p { color: red; } div p { color: red; } div > p { color: red; } section > p.note { color: red; } #main section p { color: red; }
+            .page > section > .note { color: red; } html > p { color: red; } span p { color: red; } * { color: red; } p.other { color: red; } html div > p { color: red; }";
+
+        assert_eq!(vec![0, 1, 3, 4, 5, 8], matches(css, &p, &[&html, &main, &section]));
+        assert_eq!(vec![0, 1, 2, 8, 10], matches(css, &p, &[&html, &main]));
+        assert_eq!(vec![0, 6, 8], matches(css, &p, &[&html]));
+    }
+
+    /// Test a descendant combinator backtracks to further ancestors when the nearest one
+    /// that matches doesn't lead to a match.
+    #[test]
+    fn match_backtracking() {
+        let outer = elem("div", None, "a");
+        let inner = elem("div", None, "");
+        let span = elem("span", None, "");
+        let p = elem("p", None, "");
+        let ancestors = [&outer, &inner, &inner, &span];
+
+        assert_eq!(vec![0], matches(".a > div p { color: red; }", &p, &ancestors));
+        assert_eq!(vec![0], matches(".a div > span > p { color: red; }", &p, &ancestors));
+        assert!(matches(".a > span p { color: red; }", &p, &ancestors).is_empty());
+        assert!(matches(".a > div > div > div p { color: red; }", &p, &ancestors).is_empty());
+    }
+
+    /// Test the ancestor filter forgets popped ancestors, and a rule matches once with its
+    /// most specific selector.
+    #[test]
+    fn ancestor_filter() {
+        let div = elem("div", Some("main"), "a b");
+        let mut ancestors = Ancestors::new();
+
+        ancestors.push(&div);
+        assert!(ancestors.may_contain(hash('.', "a")));
+        assert!(ancestors.may_contain(hash('#', "main")));
+        ancestors.push(&div);
+        ancestors.pop();
+        assert!(ancestors.may_contain(hash('t', "div")));
+        ancestors.pop();
+        assert!(!ancestors.may_contain(hash('.', "a")));
+        assert!(ancestors.filter.iter().all(|&c| c == 0));
+
+        let sheets = vec![CssParser::new("p, div p.x, #main p { color: red; }").parse_stylesheet()];
+        let index = RuleIndex::new(&sheets, &MediaEnvironment::default());
+        ancestors.push(&div);
+        let matched = index.matching_rules(&elem("p", None, "x"), &ancestors);
+
+        assert_eq!(1, matched.len());
+        assert_eq!(Specificity(1, 0, 1), matched[0].specificity);
+        assert_eq!("#main p", format!("{:?}", matched[0].selector));
+    }
+}
@@ -0,0 +1,316 @@
+//! The `media` module describes media queries and evaluates them against the device being
+//! rendered to.
+
+use css::{Unit, Value};
+use layout::Dimensions;
+
+use std::fmt;
+
+/// A comma separated list of media queries, matching if any of its queries match.
+/// An empty list matches all media.
+#[derive(PartialEq, Clone, Default)]
+pub struct MediaQueryList {
+    pub queries: Vec<MediaQuery>,
+}
+
+/// A single media query, ex. "not screen and (max-width: 600px)".
+#[derive(PartialEq, Clone)]
+pub struct MediaQuery {
+    pub negated: bool,
+    pub media_type: MediaType,
+    pub features: Vec<MediaFeature>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MediaType {
+    All,
+    Screen,
+    Print,
+    Unknown, // a media type that never matches, ex. "tv"
+}
+
+/// A media feature test, all the features of a query have to match.
+#[derive(PartialEq, Clone)]
+pub enum MediaFeature {
+    Width(Range, Value),
+    Height(Range, Value),
+    Orientation(Orientation),
+    PrefersColorScheme(ColorScheme),
+}
+
+/// How a size feature compares to the device, from its min- or max- prefix.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Range {
+    Min,
+    Max,
+    Exact,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+/// The device media queries are evaluated against.
+#[derive(Clone, Copy, Debug)]
+pub struct MediaEnvironment {
+    pub media_type: MediaType,
+    pub width: f32,
+    pub height: f32,
+    pub color_scheme: ColorScheme,
+    pub font_size: f32, // the initial font size, used to resolve em and rem
+}
+
+impl MediaQueryList {
+    /// Constructs a new MediaQueryList.
+    pub fn new(queries: Vec<MediaQuery>) -> MediaQueryList {
+        MediaQueryList { queries }
+    }
+
+    /// Returns true if any query in the list matches the environment.
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        self.queries.is_empty() || self.queries.iter().any(|q| q.matches(env))
+    }
+}
+
+impl MediaQuery {
+    /// Constructs the query a malformed query is replaced with, "not all", which never matches.
+    pub fn not_all() -> MediaQuery {
+        MediaQuery {
+            negated: true,
+            media_type: MediaType::All,
+            features: Vec::new(),
+        }
+    }
+
+    /// Returns true if the query matches the environment.
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        let type_matches = match self.media_type {
+            MediaType::All => true,
+            MediaType::Unknown => false,
+            t => t == env.media_type,
+        };
+
+        let result = type_matches && self.features.iter().all(|f| f.matches(env));
+        result != self.negated
+    }
+}
+
+impl MediaType {
+    /// Returns the media type for a lowercase name.
+    pub fn from_name(name: &str) -> MediaType {
+        match name {
+            "all" => MediaType::All,
+            "screen" => MediaType::Screen,
+            "print" => MediaType::Print,
+            _ => MediaType::Unknown,
+        }
+    }
+}
+
+impl MediaFeature {
+    /// Returns true if the feature matches the environment.
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        match *self {
+            MediaFeature::Width(range, ref v) => compare(range, env.width, v, env),
+            MediaFeature::Height(range, ref v) => compare(range, env.height, v, env),
+            MediaFeature::Orientation(o) => o == env.orientation(),
+            MediaFeature::PrefersColorScheme(s) => s == env.color_scheme,
+        }
+    }
+}
+
+impl MediaEnvironment {
+    /// Constructs the environment of a screen showing the given viewport.
+    ///
+    /// viewport: the dimensions of the viewport being laid out into.
+    pub fn new(viewport: &Dimensions) -> MediaEnvironment {
+        MediaEnvironment {
+            media_type: MediaType::Screen,
+            width: viewport.content.width,
+            height: viewport.content.height,
+            color_scheme: ColorScheme::Light,
+            font_size: 16.0,
+        }
+    }
+
+    /// Returns the orientation of the viewport, a square viewport is portrait.
+    pub fn orientation(&self) -> Orientation {
+        if self.height >= self.width {
+            Orientation::Portrait
+        } else {
+            Orientation::Landscape
+        }
+    }
+}
+impl Default for MediaEnvironment {
+    fn default() -> Self {
+        MediaEnvironment::new(&Dimensions::default())
+    }
+}
+
+impl fmt::Debug for MediaQueryList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.queries.is_empty() {
+            return write!(f, "all");
+        }
+
+        let queries = self.queries.iter().map(|q| format!("{:?}", q)).collect::<Vec<String>>();
+        write!(f, "{}", queries.join(", "))
+    }
+}
+impl fmt::Debug for MediaQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+
+        if self.negated {
+            parts.push(String::from("not"));
+        }
+        parts.push(String::from(match self.media_type {
+            MediaType::All => "all",
+            MediaType::Screen => "screen",
+            MediaType::Print => "print",
+            MediaType::Unknown => "unknown",
+        }));
+        for feature in &self.features {
+            parts.push(format!("and {:?}", feature));
+        }
+
+        write!(f, "{}", parts.join(" "))
+    }
+}
+impl fmt::Debug for MediaFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefix = |range| match range {
+            Range::Min => "min-",
+            Range::Max => "max-",
+            Range::Exact => "",
+        };
+
+        match *self {
+            MediaFeature::Width(r, ref v) => write!(f, "({}width: {:?})", prefix(r), v),
+            MediaFeature::Height(r, ref v) => write!(f, "({}height: {:?})", prefix(r), v),
+            MediaFeature::Orientation(o) => write!(f, "(orientation: {})", format!("{:?}", o).to_lowercase()),
+            MediaFeature::PrefersColorScheme(s) => {
+                write!(f, "(prefers-color-scheme: {})", format!("{:?}", s).to_lowercase())
+            },
+        }
+    }
+}
+
+/// Compares a size of the environment against the length of a size feature.
+fn compare(range: Range, actual: f32, length: &Value, env: &MediaEnvironment) -> bool {
+    let expected = match to_px(length, env) {
+        Some(px) => px,
+        None => return false,
+    };
+
+    match range {
+        Range::Min => actual >= expected,
+        Range::Max => actual <= expected,
+        Range::Exact => actual == expected,
+    }
+}
+
+/// Resolves the length of a media feature in pixels.
+/// Relative units resolve against the initial font size and the viewport.
+fn to_px(length: &Value, env: &MediaEnvironment) -> Option<f32> {
+    let (n, unit) = match *length {
+        Value::Length(n, unit) => (n, unit),
+        _ => return None,
+    };
+
+    let px = match unit.absolute_px() {
+        Some(px) => n * px,
+        None => match unit {
+            Unit::Vw => n * env.width / 100.0,
+            Unit::Vh => n * env.height / 100.0,
+            Unit::Vmin => n * env.width.min(env.height) / 100.0,
+            Unit::Vmax => n * env.width.max(env.height) / 100.0,
+            Unit::Ex|Unit::Ch => n * env.font_size / 2.0,
+            _ => n * env.font_size, // em and rem
+        },
+    };
+
+    Some(px)
+}
+
+/// Tests ----------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An environment for a screen of the given size.
+    fn screen(width: f32, height: f32) -> MediaEnvironment {
+        let mut viewport = Dimensions::default();
+        viewport.content.width = width;
+        viewport.content.height = height;
+        MediaEnvironment::new(&viewport)
+    }
+
+    fn query(negated: bool, media_type: MediaType, features: Vec<MediaFeature>) -> MediaQuery {
+        MediaQuery { negated, media_type, features }
+    }
+
+    /// Test media types match the environment's type.
+    #[test]
+    fn media_types() {
+        let env = screen(800.0, 600.0);
+
+        assert!(query(false, MediaType::All, vec![]).matches(&env));
+        assert!(query(false, MediaType::Screen, vec![]).matches(&env));
+        assert!(!query(false, MediaType::Print, vec![]).matches(&env));
+        assert!(!query(false, MediaType::Unknown, vec![]).matches(&env));
+        assert!(query(true, MediaType::Print, vec![]).matches(&env));
+        assert!(!MediaQuery::not_all().matches(&env));
+    }
+
+    /// Test size features compare against the viewport.
+    #[test]
+    fn media_sizes() {
+        let env = screen(800.0, 600.0);
+        let min = |n| query(false, MediaType::All,
+            vec![MediaFeature::Width(Range::Min, Value::Length(n, Unit::Px))]);
+
+        assert!(min(800.0).matches(&env));
+        assert!(!min(801.0).matches(&env));
+        assert!(query(false, MediaType::All,
+            vec![MediaFeature::Height(Range::Max, Value::Length(40.0, Unit::Em))]).matches(&env));
+        assert!(!query(false, MediaType::All,
+            vec![MediaFeature::Width(Range::Exact, Value::Length(50.0, Unit::Vw))]).matches(&env));
+    }
+
+    /// Test orientation and color scheme features.
+    #[test]
+    fn media_orientation_scheme() {
+        let wide = screen(800.0, 600.0);
+        let tall = screen(600.0, 800.0);
+        let landscape = query(false, MediaType::All,
+            vec![MediaFeature::Orientation(Orientation::Landscape)]);
+        let dark = query(false, MediaType::All,
+            vec![MediaFeature::PrefersColorScheme(ColorScheme::Dark)]);
+
+        assert!(landscape.matches(&wide));
+        assert!(!landscape.matches(&tall));
+        assert!(!dark.matches(&wide));
+    }
+
+    /// Test a list matches if any query matches, and an empty list matches everything.
+    #[test]
+    fn media_lists() {
+        let env = screen(800.0, 600.0);
+        let print = query(false, MediaType::Print, vec![]);
+        let screen_q = query(false, MediaType::Screen, vec![]);
+
+        assert!(MediaQueryList::default().matches(&env));
+        assert!(!MediaQueryList::new(vec![print.clone()]).matches(&env));
+        assert!(MediaQueryList::new(vec![print, screen_q]).matches(&env));
+    }
+}
@@ -0,0 +1,178 @@
+//! The `pool` module runs tasks that spawn more tasks on a work-stealing pool of threads.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// The task queues of a pool's workers.
+///
+/// A worker runs its newest task first and steals the oldest task of another worker when it
+/// runs out, which for tasks that spawn their children is the largest piece of work left.
+struct Queues<T> {
+    queues: Vec<Mutex<VecDeque<T>>>,
+    pending: AtomicUsize, // the tasks queued or running
+    panicked: AtomicBool, // stops the workers if a task panics
+    generation: Mutex<usize>, // counts the changes idle workers wait for
+    wake: Condvar, // wakes idle workers when a task is queued or the pool is done
+}
+
+/// A worker's handle for spawning tasks from the task it is running.
+pub struct Spawner<'q, T: 'q> {
+    queues: &'q Queues<T>,
+    worker: usize,
+}
+
+/// Stops the other workers if the worker holding it panics, instead of waiting forever for
+/// the tasks the panicking task would have spawned.
+struct PanicGuard<'q, T: 'q>(&'q Queues<T>);
+
+/// Runs tasks on a pool of threads until every task and the tasks they spawn have run.
+///
+/// A thread is started for each worker state, and the states are returned in the same order
+/// once all tasks have run. If a task panics the panic is resumed on the calling thread.
+///
+/// states: The state of each worker, which the tasks it runs can change.
+/// tasks: The tasks to start with.
+/// work: Runs a task with the state of the worker running it.
+pub fn run<T, S, F>(states: Vec<S>, tasks: Vec<T>, work: F) -> Vec<S>
+    where T: Send, S: Send, F: Fn(&mut S, T, &Spawner<T>) + Sync {
+    assert!(!states.is_empty(), "a pool needs at least one worker");
+
+    let queues = Queues {
+        queues: states.iter().map(|_| Mutex::new(VecDeque::new())).collect(),
+        pending: AtomicUsize::new(tasks.len()),
+        panicked: AtomicBool::new(false),
+        generation: Mutex::new(0),
+        wake: Condvar::new(),
+    };
+    queues.queues[0].lock().unwrap().extend(tasks);
+
+    thread::scope(|scope| {
+        let handles = states.into_iter().enumerate().map(|(worker, mut state)| {
+            let (queues, work) = (&queues, &work);
+
+            scope.spawn(move || {
+                let _guard = PanicGuard(queues);
+                let spawner = Spawner { queues, worker };
+
+                while let Some(task) = queues.next(worker) {
+                    work(&mut state, task, &spawner);
+                    if queues.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                        queues.notify(true);
+                    }
+                }
+                state
+            })
+        }).collect::<Vec<_>>();
+
+        handles.into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    })
+}
+
+impl<T> Queues<T> {
+    /// Returns the next task for a worker, waiting while running tasks may still spawn more.
+    /// Returns None once every task has run.
+    fn next(&self, worker: usize) -> Option<T> {
+        let count = self.queues.len();
+
+        loop {
+            // a change after this read means the queues may have a task the search misses
+            let seen = *self.generation.lock().unwrap();
+
+            if let Some(task) = self.queues[worker].lock().unwrap().pop_back() {
+                return Some(task);
+            }
+            for other in (1..count).map(|i| (worker + i) % count) {
+                if let Some(task) = self.queues[other].lock().unwrap().pop_front() {
+                    return Some(task);
+                }
+            }
+            if self.pending.load(Ordering::SeqCst) == 0 || self.panicked.load(Ordering::SeqCst) {
+                return None;
+            }
+
+            let generation = self.generation.lock().unwrap();
+            if *generation == seen {
+                drop(self.wake.wait(generation).unwrap());
+            }
+        }
+    }
+
+    /// Wakes idle workers after a task was queued or the pool stopped.
+    ///
+    /// all: Whether to wake every idle worker, rather than one to take the new task.
+    fn notify(&self, all: bool) {
+        *self.generation.lock().unwrap() += 1;
+        if all {
+            self.wake.notify_all();
+        } else {
+            self.wake.notify_one();
+        }
+    }
+}
+
+impl<'q, T> Spawner<'q, T> {
+    /// Queues a task on the current worker, where idle workers can steal it.
+    pub fn spawn(&self, task: T) {
+        self.queues.pending.fetch_add(1, Ordering::SeqCst);
+        self.queues.queues[self.worker].lock().unwrap().push_back(task);
+        self.queues.notify(false);
+    }
+}
+
+impl<'q, T> Drop for PanicGuard<'q, T> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.panicked.store(true, Ordering::SeqCst);
+            self.0.notify(true);
+        }
+    }
+}
+
+/// Tests -----------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test every spawned task runs once, whatever the number of workers.
+    #[test]
+    fn run_spawned() {
+        for workers in 1..5 {
+            // each task n spawns the tasks 2n + 1 and 2n + 2 of a binary tree of 1023 tasks
+            let states = run(vec![Vec::new(); workers], vec![0], |ran: &mut Vec<usize>, n, spawner| {
+                ran.push(n);
+                if n < 511 {
+                    spawner.spawn(2 * n + 1);
+                    spawner.spawn(2 * n + 2);
+                }
+            });
+            let mut ran = states.into_iter().flatten().collect::<Vec<usize>>();
+            ran.sort();
+
+            assert_eq!((0..1023).collect::<Vec<usize>>(), ran);
+        }
+    }
+
+    /// Test running no tasks returns the states unchanged.
+    #[test]
+    fn run_empty() {
+        assert_eq!(vec![1, 2], run(vec![1, 2], Vec::<usize>::new(), |state, _, _| *state += 1));
+    }
+
+    /// Test a panicking task stops the pool and panics the caller.
+    #[test]
+    #[should_panic(expected = "task 5")]
+    fn run_panic() {
+        run(vec![(); 3], vec![0], |_, n: usize, spawner| {
+            if n == 5 {
+                panic!("task 5");
+            }
+            if n < 10 {
+                spawner.spawn(n + 1);
+            }
+        });
+    }
+}
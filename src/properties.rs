@@ -0,0 +1,506 @@
+//! The `properties` module describes the grammar of each supported css property.
+
+use css::{CalcType, Color, Keyword, Separator, Unit, Value};
+use css_parse::named_color;
+
+/// Checks a value against a grammar, returning the value normalized where the grammar
+/// allows several spellings, ex. "thin" for 1px, or None if the value doesn't match.
+type Grammar = fn(&Value) -> Option<Value>;
+
+const DISPLAY: &[Keyword] = &[
+    Keyword::Block, Keyword::Inline, Keyword::InlineBlock, Keyword::None, Keyword::ListItem,
+    Keyword::Flex, Keyword::InlineFlex, Keyword::Grid, Keyword::InlineGrid, Keyword::Table,
+    Keyword::InlineTable, Keyword::TableRowGroup, Keyword::TableHeaderGroup,
+    Keyword::TableFooterGroup, Keyword::TableRow, Keyword::TableCell, Keyword::TableColumnGroup,
+    Keyword::TableColumn, Keyword::TableCaption, Keyword::Contents, Keyword::FlowRoot,
+];
+
+const POSITION: &[Keyword] = &[
+    Keyword::Static, Keyword::Relative, Keyword::Absolute, Keyword::Fixed, Keyword::Sticky,
+];
+
+const BORDER_STYLE: &[Keyword] = &[
+    Keyword::None, Keyword::Hidden, Keyword::Dotted, Keyword::Dashed, Keyword::Solid,
+    Keyword::Double, Keyword::Groove, Keyword::Ridge, Keyword::Inset, Keyword::Outset,
+];
+
+const FONT_SIZE: &[Keyword] = &[
+    Keyword::XxSmall, Keyword::XSmall, Keyword::Small, Keyword::Medium, Keyword::Large,
+    Keyword::XLarge, Keyword::XxLarge, Keyword::Larger, Keyword::Smaller,
+];
+
+const FONT_WEIGHT: &[Keyword] = &[Keyword::Normal, Keyword::Bold, Keyword::Bolder, Keyword::Lighter];
+
+const GENERIC_FAMILY: &[Keyword] = &[
+    Keyword::Serif, Keyword::SansSerif, Keyword::Monospace, Keyword::Cursive, Keyword::Fantasy,
+];
+
+const BACKGROUND_REPEAT: &[Keyword] = &[
+    Keyword::Repeat, Keyword::RepeatX, Keyword::RepeatY, Keyword::NoRepeat, Keyword::Space,
+    Keyword::Round,
+];
+
+const POSITION_SIDE: &[Keyword] = &[
+    Keyword::Left, Keyword::Right, Keyword::Top, Keyword::Bottom, Keyword::Center,
+];
+
+const OVERFLOW: &[Keyword] = &[
+    Keyword::Visible, Keyword::Hidden, Keyword::Clip, Keyword::Scroll, Keyword::Auto,
+];
+
+const LIST_STYLE_TYPE: &[Keyword] = &[
+    Keyword::None, Keyword::Disc, Keyword::Circle, Keyword::Square, Keyword::Decimal,
+    Keyword::LowerAlpha, Keyword::UpperAlpha, Keyword::LowerRoman, Keyword::UpperRoman,
+];
+
+/// Returns the grammar of a property, or None if the property isn't supported.
+fn grammar(property: &str) -> Option<Grammar> {
+    let grammar: Grammar = match property {
+        "display" => display,
+        "position" => |v| keyword(v, POSITION),
+        "width"|"height"|"min-width"|"min-height" => {
+            |v| keyword(v, &[Keyword::Auto]).or_else(|| length_percentage(v, false))
+        },
+        "max-width"|"max-height" => {
+            |v| keyword(v, &[Keyword::None]).or_else(|| length_percentage(v, false))
+        },
+        "margin-top"|"margin-right"|"margin-bottom"|"margin-left"|
+        "top"|"right"|"bottom"|"left" => {
+            |v| keyword(v, &[Keyword::Auto]).or_else(|| length_percentage(v, true))
+        },
+        "padding-top"|"padding-right"|"padding-bottom"|"padding-left" => {
+            |v| length_percentage(v, false)
+        },
+        "border-top-width"|"border-right-width"|"border-bottom-width"|"border-left-width" => {
+            border_width
+        },
+        "border-top-style"|"border-right-style"|"border-bottom-style"|"border-left-style" => {
+            |v| keyword(v, BORDER_STYLE)
+        },
+        "color"|"background-color"|
+        "border-top-color"|"border-right-color"|"border-bottom-color"|"border-left-color" => color,
+        "font-size" => |v| keyword(v, FONT_SIZE).or_else(|| length_percentage(v, false)),
+        "font-weight" => |v| keyword(v, FONT_WEIGHT).or_else(|| font_weight_number(v)),
+        "font-style" => |v| keyword(v, &[Keyword::Normal, Keyword::Italic, Keyword::Oblique]),
+        "font-variant" => |v| keyword(v, &[Keyword::Normal, Keyword::SmallCaps]),
+        "font-family" => |v| comma_list(v, font_family),
+        "line-height" => {
+            |v| keyword(v, &[Keyword::Normal])
+                .or_else(|| number(v, false))
+                .or_else(|| length_percentage(v, false))
+        },
+        "background-image" => |v| keyword(v, &[Keyword::None]).or_else(|| image(v)),
+        "background-repeat" => |v| space_list(v, 2, |c| keyword(c, BACKGROUND_REPEAT)),
+        "background-attachment" => {
+            |v| keyword(v, &[Keyword::Scroll, Keyword::Fixed, Keyword::Local])
+        },
+        "background-position" => {
+            |v| space_list(v, 4, |c| keyword(c, POSITION_SIDE).or_else(|| length_percentage(c, true)))
+        },
+        "visibility" => |v| keyword(v, &[Keyword::Visible, Keyword::Hidden, Keyword::Collapse]),
+        "float" => |v| keyword(v, &[Keyword::Left, Keyword::Right, Keyword::None]),
+        "clear" => |v| keyword(v, &[Keyword::None, Keyword::Left, Keyword::Right, Keyword::Both]),
+        "text-align" => {
+            |v| keyword(v, &[Keyword::Left, Keyword::Right, Keyword::Center, Keyword::Justify,
+                Keyword::Start, Keyword::End])
+        },
+        "overflow"|"overflow-x"|"overflow-y" => |v| keyword(v, OVERFLOW),
+        "list-style-type" => |v| keyword(v, LIST_STYLE_TYPE).or_else(|| string(v)),
+        "list-style-position" => |v| keyword(v, &[Keyword::Inside, Keyword::Outside]),
+        "z-index" => |v| keyword(v, &[Keyword::Auto]).or_else(|| integer(v)),
+        "opacity" => |v| number(v, true).or_else(|| percentage(v)),
+        _ => return None,
+    };
+
+    Some(grammar)
+}
+
+/// Validates a value against the grammar of a property.
+///
+/// Returns the normalized value, or None if the value is invalid for the property.
+/// Every property accepts the css-wide keywords, unsupported properties accept any value.
+pub fn validate(property: &str, value: Value) -> Option<Value> {
+    match value {
+        Value::Keyword(k) if k.is_css_wide() => Some(value),
+        _ => match grammar(property) {
+            Some(g) => g(&value),
+            None => Some(value),
+        },
+    }
+}
+
+/// Returns true if the property has a grammar its values are validated against.
+pub fn is_known(property: &str) -> bool {
+    grammar(property).is_some()
+}
+
+/// Returns true if a property inherits its parent's value when no declaration applies.
+/// Custom properties always inherit.
+pub fn is_inherited(property: &str) -> bool {
+    property.starts_with("--") || matches!(property,
+        "color" | "font-size" | "font-weight" | "font-style" | "font-variant" | "font-family" |
+        "line-height" | "visibility" | "text-align" | "list-style-type" | "list-style-position")
+}
+
+/// Returns the initial value of a property, or None if the property isn't supported.
+pub fn initial_value(property: &str) -> Option<Value> {
+    let zero = Value::Length(0.0, Unit::Px);
+    let value = match property {
+        "display" => Value::Keyword(Keyword::Inline),
+        "position" => Value::Keyword(Keyword::Static),
+        "width"|"height"|"min-width"|"min-height"|"top"|"right"|"bottom"|"left"|"z-index" => {
+            Value::Keyword(Keyword::Auto)
+        },
+        "max-width"|"max-height" => Value::Keyword(Keyword::None),
+        "margin-top"|"margin-right"|"margin-bottom"|"margin-left"|
+        "padding-top"|"padding-right"|"padding-bottom"|"padding-left" => zero,
+        "border-top-width"|"border-right-width"|"border-bottom-width"|"border-left-width" => {
+            Value::Length(3.0, Unit::Px)
+        },
+        "border-top-style"|"border-right-style"|"border-bottom-style"|"border-left-style" => {
+            Value::Keyword(Keyword::None)
+        },
+        "border-top-color"|"border-right-color"|"border-bottom-color"|"border-left-color" => {
+            Value::Keyword(Keyword::CurrentColor)
+        },
+        "color" => Value::Color(Color::new(0.0, 0.0, 0.0, 1.0)),
+        "background-color" => Value::Color(Color::new(0.0, 0.0, 0.0, 0.0)),
+        "font-size" => Value::Keyword(Keyword::Medium),
+        "font-weight"|"font-style"|"font-variant"|"line-height" => Value::Keyword(Keyword::Normal),
+        "font-family" => Value::Keyword(Keyword::Serif),
+        "background-image"|"float"|"clear" => Value::Keyword(Keyword::None),
+        "background-repeat" => Value::Keyword(Keyword::Repeat),
+        "background-attachment" => Value::Keyword(Keyword::Scroll),
+        "background-position" => {
+            Value::List(vec![Value::Percentage(0.0), Value::Percentage(0.0)], Separator::Space)
+        },
+        "visibility"|"overflow"|"overflow-x"|"overflow-y" => Value::Keyword(Keyword::Visible),
+        "text-align" => Value::Keyword(Keyword::Start),
+        "list-style-type" => Value::Keyword(Keyword::Disc),
+        "list-style-position" => Value::Keyword(Keyword::Outside),
+        "opacity" => Value::Number(1.0),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Matches one of the allowed keywords.
+fn keyword(v: &Value, allowed: &[Keyword]) -> Option<Value> {
+    match *v {
+        Value::Keyword(k) if allowed.contains(&k) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// Matches a length, a unitless 0 is normalized to 0px.
+fn length(v: &Value, negative: bool) -> Option<Value> {
+    match *v {
+        Value::Length(n, _) if negative || n >= 0.0 => Some(v.clone()),
+        Value::Number(0.0) => Some(Value::Length(0.0, Unit::Px)),
+        _ => calc(v, &[CalcType::Length]),
+    }
+}
+
+/// Matches a length or a percentage.
+fn length_percentage(v: &Value, negative: bool) -> Option<Value> {
+    match *v {
+        Value::Percentage(p) if negative || p >= 0.0 => Some(v.clone()),
+        Value::Calc(_) => calc(v, &[CalcType::Length, CalcType::Percentage, CalcType::LengthPercentage]),
+        _ => length(v, negative),
+    }
+}
+
+/// Matches a percentage.
+fn percentage(v: &Value) -> Option<Value> {
+    match *v {
+        Value::Percentage(_) => Some(v.clone()),
+        _ => calc(v, &[CalcType::Percentage]),
+    }
+}
+
+/// Matches a number.
+fn number(v: &Value, negative: bool) -> Option<Value> {
+    match *v {
+        Value::Number(n) if negative || n >= 0.0 => Some(v.clone()),
+        _ => calc(v, &[CalcType::Number]),
+    }
+}
+
+/// Matches a math function that resolves to one of the allowed types.
+/// Its result is clamped to the property's range when it's computed, so any sign is allowed.
+fn calc(v: &Value, allowed: &[CalcType]) -> Option<Value> {
+    match *v {
+        Value::Calc(ref node) if node.calc_type().is_some_and(|t| allowed.contains(&t)) => {
+            Some(v.clone())
+        },
+        _ => None,
+    }
+}
+
+/// Matches a whole number.
+fn integer(v: &Value) -> Option<Value> {
+    match *v {
+        Value::Number(n) if n.fract() == 0.0 => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// Matches a string.
+fn string(v: &Value) -> Option<Value> {
+    match *v {
+        Value::Str(_) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// Matches a color, named colors are normalized to their rgba value.
+fn color(v: &Value) -> Option<Value> {
+    match *v {
+        Value::Color(_) | Value::Keyword(Keyword::CurrentColor) => Some(v.clone()),
+        Value::Ident(ref name) => named_color(name).map(Value::Color),
+        _ => None,
+    }
+}
+
+/// Matches a border width, the width keywords are normalized to lengths.
+fn border_width(v: &Value) -> Option<Value> {
+    match *v {
+        Value::Keyword(Keyword::Thin) => Some(Value::Length(1.0, Unit::Px)),
+        Value::Keyword(Keyword::Medium) => Some(Value::Length(3.0, Unit::Px)),
+        Value::Keyword(Keyword::Thick) => Some(Value::Length(5.0, Unit::Px)),
+        _ => length(v, false),
+    }
+}
+
+/// Matches a display keyword, or an outer display type, an inner display type and list-item
+/// in any order, normalized to the shortest form, ex. "flex inline" to "inline-flex". A
+/// missing outer type is block and a missing inner type is flow, and a list item's inner type
+/// has to be flow or flow-root.
+fn display(v: &Value) -> Option<Value> {
+    let parts = match *v {
+        Value::Keyword(k) if DISPLAY.contains(&k) => return Some(v.clone()),
+        Value::List(ref parts, Separator::Space) if parts.len() <= 3 => &parts[..],
+        Value::Ident(_) => ::std::slice::from_ref(v),
+        _ => return None,
+    };
+
+    // an inner type of None is flow
+    let (mut outside, mut inside, mut has_inside, mut list_item) = (None, None, false, false);
+    for part in parts {
+        match *part {
+            Value::Keyword(k @ Keyword::Block) | Value::Keyword(k @ Keyword::Inline) if outside.is_none() => {
+                outside = Some(k);
+            },
+            Value::Keyword(k @ Keyword::FlowRoot) | Value::Keyword(k @ Keyword::Table) |
+            Value::Keyword(k @ Keyword::Flex) | Value::Keyword(k @ Keyword::Grid) if !has_inside => {
+                inside = Some(k);
+                has_inside = true;
+            },
+            Value::Ident(ref flow) if flow == "flow" && !has_inside => has_inside = true,
+            Value::Keyword(Keyword::ListItem) if !list_item => list_item = true,
+            _ => return None,
+        }
+    }
+
+    let keyword = match (outside.unwrap_or(Keyword::Block), inside, list_item) {
+        (_, Some(Keyword::FlowRoot), true) | (_, None, true) => None,
+        (_, Some(_), true) => return None,
+        (Keyword::Block, None, false) => Some(Keyword::Block),
+        (Keyword::Block, Some(k), false) => Some(k),
+        (_, None, false) => Some(Keyword::Inline),
+        (_, Some(Keyword::FlowRoot), false) => Some(Keyword::InlineBlock),
+        (_, Some(Keyword::Table), false) => Some(Keyword::InlineTable),
+        (_, Some(Keyword::Flex), false) => Some(Keyword::InlineFlex),
+        (_, Some(_), false) => Some(Keyword::InlineGrid),
+    };
+    if let Some(keyword) = keyword {
+        return Some(Value::Keyword(keyword));
+    }
+
+    // a list item is only a keyword with the defaults, the others list what isn't a default
+    let mut parts = Vec::new();
+    if outside == Some(Keyword::Inline) {
+        parts.push(Value::Keyword(Keyword::Inline));
+    }
+    if inside == Some(Keyword::FlowRoot) {
+        parts.push(Value::Keyword(Keyword::FlowRoot));
+    }
+    if parts.is_empty() {
+        return Some(Value::Keyword(Keyword::ListItem));
+    }
+    parts.push(Value::Keyword(Keyword::ListItem));
+    Some(Value::List(parts, Separator::Space))
+}
+
+/// Matches a numeric font weight from 1 to 1000.
+fn font_weight_number(v: &Value) -> Option<Value> {
+    match *v {
+        Value::Number(n) if (1.0..=1000.0).contains(&n) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// Matches a single font family: a string, a generic family or a sequence of identifiers.
+/// Words the tokenizer took for other keywords are part of the name, ex. "fixed" or the
+/// "medium" of "source code pro medium", and are normalized to identifiers.
+fn font_family(v: &Value) -> Option<Value> {
+    let word = |w: &Value| match *w {
+        Value::Ident(_) => Some(w.clone()),
+        Value::Keyword(k) if !k.is_css_wide() => Some(Value::Ident(String::from(k.as_str()))),
+        _ => None,
+    };
+
+    match *v {
+        Value::Str(_) | Value::Ident(_) => Some(v.clone()),
+        Value::Keyword(_) => keyword(v, GENERIC_FAMILY).or_else(|| word(v)),
+        Value::List(ref words, Separator::Space) => {
+            let words = words.iter().map(word).collect::<Option<Vec<Value>>>()?;
+            Some(Value::List(words, Separator::Space))
+        },
+        _ => None,
+    }
+}
+
+/// Matches an image, a url or an image function like linear-gradient().
+fn image(v: &Value) -> Option<Value> {
+    match *v {
+        Value::Url(_) | Value::Function(..) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// Matches a value or a comma separated list where every item matches the grammar.
+fn comma_list<F>(v: &Value, item: F) -> Option<Value> where F : Fn(&Value) -> Option<Value> {
+    match *v {
+        Value::List(ref items, Separator::Comma) => {
+            let items = items.iter().map(&item).collect::<Option<Vec<Value>>>()?;
+            Some(Value::List(items, Separator::Comma))
+        },
+        _ => item(v),
+    }
+}
+
+/// Matches a value or a space separated list of up to max values that match the grammar.
+fn space_list<F>(v: &Value, max: usize, item: F) -> Option<Value> where F : Fn(&Value) -> Option<Value> {
+    match *v {
+        Value::List(ref items, Separator::Space) if items.len() <= max => {
+            let items = items.iter().map(&item).collect::<Option<Vec<Value>>>()?;
+            Some(Value::List(items, Separator::Space))
+        },
+        Value::List(..) => None,
+        _ => item(v),
+    }
+}
+
+/// Tests ----------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use css::Color;
+    use css_parse::parse_value;
+
+    /// Test keyword grammars accept only their keywords.
+    #[test]
+    fn validate_keywords() {
+        let block = Value::Keyword(Keyword::Block);
+
+        assert_eq!(Some(block.clone()), validate("display", block.clone()));
+        assert_eq!(None, validate("position", block));
+        assert_eq!(None, validate("display", Value::Ident(String::from("blocky"))));
+    }
+
+    /// Test multi-keyword displays are normalized to their shortest form, and invalid
+    /// combinations are rejected.
+    #[test]
+    fn validate_display() {
+        let display = |css: &str| validate("display", parse_value(css).unwrap());
+        let list = |keywords: &[Keyword]| Value::List(keywords.iter().map(|&k| Value::Keyword(k)).collect(), Separator::Space);
+
+        assert_eq!(Some(Value::Keyword(Keyword::Block)), display("block flow"));
+        assert_eq!(Some(Value::Keyword(Keyword::InlineFlex)), display("flex inline"));
+        assert_eq!(Some(Value::Keyword(Keyword::InlineBlock)), display("inline flow-root"));
+        assert_eq!(Some(Value::Keyword(Keyword::ListItem)), display("list-item block flow"));
+        assert_eq!(Some(Value::Keyword(Keyword::Table)), display("table"));
+        assert_eq!(Some(list(&[Keyword::Inline, Keyword::ListItem])), display("list-item inline"));
+        assert_eq!(Some(list(&[Keyword::FlowRoot, Keyword::ListItem])), display("flow-root list-item"));
+        assert_eq!(None, display("list-item flex"));
+        assert_eq!(None, display("block inline"));
+        assert_eq!(None, display("table-cell block"));
+    }
+
+    /// Test every property accepts the css-wide keywords.
+    #[test]
+    fn validate_css_wide() {
+        let inherit = Value::Keyword(Keyword::Inherit);
+
+        assert_eq!(Some(inherit.clone()), validate("width", inherit.clone()));
+        assert_eq!(Some(inherit.clone()), validate("color", inherit));
+    }
+
+    /// Test lengths, including the sign restrictions and unitless zero.
+    #[test]
+    fn validate_lengths() {
+        let negative = Value::Length(-1.0, Unit::Px);
+
+        assert_eq!(Some(negative.clone()), validate("margin-left", negative.clone()));
+        assert_eq!(None, validate("padding-left", negative));
+        assert_eq!(Some(Value::Length(0.0, Unit::Px)), validate("width", Value::Number(0.0)));
+        assert_eq!(None, validate("width", Value::Number(10.0)));
+        assert_eq!(Some(Value::Percentage(50.0)), validate("width", Value::Percentage(50.0)));
+        assert_eq!(Some(Value::Length(5.0, Unit::Px)), validate("border-top-width", Value::Keyword(Keyword::Thick)));
+    }
+
+    /// Test named colors are normalized and other identifiers are rejected.
+    #[test]
+    fn validate_colors() {
+        let red = Value::Color(Color::new(1.0, 0.0, 0.0, 1.0));
+
+        assert_eq!(Some(red), validate("color", Value::Ident(String::from("red"))));
+        assert_eq!(None, validate("color", Value::Ident(String::from("reddish"))));
+        assert!(validate("color", Value::Keyword(Keyword::CurrentColor)).is_some());
+    }
+
+    /// Test initial values are valid values of their properties.
+    #[test]
+    fn initial_values() {
+        for property in &["display", "width", "max-width", "margin-top", "border-top-width",
+            "border-top-color", "color", "font-size", "font-family", "background-position", "opacity"] {
+            let initial = initial_value(property).unwrap();
+            assert_eq!(Some(initial.clone()), validate(property, initial));
+        }
+        assert_eq!(None, initial_value("--custom"));
+        assert!(is_inherited("color") && is_inherited("--custom"));
+        assert!(!is_inherited("width"));
+    }
+
+    /// Test font families are a comma separated list.
+    #[test]
+    fn validate_font_family() {
+        let families = Value::List(vec![
+            Value::List(vec![Value::Ident(String::from("times")), Value::Ident(String::from("roman"))], Separator::Space),
+            Value::Str(String::from("Georgia")),
+            Value::Keyword(Keyword::Serif),
+        ], Separator::Comma);
+
+        assert_eq!(Some(families.clone()), validate("font-family", families));
+        assert_eq!(None, validate("font-family", Value::Number(1.0)));
+
+        let ident = |name: &str| Value::Ident(String::from(name));
+        let source_code = Value::List(vec![ident("source"), ident("code"), ident("pro"), ident("medium")], Separator::Space);
+        assert_eq!(Some(source_code), validate("font-family", parse_value("source code pro medium").unwrap()));
+        assert_eq!(Some(ident("fixed")), validate("font-family", Value::Keyword(Keyword::Fixed)));
+        assert_eq!(Some(Value::Keyword(Keyword::Monospace)), validate("font-family", parse_value("monospace").unwrap()));
+        assert_eq!(None, validate("font-family", parse_value("Times inherit").unwrap()));
+    }
+
+    /// Test unsupported properties accept any value.
+    #[test]
+    fn validate_unknown() {
+        let v = Value::Dimension(2.0, String::from("s"));
+
+        assert_eq!(Some(v.clone()), validate("transition-duration", v));
+        assert!(!is_known("transition-duration"));
+        assert!(is_known("width"));
+    }
+}
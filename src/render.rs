@@ -102,7 +102,16 @@ pub fn render_loop(command_list: &[DisplayCommand]) {
 }
 
 fn render_texts(command_list: &[DisplayCommand]) -> Vec<RenderText> {
-    Vec::new()
+    command_list.iter()
+        .filter_map(|command| match *command {
+            DisplayCommand::Text(ref text, ref color, ref rect) => Some(RenderText {
+                text,
+                position: [rect.x as i32, rect.y as i32],
+                color: [color.r, color.g, color.b, color.a],
+            }),
+            _ => None,
+        })
+        .collect()
 }
 
 fn render_commands(command_list: &[DisplayCommand]) -> (Vec<Vertex>, Vec<u16>) {
@@ -122,6 +131,7 @@ fn render_commands(command_list: &[DisplayCommand]) -> (Vec<Vertex>, Vec<u16>) {
                 index_data.append(&mut vec![index_base, index_base + 1, index_base + 2, index_base + 2, index_base + 3, index_base]);
                 rect_num += 1;
             },
+            DisplayCommand::Text(..) => {},
         }
     }
     return (vertices, index_data);
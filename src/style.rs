@@ -1,169 +1,982 @@
-//! The `style` module takes a dom tree and stylesheet and constructs a style tree.
+//! The `style` module takes a dom tree and stylesheets and constructs a style tree.
 
+use computed::{font_weight, ComputedStyle, LengthContext};
 use dom::{Node, ElementData, NodeType};
-use css::{Selector, Stylesheet, Value};
+use css::{CalcType, Declaration, Keyword, Origin, Rule, SourceLocation, Specificity, Stylesheet, Unit, Value};
+use css_parse::{parse_substituted, substitute_vars, CssParser};
+use invalidation::{Dependencies, RestyleHint};
+use matching::{simple_matches, Ancestors, MatchedRule, RuleIndex};
+use media::MediaEnvironment;
+use properties::{initial_value, is_inherited};
 
-use std::collections::HashMap;
-use std::{fmt, str};
+use pool;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::{fmt, iter, mem, ptr, str};
 
-type PropertyMap<'a> = HashMap<&'a str, &'a Value>;
+type PropertyMap = HashMap<String, Value>;
 
-pub struct StyledNode<'a> {
-    node: &'a Node,
-    styles: PropertyMap<'a>,
-    pub children: Vec<StyledNode<'a>>
+/// A node of the style tree. It owns a copy of its dom node's data, so the style tree can be
+/// kept between frames and restyled as the dom changes.
+pub struct StyledNode {
+    node: NodeType,
+    id: usize, // the id of the styles, equal ids are equal styles
+    styles: PropertyMap,
+    computed: ComputedStyle,
+    pub children: Vec<StyledNode>,
+    dirty: RestyleHint,
+    dirty_descendants: bool, // a descendant has dirty bits set
+    children_changed: bool, // the children of the dom node were added or removed
 }
 
-pub enum Display {
-    Block,
-    Inline,
-    InlineBlock,
-    None
+/// Styles the nodes of a document, sharing the styles of elements that are provably styled
+/// the same instead of cascading each of them.
+pub struct StyleContext<'s> {
+    index: RuleIndex<'s>,
+    media: &'s MediaEnvironment,
+    shared: SharingCache,
+    dependencies: Dependencies,
+    pub traversal: Traversal,
+    pub stats: SharingStats,
 }
 
-impl<'a> StyledNode<'a> {
+/// How a StyleContext walks the dom tree. Both walks construct the same style tree.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Traversal {
+    Sequential,
+    /// Style the children of each node as separate tasks on a work-stealing pool of the
+    /// given number of threads. Which elements share styles depends on the scheduling, so
+    /// the sharing stats may differ between runs.
+    Parallel(usize),
+}
+
+/// The styles of elements that later elements with the same key can share, and their ids.
+#[derive(Default)]
+struct SharingCache {
+    styles: HashMap<SharingKey, (usize, PropertyMap, ComputedStyle)>,
+    stats: SharingStats,
+}
+
+/// What the styles of an element depend on. Selectors only match the tag, id and classes of
+/// an element and its ancestors, so elements that agree on those, their style attribute and
+/// their parent's style get the same styles. Parents only share styles if their own
+/// ancestors agree, so the parent's style stands in for the ancestors.
+#[derive(PartialEq, Eq, Hash, Clone)]
+struct SharingKey {
+    tag_name: String,
+    id: Option<String>,
+    classes: Vec<String>, // sorted
+    style: Option<String>,
+    parent: usize, // the id of the parent's style
+}
+
+/// How often the style sharing cache let an element reuse the styles of another.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub struct SharingStats {
+    pub lookups: usize,
+    pub hits: usize,
+}
+
+/// A rule that matched an element, as the cascade saw it.
+pub struct TracedRule {
+    pub selector: Option<String>, // the most specific matching selector, None for the style attribute
+    pub specificity: Specificity,
+    pub origin: Origin,
+    pub location: Option<SourceLocation>, // None for the style attribute
+    pub declarations: Vec<TracedDeclaration>,
+}
+
+/// A declaration of a traced rule.
+pub struct TracedDeclaration {
+    pub property: String,
+    pub value: Value,
+    pub important: bool,
+    pub overridden: bool, // another declaration of the property won the cascade
+}
+
+/// A declaration that applies to an element, with the data needed to cascade it.
+struct MatchedDeclaration<'a> {
+    level: usize,
+    origin: Origin,
+    inline: bool, // from the element's style attribute, which beats any selector
+    specificity: Specificity,
+    order: usize,
+    declaration: &'a Declaration,
+}
+
+/// Resolves the var() references of custom properties, detecting reference cycles.
+struct CustomResolver<'a> {
+    specified: HashMap<&'a str, &'a Value>,
+    resolved: HashMap<String, Option<String>>,
+    stack: Vec<String>,
+    cyclic: HashSet<String>,
+}
+
+/// The source of style ids. It is shared by every StyleContext so the ids of a style tree
+/// that is restyled by a new context never collide with the ids the context hands out.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1); // 0 is the id of the root's parent
+
+/// The number of nodes a subtree needs for the parallel traversal to style it as a task of its
+/// own, rather than as part of its parent's task.
+const TASK_NODES: usize = 8;
+
+impl StyledNode {
     /// Constructs a new StyledNode
     ///
     /// node: The current dom node being styled.
-    /// ss: The stylesheet being applied.
-    pub fn new(node: &'a Node, ss: &'a Stylesheet) -> StyledNode<'a> {
-        // recursively make a styletree without any styles
-        // then apply rules to the tree
+    /// sheets: The stylesheets being applied, each tagged with its origin.
+    /// media: The device @media rules are evaluated against.
+    pub fn new(node: &Node, sheets: &[Stylesheet], media: &MediaEnvironment) -> StyledNode {
+        StyleContext::new(sheets, media).style(node)
+    }
+
+    /// Return the styles of the current node.
+    ///
+    /// elem: The current node's element data.
+    /// index: The rules being applied.
+    /// ancestors: The ancestors of the element, which descendant selectors match.
+    /// media: The device relative lengths resolve against.
+    /// parent: The styles of the parent node.
+    /// root: The styles of the root node, or None if this node is the root.
+    fn get_styles(elem: &ElementData, index: &RuleIndex, ancestors: &Ancestors, media: &MediaEnvironment,
+        parent: &PropertyMap, root: Option<&PropertyMap>) -> PropertyMap {
+        let mut declared = HashMap::new();
+        let inline = inline_declarations(elem);
+        let matched = cascade(&index.matching_rules(elem, ancestors), &inline);
+
+        // the winning declarations of each origin, which revert rolls back to
+        let mut by_origin: [HashMap<&str, &Value>; 3] = Default::default();
+
+        for m in matched {
+            let (property, value) = (&m.declaration.property[..], &m.declaration.value);
+            by_origin[m.origin as usize].insert(property, value);
+            declared.insert(property, (m.origin, value));
+        }
+
+        let declared = declared.into_iter()
+            .map(|(property, (origin, value))| (property, revert(property, origin, value, &by_origin)))
+            .collect();
+
+        compute_values(&declared, parent, root, media)
+    }
+
+    /// Return a style property for the current node.
+    ///
+    /// name: the property name to return the value of.
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.styles.get(name)
+    }
+
+    /// Return the text of the current node, or None if it isn't a text node.
+    pub fn text(&self) -> Option<&str> {
+        match self.node {
+            NodeType::Text(ref text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the computed values of the current node, one "property: value" line per
+    /// property sorted by name, with lengths in pixels. Properties without a value take their
+    /// initial value, and custom properties are listed as they were substituted.
+    pub fn computed_dump(&self) -> String {
+        let custom = self.styles.iter()
+            .filter(|&(property, _)| property.starts_with("--"))
+            .map(|(property, value)| (property.clone(), value.clone()));
+        let mut lines = self.computed.to_values().into_iter()
+            .chain(custom)
+            .map(|(property, value)| format!("{}: {:?}\n", property, value))
+            .collect::<Vec<String>>();
+        lines.sort();
+        lines.concat()
+    }
+
+    /// Return the typed computed style of the current node.
+    pub fn computed(&self) -> &ComputedStyle {
+        &self.computed
+    }
+
+    /// Return a length property for the current node in pixels or a default value.
+    /// Relative lengths depend on layout, so only absolute lengths are returned.
+    ///
+    /// name: the property name to return the value of.
+    /// default: the value to return if None is found.
+    pub fn num_or(&self, name: &str, default: f32) -> f32 {
+        match self.value(name) {
+            Some(&Value::Length(n, unit)) => unit.absolute_px().map_or(default, |px| n * px),
+            _ => default,
+        }
+    }
+
+    /// Marks a node as needing a restyle after a change to the dom.
+    ///
+    /// path: The indices of the children leading from this node to the changed node.
+    /// hint: What the change could restyle.
+    pub fn invalidate(&mut self, path: &[usize], hint: RestyleHint) {
+        let node = self.descendant(path);
+        node.dirty = node.dirty.union(hint);
+    }
+
+    /// Marks a node whose dom node had children added or removed, which are styled anew.
+    ///
+    /// path: The indices of the children leading from this node to the changed node.
+    pub fn invalidate_children(&mut self, path: &[usize]) {
+        self.descendant(path).children_changed = true;
+    }
+
+    /// Marks the elements that changed rules could match as needing a restyle. Only the
+    /// rightmost compound of each selector is matched, which finds every element a rule
+    /// matches and possibly more. Returns true if any element was marked.
+    ///
+    /// rules: The rules that were added, removed or moved in the cascade.
+    pub fn invalidate_rules(&mut self, rules: &[&Rule]) -> bool {
+        if let NodeType::Element(ref e) = self.node {
+            let matches = rules.iter()
+                .flat_map(|rule| &rule.selectors)
+                .filter_map(|selector| selector.subject())
+                .any(|subject| simple_matches(e, subject));
+            self.dirty.element |= matches;
+        }
+
+        for child in &mut self.children {
+            self.dirty_descendants |= child.invalidate_rules(rules);
+        }
+
+        self.dirty.element || self.dirty_descendants
+    }
+
+    /// Returns a descendant of this node, marking the nodes on the way as having a dirty
+    /// descendant.
+    fn descendant(&mut self, path: &[usize]) -> &mut StyledNode {
+        let mut node = self;
+
+        for &i in path {
+            node.dirty_descendants = true;
+            node = &mut node.children[i];
+        }
+
+        node
+    }
+
+    /// Returns the number of nodes in the subtree of this node.
+    fn count(&self) -> usize {
+        1 + self.children.iter().map(StyledNode::count).sum::<usize>()
+    }
+}
+impl<'s> StyleContext<'s> {
+    /// Constructs a new StyleContext.
+    ///
+    /// sheets: The stylesheets being applied, each tagged with its origin.
+    /// media: The device @media rules are evaluated against.
+    pub fn new(sheets: &'s [Stylesheet], media: &'s MediaEnvironment) -> StyleContext<'s> {
+        StyleContext {
+            index: RuleIndex::new(iter::once(user_agent_stylesheet()).chain(sheets), media),
+            media,
+            shared: SharingCache::default(),
+            dependencies: Dependencies::new(iter::once(user_agent_stylesheet()).chain(sheets)),
+            traversal: Traversal::Sequential,
+            stats: SharingStats::default(),
+        }
+    }
+
+    /// Constructs the style tree of a dom tree, walking it as set by the traversal.
+    ///
+    /// root: The root of the dom tree.
+    pub fn style(&mut self, root: &Node) -> StyledNode {
+        let mut cache = mem::take(&mut self.shared);
+
+        let styled = match self.traversal {
+            Traversal::Sequential => {
+                self.style_node(root, &mut cache, &mut Ancestors::new(), &PropertyMap::new(), 0, None)
+            },
+            Traversal::Parallel(threads) => self.style_parallel(root, &mut cache, threads.max(1)),
+        };
+
+        self.stats.lookups += cache.stats.lookups;
+        self.stats.hits += cache.stats.hits;
+        cache.stats = SharingStats::default();
+        self.shared = cache;
+        styled
+    }
+
+    /// Restyles the nodes of a style tree that changes marked as dirty, and the descendants
+    /// whose inherited styles changed as a result, then clears the dirty bits. The nodes
+    /// nothing could affect aren't visited. Returns the number of nodes that were styled.
+    ///
+    /// Each node on the way to a dirty node takes a new copy of its dom node's data.
+    ///
+    /// styled: The style tree of the dom tree before the changes.
+    /// root: The root of the dom tree after the changes.
+    pub fn restyle(&mut self, styled: &mut StyledNode, root: &Node) -> usize {
+        let mut cache = mem::take(&mut self.shared);
+
+        let restyled = {
+            let mut state = Restyle { cache: &mut cache, ancestors: Ancestors::new(), restyled: 0 };
+            self.restyle_node(&mut state, styled, root, (0, &PropertyMap::new()), None, RestyleHint::default());
+            state.restyled
+        };
+
+        self.stats.lookups += cache.stats.lookups;
+        self.stats.hits += cache.stats.hits;
+        cache.stats = SharingStats::default();
+        self.shared = cache;
+        restyled
+    }
+
+    /// Returns every rule that matches an element, and its style attribute, in cascade
+    /// order: the later of two rules wins, except for their important declarations.
+    /// Returns None if the path doesn't lead to an element.
+    ///
+    /// root: The root of the dom tree.
+    /// path: The indices of the styled children leading from the root to the element.
+    pub fn trace(&self, root: &Node, path: &[usize]) -> Option<Vec<TracedRule>> {
+        let mut ancestors = Ancestors::new();
+        let mut node = root;
+
+        for &i in path {
+            if let NodeType::Element(ref e) = node.node_type {
+                ancestors.push(e);
+            }
+            node = styled_children(node).nth(i)?;
+        }
+
+        let elem = match node.node_type {
+            NodeType::Element(ref e) => e,
+            _ => return None,
+        };
+        let inline = inline_declarations(elem);
+        let mut rules = self.index.matching_rules(elem, &ancestors);
+        let matched = cascade(&rules, &inline);
+
+        // the last declaration of a property in cascade order wins
+        let mut winners = HashMap::new();
+        for m in &matched {
+            winners.insert(&m.declaration.property[..], m.declaration);
+        }
+        let traced = |decls: &[Declaration]| decls.iter().map(|decl| TracedDeclaration {
+            property: decl.property.clone(),
+            value: decl.value.clone(),
+            important: decl.important,
+            overridden: !winners.get(&decl.property[..]).is_some_and(|&w| ptr::eq(w, decl)),
+        }).collect();
+
+        rules.sort_by_key(|m| (cascade_level(m.origin, false), m.specificity, m.order));
+        let mut trace = rules.iter().map(|m| TracedRule {
+            selector: Some(format!("{:?}", m.selector)),
+            specificity: m.specificity,
+            origin: m.origin,
+            location: Some(m.rule.location.clone()),
+            declarations: traced(&m.rule.declarations),
+        }).collect::<Vec<TracedRule>>();
+
+        if elem.get_style().is_some() {
+            trace.push(TracedRule {
+                selector: None,
+                specificity: Specificity::default(),
+                origin: Origin::Author,
+                location: None,
+                declarations: traced(&inline),
+            });
+        }
+
+        Some(trace)
+    }
+
+    /// Marks an element of a style tree whose attributes changed as needing a restyle of
+    /// whatever its changed ids, classes and style attribute could affect.
+    ///
+    /// styled: The root of the style tree.
+    /// path: The indices of the children leading from the root to the element.
+    /// old: The element before the change.
+    /// new: The element after the change.
+    pub fn element_changed(&self, styled: &mut StyledNode, path: &[usize], old: &ElementData, new: &ElementData) {
+        // the node is marked even if nothing is restyled, to take a copy of the new data
+        styled.invalidate(path, self.dependencies.hint(old, new));
+    }
+
+    /// Restyles a node if it or its parent asks for it, then the children that need it.
+    ///
+    /// styled: The node of the style tree.
+    /// node: The dom node after the changes.
+    /// parent: The id and styles of the parent node.
+    /// root: The styles of the root node, or None if this node is the root.
+    /// force: What the parent's restyle requires to be restyled.
+    fn restyle_node<'a, 'c>(&self, state: &mut Restyle<'a, 'c>, styled: &mut StyledNode, node: &'a Node,
+        parent: (usize, &PropertyMap), root: Option<&PropertyMap>, force: RestyleHint) {
+        let hint = mem::take(&mut styled.dirty).union(force);
+        let dirty_descendants = mem::replace(&mut styled.dirty_descendants, false);
+        let children_changed = mem::replace(&mut styled.children_changed, false) ||
+            styled.children.len() != styled_children(node).count();
+        let mut changed = false;
+
+        styled.node = node.node_type.clone();
+
+        if hint.element || hint.subtree {
+            let (parent_id, parent) = parent;
+            let (id, styles, computed) = self.node_styles(node, state.cache, &state.ancestors, parent, parent_id, root);
+            changed = styles != styled.styles;
+            styled.id = id;
+            styled.styles = styles;
+            styled.computed = computed;
+            state.restyled += 1;
+        }
+
+        if !(changed || hint.subtree || dirty_descendants || children_changed) {
+            return;
+        }
+
+        // children inherit from a changed node, and every node's lengths depend on the root
+        let force = RestyleHint {
+            element: changed,
+            subtree: hint.subtree || (changed && root.is_none()),
+            siblings: false,
+        };
+        let StyledNode { id, ref styles, ref mut children, .. } = *styled;
+        let root = Some(root.unwrap_or(styles));
+
+        if let NodeType::Element(ref e) = node.node_type {
+            state.ancestors.push(e);
+        }
+
+        if children_changed {
+            *children = styled_children(node)
+                .map(|child| self.style_node(child, state.cache, &mut state.ancestors, styles, id, root))
+                .collect();
+            state.restyled += children.iter().map(StyledNode::count).sum::<usize>();
+        } else {
+            // a sibling hint restyles the later siblings
+            let mut siblings = RestyleHint::default();
+
+            for (child, node) in children.iter_mut().zip(styled_children(node)) {
+                let later = child.dirty.siblings;
+                self.restyle_node(state, child, node, (id, styles), root, force.union(siblings));
+
+                if later {
+                    siblings = RestyleHint { element: true, subtree: true, siblings: false };
+                }
+            }
+        }
+
+        if let NodeType::Element(_) = node.node_type {
+            state.ancestors.pop();
+        }
+    }
+
+    /// Constructs a StyledNode whose inherited properties come from its parent's styles.
+    ///
+    /// cache: The styles elements can share.
+    /// ancestors: The ancestors of the node.
+    /// parent: The styles of the parent node.
+    /// parent_id: The id of the parent's styles, equal ids are equal styles.
+    /// root: The styles of the root node, or None if this node is the root.
+    fn style_node<'a>(&self, node: &'a Node, cache: &mut SharingCache, ancestors: &mut Ancestors<'a>,
+        parent: &PropertyMap, parent_id: usize, root: Option<&PropertyMap>) -> StyledNode {
+        let (id, styles, computed) = self.node_styles(node, cache, ancestors, parent, parent_id, root);
+
+        // recursively style the children with the styles of this node to inherit
         let mut style_children = Vec::new();
 
+        if let NodeType::Element(ref e) = node.node_type {
+            ancestors.push(e);
+        }
+
         for child in &node.children {
             match child.node_type {
-                NodeType::Element(_) => style_children.push(StyledNode::new(&child, ss)),
+                NodeType::Element(_) | NodeType::Text(_) => {
+                    let root = Some(root.unwrap_or(&styles));
+                    style_children.push(self.style_node(child, cache, ancestors, &styles, id, root))
+                },
                 _ => {}
             }
         }
 
+        if let NodeType::Element(_) = node.node_type {
+            ancestors.pop();
+        }
+
         StyledNode {
-            node: node,
-            styles: match node.node_type {
-                NodeType::Element(ref e) => StyledNode::get_styles(e, ss),
-                _ => PropertyMap::new()
-            },
-            children: style_children
+            node: node.node_type.clone(),
+            id,
+            styles,
+            computed,
+            children: style_children,
+            dirty: RestyleHint::default(),
+            dirty_descendants: false,
+            children_changed: false,
         }
     }
 
-    /// Return the styles of the current node.
+    /// Constructs the style tree of a dom tree on a pool of threads.
     ///
-    /// elem: The current node's element data.
-    /// ss: The current stylesheet being applied.
-    fn get_styles(elem: &'a ElementData, ss: &'a Stylesheet) -> PropertyMap<'a> {
-        let mut styles = PropertyMap::new();
-
-        for rule in &ss.rules {
-            for selector in &rule.selectors {
-                if selector_matches(elem, &selector) {
-                    for decl in &rule.declarations {
-                        styles.insert(&decl.property, &decl.value);
-                    }
-                    break;
-                }
+    /// Each task styles a subtree depth first, once the styles of the subtree's parent are
+    /// known, and spawns a task for each subtree under it of at least `TASK_NODES` nodes with
+    /// a copy of its ancestors. The results are kept by the node's position in the tree, which
+    /// the tree is assembled from afterwards, so it is the same however the tasks were scheduled.
+    ///
+    /// cache: The styles elements can share. Each thread starts with an empty cache, and the
+    /// styles the threads cached are added to it afterwards.
+    /// threads: The number of threads to style with.
+    fn style_parallel(&self, root: &Node, cache: &mut SharingCache, threads: usize) -> StyledNode {
+        let tree = FlatTree::new(root);
+        let results: Vec<OnceLock<(usize, PropertyMap, ComputedStyle)>> =
+            tree.nodes.iter().map(|_| OnceLock::new()).collect();
+        let caches = (0..threads).map(|_| SharingCache::default()).collect();
+
+        let caches = pool::run(caches, vec![(0, Ancestors::new())], |cache: &mut SharingCache, (index, mut ancestors), spawner| {
+            self.style_subtree(&tree, &results, cache, index, &mut ancestors, spawner);
+        });
+
+        for worker in caches {
+            cache.stats.lookups += worker.stats.lookups;
+            cache.stats.hits += worker.stats.hits;
+            for (key, styles) in worker.styles {
+                cache.styles.entry(key).or_insert(styles);
             }
         }
 
-        styles
+        let mut results = results.into_iter()
+            .map(OnceLock::into_inner)
+            .collect::<Vec<_>>();
+        tree.assemble(0, &mut results)
     }
 
-    /// Return a style property for the current node.
+    /// Styles a node of a flat tree and its descendants, except the large subtrees under it,
+    /// which are spawned as tasks of their own.
     ///
-    /// name: the property name to return the value of.
-    pub fn value(&self, name: &str) -> Option<&&Value> {
-        self.styles.get(name)
+    /// results: The styles of each node of the tree, set once the node is styled.
+    /// index: The node to style, whose parent is styled already.
+    /// ancestors: The ancestors of the node.
+    fn style_subtree<'a>(&self, tree: &FlatTree<'a>, results: &[OnceLock<(usize, PropertyMap, ComputedStyle)>],
+        cache: &mut SharingCache, index: usize, ancestors: &mut Ancestors<'a>,
+        spawner: &pool::Spawner<(usize, Ancestors<'a>)>) {
+        let (node, parent) = tree.nodes[index];
+        let styled = |i: usize| results[i].get().expect("the parent is styled");
+        let no_styles = PropertyMap::new();
+        let (parent_id, parent_styles) = parent.map_or((0, &no_styles), |p| (styled(p).0, &styled(p).1));
+        let root = parent.map(|_| &styled(0).1);
+
+        let styled = self.node_styles(node, cache, ancestors, parent_styles, parent_id, root);
+        assert!(results[index].set(styled).is_ok(), "a node is styled once");
+
+        if let NodeType::Element(ref elem) = node.node_type {
+            ancestors.push(elem);
+            for &child in &tree.children[index] {
+                if tree.sizes[child] >= TASK_NODES {
+                    spawner.spawn((child, ancestors.clone()));
+                } else {
+                    self.style_subtree(tree, results, cache, child, ancestors, spawner);
+                }
+            }
+            ancestors.pop();
+        }
     }
 
-    /// Return the value of display property of the current node.
-    pub fn get_display(&self) -> Display {
-        match self.value("display") {
-            Some(s) => match **s {
-                Value::Other(ref v) => {
-                    match v.as_ref() {
-                        "block" => Display::Block,
-                        "none" => Display::None,
-                        "inline-block" => Display::InlineBlock,
-                        _ => Display::Inline
-                    }
-                },
-                _ => Display::Inline,
+    /// Returns the styles of a node and their id.
+    fn node_styles(&self, node: &Node, cache: &mut SharingCache, ancestors: &Ancestors, parent: &PropertyMap,
+        parent_id: usize, root: Option<&PropertyMap>) -> (usize, PropertyMap, ComputedStyle) {
+        match node.node_type {
+            NodeType::Element(ref e) => self.element_styles(e, cache, ancestors, parent, parent_id, root),
+            // text has no declarations of its own, only the properties it inherits
+            _ => {
+                let styles = compute_values(&HashMap::new(), parent, root, self.media);
+                let computed = self.compute(&styles, root);
+                (self.new_id(), styles, computed)
             },
-            None => Display::Inline
         }
     }
 
-    /// Return a style property for the current node or a default value.
-    ///
-    /// name: the property name to return the value of.
-    /// default: the value to return if None is found.
-    pub fn num_or(&self, name: &str, default: f32) -> f32 {
-        match self.value(name) {
-            Some(v) => {
-                match **v {
-                    Value::Length(n, _) => n,
-                    _ => default,
-                }
+    /// Returns the styles of an element and their id, from the sharing cache if an element
+    /// with the same key was already styled.
+    fn element_styles(&self, elem: &ElementData, cache: &mut SharingCache, ancestors: &Ancestors,
+        parent: &PropertyMap, parent_id: usize, root: Option<&PropertyMap>) -> (usize, PropertyMap, ComputedStyle) {
+        let mut classes = elem.get_classes().into_iter().map(String::from).collect::<Vec<String>>();
+        classes.sort();
+
+        let key = SharingKey {
+            tag_name: elem.tag_name.clone(),
+            id: elem.get_id().cloned(),
+            classes,
+            style: elem.get_style().cloned(),
+            parent: parent_id,
+        };
+
+        cache.stats.lookups += 1;
+        if let Some(&(id, ref styles, ref computed)) = cache.styles.get(&key) {
+            cache.stats.hits += 1;
+            return (id, styles.clone(), computed.clone());
+        }
+
+        let styles = StyledNode::get_styles(elem, &self.index, ancestors, self.media, parent, root);
+        let computed = self.compute(&styles, root);
+        let id = self.new_id();
+
+        cache.styles.insert(key, (id, styles.clone(), computed.clone()));
+        (id, styles, computed)
+    }
+
+    /// Returns the typed style of a node from its computed values.
+    fn compute(&self, styles: &PropertyMap, root: Option<&PropertyMap>) -> ComputedStyle {
+        ComputedStyle::new(styles, &length_context(styles, root.unwrap_or(styles), self.media))
+    }
+
+    /// Returns an id for styles that aren't shared with any other node yet.
+    fn new_id(&self) -> usize {
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// The state of a restyle of a style tree.
+struct Restyle<'a, 'c> {
+    cache: &'c mut SharingCache,
+    ancestors: Ancestors<'a>, // the ancestors of the node being restyled
+    restyled: usize, // the number of nodes styled so far
+}
+
+/// Returns the children of a dom node that are styled, its elements and text.
+fn styled_children(node: &Node) -> impl Iterator<Item = &Node> {
+    node.children.iter().filter(|child| matches!(child.node_type, NodeType::Element(_) | NodeType::Text(_)))
+}
+
+/// The styled nodes of a dom tree in document order, which the parallel traversal keeps its
+/// results by.
+struct FlatTree<'a> {
+    nodes: Vec<(&'a Node, Option<usize>)>, // and the index of the parent
+    children: Vec<Vec<usize>>,
+    sizes: Vec<usize>, // the number of nodes in the subtree under each node, itself included
+}
+
+impl<'a> FlatTree<'a> {
+    /// Constructs the flat tree of the element and text nodes under a root.
+    fn new(root: &'a Node) -> FlatTree<'a> {
+        let mut tree = FlatTree { nodes: Vec::new(), children: Vec::new(), sizes: Vec::new() };
+        tree.push(root, None);
+        tree
+    }
+
+    /// Adds a node and its descendants, returning the index of the node.
+    fn push(&mut self, node: &'a Node, parent: Option<usize>) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push((node, parent));
+        self.children.push(Vec::new());
+        self.sizes.push(1);
+
+        for child in &node.children {
+            if let NodeType::Element(_) | NodeType::Text(_) = child.node_type {
+                let child = self.push(child, Some(index));
+                self.children[index].push(child);
             }
-            None => default,
+        }
+
+        // the subtree's nodes were pushed after the node
+        self.sizes[index] = self.nodes.len() - index;
+        index
+    }
+
+    /// Constructs the style tree under a node from the styles of each node.
+    ///
+    /// results: The styles of each node, which are moved into the style tree.
+    fn assemble(&self, index: usize, results: &mut [Option<(usize, PropertyMap, ComputedStyle)>]) -> StyledNode {
+        let (id, styles, computed) = results[index].take().expect("every node is styled");
+
+        StyledNode {
+            node: self.nodes[index].0.node_type.clone(),
+            id,
+            styles,
+            computed,
+            children: self.children[index].iter().map(|&child| self.assemble(child, results)).collect(),
+            dirty: RestyleHint::default(),
+            dirty_descendants: false,
+            children_changed: false,
+        }
+    }
+}
+
+impl SharingStats {
+    /// Returns the fraction of lookups that reused styles, or 0 if there were none.
+    pub fn hit_rate(&self) -> f32 {
+        if self.lookups == 0 {
+            0.0
+        } else {
+            self.hits as f32 / self.lookups as f32
         }
     }
 }
-impl<'a> fmt::Debug for StyledNode<'a> {
+
+impl fmt::Debug for StyledNode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}: {:?}", self.node, self.styles)
     }
 }
 
-/// Utility to check if a selector matches a dom node.
+impl fmt::Debug for TracedRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Specificity(a, b, c) = self.specificity;
+
+        match (self.selector.as_ref(), self.location.as_ref()) {
+            (Some(selector), Some(location)) => {
+                writeln!(f, "{} ({}, {}, {}) {:?} {}", selector, a, b, c, self.origin, location)?
+            },
+            _ => writeln!(f, "style attribute")?,
+        }
+        for decl in &self.declarations {
+            let important = if decl.important { " !important" } else { "" };
+            let overridden = if decl.overridden { " (overridden)" } else { "" };
+            writeln!(f, "    {}: {:?}{}{}", decl.property, decl.value, important, overridden)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the built-in user agent stylesheet, which is applied before every other stylesheet.
+/// It gives html elements their default display types, margins and fonts.
+pub fn user_agent_stylesheet() -> &'static Stylesheet {
+    static STYLESHEET: OnceLock<Stylesheet> = OnceLock::new();
+
+    STYLESHEET.get_or_init(|| {
+        let mut stylesheet = CssParser::new(include_str!("ua.css")).parse_stylesheet();
+        stylesheet.origin = Origin::UserAgent;
+        stylesheet
+    })
+}
+
+/// Returns the declarations of an element's style attribute, which are part of the author's
+/// styles.
+fn inline_declarations(elem: &ElementData) -> Vec<Declaration> {
+    elem.get_style().map_or_else(Vec::new, |style| CssParser::new(style).parse_declaration_list())
+}
+
+/// Returns the declarations of the rules that match an element and of its style attribute,
+/// sorted by their precedence in the cascade, lowest first.
 ///
-/// elem: The element data of the dom node to match.
-/// sel: The selector to match.
-fn selector_matches(elem: &ElementData, sel: &Selector) -> bool {
-    for simple in &sel.simple {
-        let mut sel_match = true;
-
-        match simple.tag_name {
-            Some(ref t) => {
-                if *t != elem.tag_name {
-                    continue;
-                }
+/// rules: The rules that match the element.
+/// inline: The declarations of the element's style attribute.
+fn cascade<'a>(rules: &[MatchedRule<'a>], inline: &'a [Declaration]) -> Vec<MatchedDeclaration<'a>> {
+    let mut matched = Vec::new();
+
+    for m in rules {
+        for decl in &m.rule.declarations {
+            matched.push(MatchedDeclaration {
+                level: cascade_level(m.origin, decl.important),
+                origin: m.origin,
+                inline: false,
+                specificity: m.specificity,
+                order: m.order,
+                declaration: decl,
+            });
+        }
+    }
+
+    for decl in inline {
+        matched.push(MatchedDeclaration {
+            level: cascade_level(Origin::Author, decl.important),
+            origin: Origin::Author,
+            inline: true,
+            specificity: Specificity::default(),
+            order: 0,
+            declaration: decl,
+        });
+    }
+
+    // the sort is stable so declarations within a rule keep their order
+    matched.sort_by_key(|m| (m.level, m.inline, m.specificity, m.order));
+    matched
+}
+
+/// Rolls back a declaration of revert to the value its property would have without the
+/// declarations of its origin, or unset if no lower origin declares the property.
+///
+/// by_origin: The winning declarations of each origin.
+fn revert<'v>(property: &str, origin: Origin, value: &'v Value, by_origin: &[HashMap<&str, &'v Value>; 3])
+    -> &'v Value {
+    static UNSET: Value = Value::Keyword(Keyword::Unset);
+    let (mut value, mut index) = (value, origin as usize);
+
+    while *value == Value::Keyword(Keyword::Revert) {
+        if index == 0 {
+            return &UNSET;
+        }
+        index -= 1;
+        if let Some(lower) = by_origin[index].get(property) {
+            value = lower;
+        }
+    }
+
+    value
+}
+
+/// Computes the values of the declarations that won the cascade for an element.
+///
+/// Custom properties are inherited from the parent and var() references are substituted.
+/// A declaration whose references can't be substituted, or whose substituted value is
+/// invalid, is invalid at computed-value time and computes to unset.
+///
+/// The css-wide keywords resolve to the parent's value or the initial value, and inherited
+/// properties without a declaration take the parent's value. Font sizes and weights compute
+/// to absolute values, so descendants inherit them rather than relative values that would
+/// compound, and the color currentcolor is the parent's color.
+///
+/// declared: The winning declared value of each property.
+/// parent: The styles of the parent node.
+/// root: The styles of the root node, or None if this node is the root.
+/// media: The device viewport relative font sizes resolve against.
+fn compute_values(declared: &HashMap<&str, &Value>, parent: &PropertyMap, root: Option<&PropertyMap>,
+    media: &MediaEnvironment) -> PropertyMap {
+    let mut specified = HashMap::new();
+
+    for (name, value) in parent.iter().filter(|&(name, _)| name.starts_with("--")) {
+        specified.insert(&name[..], value);
+    }
+    for (&name, &value) in declared.iter().filter(|&(name, _)| name.starts_with("--")) {
+        match *value {
+            Value::Other(ref text) if text == "initial" => { specified.remove(name); },
+            // custom properties always inherit
+            Value::Other(ref text) if matches!(&text[..], "inherit" | "unset" | "revert") => {},
+            _ => { specified.insert(name, value); },
+        }
+    }
+
+    let mut computed = PropertyMap::new();
+    let names = specified.keys().cloned().collect::<Vec<&str>>();
+    let mut resolver = CustomResolver {
+        specified,
+        resolved: HashMap::new(),
+        stack: Vec::new(),
+        cyclic: HashSet::new(),
+    };
+
+    for name in names {
+        if let Some(text) = resolver.resolve(name) {
+            computed.insert(name.to_string(), Value::Other(text));
+        }
+    }
+
+    for (&name, &value) in declared.iter().filter(|&(name, _)| !name.starts_with("--")) {
+        let value = match *value {
+            Value::Var(ref property, ref text) => {
+                substitute_vars(text, &mut |n| resolver.resolve(n))
+                    .and_then(|substituted| parse_substituted(name, property, &substituted))
+                    .unwrap_or(Value::Keyword(Keyword::Unset))
             },
-            None => {}
+            ref v => v.clone(),
         };
 
-        match elem.get_id() {
-            Some(i) => {
-                match simple.id {
-                    Some(ref id) => {
-                        if *i != *id {
-                            continue;
-                        }
-                    },
-                    None => {}
-                }
+        let value = match value {
+            Value::Keyword(Keyword::Inherit) => parent.get(name).cloned().or_else(|| initial_value(name)),
+            Value::Keyword(Keyword::Unset) | Value::Keyword(Keyword::Revert) if is_inherited(name) => {
+                parent.get(name).cloned().or_else(|| initial_value(name))
             },
-            None => {
-                match simple.id {
-                    Some(_) => { continue; },
-                    _ => {}
-                }
+            Value::Keyword(Keyword::Initial) |
+            Value::Keyword(Keyword::Unset) | Value::Keyword(Keyword::Revert) => initial_value(name),
+            v => Some(v),
+        };
+
+        if let Some(value) = value {
+            computed.insert(name.to_string(), value);
+        }
+    }
+
+    for (name, value) in parent.iter().filter(|&(name, _)| !name.starts_with("--") && is_inherited(name)) {
+        computed.entry(name.clone()).or_insert_with(|| value.clone());
+    }
+
+    if let Some(size) = computed.get_mut("font-size") {
+        let lengths = length_context(parent, root.unwrap_or(parent), media);
+        *size = Value::Length(lengths.font_size(size), Unit::Px);
+    }
+    if let Some(weight) = computed.get_mut("font-weight") {
+        let parent_weight = match parent.get("font-weight") {
+            Some(&Value::Number(n)) => n,
+            _ => 400.0,
+        };
+        *weight = Value::Number(font_weight(weight, parent_weight));
+    }
+    // lengths and percentages inherit as px, unlike numbers which scale with each font size
+    let lengths = length_context(&computed, root.unwrap_or(&computed), media);
+    if let Some(height) = computed.get_mut("line-height") {
+        let number = match *height {
+            Value::Keyword(_) | Value::Number(_) => true,
+            Value::Calc(ref node) => node.calc_type() == Some(CalcType::Number),
+            _ => false,
+        };
+        if !number {
+            if let Some(px) = lengths.resolve(height, Some(lengths.font_size)) {
+                *height = Value::Length(px, Unit::Px);
             }
         }
+    }
+    if computed.get("color") == Some(&Value::Keyword(Keyword::CurrentColor)) {
+        match parent.get("color") {
+            Some(color) => computed.insert(String::from("color"), color.clone()),
+            None => computed.remove("color"),
+        };
+    }
 
-        let elem_classes = elem.get_classes();
+    computed
+}
 
-        for class in &simple.classes {
-            sel_match &= elem_classes.contains::<str>(class);
+/// Returns what the relative lengths of an element resolve against.
+///
+/// styles: The computed values of the element.
+/// root: The computed values of the root element.
+/// media: The device whose viewport the viewport units resolve against.
+fn length_context(styles: &PropertyMap, root: &PropertyMap, media: &MediaEnvironment) -> LengthContext {
+    let font_size = |styles: &PropertyMap| match styles.get("font-size") {
+        Some(&Value::Length(px, Unit::Px)) => px,
+        _ => media.font_size,
+    };
+
+    LengthContext {
+        font_size: font_size(styles),
+        root_font_size: font_size(root),
+        viewport_width: media.width,
+        viewport_height: media.height,
+    }
+}
+
+impl<'a> CustomResolver<'a> {
+    /// Returns the value of a custom property with its references substituted, or None if
+    /// it has no value or is invalid at computed-value time.
+    ///
+    /// Every property in a reference cycle is invalid, even if its references have fallbacks.
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        if let Some(resolved) = self.resolved.get(name) {
+            return resolved.clone();
         }
 
-        if sel_match {
-            return true;
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            self.cyclic.extend(self.stack[start..].iter().cloned());
+            return None;
         }
+
+        let value = match self.specified.get(name).cloned() {
+            Some(Value::Other(text)) => Some(text.clone()),
+            Some(Value::Var(_, text)) => {
+                self.stack.push(name.to_string());
+                let substituted = substitute_vars(text, &mut |n| self.resolve(n));
+                self.stack.pop();
+                substituted
+            },
+            _ => None,
+        };
+        let value = value.filter(|_| !self.cyclic.contains(name));
+
+        self.resolved.insert(name.to_string(), value.clone());
+        value
     }
+}
 
-    false
+/// Returns the precedence of a declaration's origin and importance, lowest first.
+///
+/// Important declarations reverse the order of the origins.
+fn cascade_level(origin: Origin, important: bool) -> usize {
+    match (origin, important) {
+        (Origin::UserAgent, false) => 0,
+        (Origin::User, false) => 1,
+        (Origin::Author, false) => 2,
+        (Origin::Author, true) => 3,
+        (Origin::User, true) => 4,
+        (Origin::UserAgent, true) => 5,
+    }
 }
 
 /// Print a styled node and it's descendents with indentation
@@ -184,17 +997,18 @@ pub fn pretty_print(n: &StyledNode, indent_size: usize) {
 
 //  TODO
 //  -make things case insensitive.
-//  -parse element attributes into styles
-//  -parse <style> elements into style sheets
-//  -computed values
-//  -inheritance
 //  -write tests
-//  -support multiple stylesheets
 
 /// Tests ----------------------------------------------------------------------
 #[cfg(test)]
 mod tests {
     use super::*;
+    use computed::{Display, LineHeight};
+    use css::Color;
+    use css_parse::CssParser;
+    use html_parse::HtmlParser;
+    use invalidation::changed_rules;
+    use layout::Dimensions;
 
     /// Test
     #[test]
@@ -213,4 +1027,577 @@ mod tests {
     fn simple_match_class() {
 
     }
+
+    /// Parse a stylesheet and an element with the given id and classes.
+    fn setup(css: &str, tag: &str, id: Option<&str>, classes: &str) -> (Stylesheet, Node) {
+        let mut attrs = HashMap::new();
+        if let Some(i) = id {
+            attrs.insert(String::from("id"), String::from(i));
+        }
+        if !classes.is_empty() {
+            attrs.insert(String::from("class"), String::from(classes));
+        }
+
+        let elem = ElementData::new(String::from(tag), attrs);
+        let node = Node::new(NodeType::Element(elem), vec![]);
+
+        (CssParser::new(css).parse_stylesheet(), node)
+    }
+
+    /// Test a more specific rule wins over a later, less specific one.
+    #[test]
+    fn cascade_specificity() {
+        let (ss, node) = setup("#main { width: 1px; } .a { width: 2px; } div { width: 3px; }",
+            "div", Some("main"), "a");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(1.0, styled.num_or("width", 0.0));
+    }
+
+    /// Test source order breaks ties between equally specific rules.
+    #[test]
+    fn cascade_source_order() {
+        let (ss, node) = setup(".a { width: 1px; height: 5px; } .b { width: 2px; }",
+            "div", None, "a b");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(2.0, styled.num_or("width", 0.0));
+        assert_eq!(5.0, styled.num_or("height", 0.0));
+    }
+
+    /// Test a rule applies with the specificity of its most specific matching selector.
+    #[test]
+    fn cascade_selector_list() {
+        let (ss, node) = setup("div, #main { width: 1px; } .a { width: 2px; }",
+            "div", Some("main"), "a");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(1.0, styled.num_or("width", 0.0));
+    }
+
+    /// Parse a stylesheet with the given origin.
+    fn sheet(css: &str, origin: Origin) -> Stylesheet {
+        let mut ss = CssParser::new(css).parse_stylesheet();
+        ss.origin = origin;
+        ss
+    }
+
+    /// Test author declarations win over user and user agent declarations.
+    #[test]
+    fn cascade_origin_normal() {
+        let (_, node) = setup("", "div", Some("main"), "");
+        let sheets = vec![
+            sheet("div { width: 1px; }", Origin::Author),
+            sheet("#main { width: 2px; }", Origin::User),
+            sheet("#main { width: 3px; }", Origin::UserAgent),
+        ];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(1.0, styled.num_or("width", 0.0));
+    }
+
+    /// Test important declarations reverse origin precedence.
+    #[test]
+    fn cascade_origin_important() {
+        let (_, node) = setup("", "div", Some("main"), "");
+        let author = sheet("#main { width: 1px !important; height: 1px !important; }", Origin::Author);
+        let user = sheet("div { width: 2px !important; height: 2px; }", Origin::User);
+        let sheets = vec![author, user];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(2.0, styled.num_or("width", 0.0));
+        assert_eq!(1.0, styled.num_or("height", 0.0));
+    }
+
+    /// Test an important declaration beats a more specific normal one.
+    #[test]
+    fn cascade_important_specificity() {
+        let (ss, node) = setup("div { width: 1px !important; } #main { width: 2px; }",
+            "div", Some("main"), "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(1.0, styled.num_or("width", 0.0));
+    }
+
+    /// Test @media rules apply only while the viewport matches them.
+    #[test]
+    fn cascade_media() {
+        let (ss, node) = setup("div { width: 1px; }
+            @media screen and (min-width: 600px) { div { width: 2px; } }
+            @media print { div { width: 3px; } }", "div", None, "");
+        let sheets = vec![ss];
+        let mut viewport = Dimensions::default();
+
+        viewport.content.width = 400.0;
+        let narrow = StyledNode::new(&node, &sheets, &MediaEnvironment::new(&viewport));
+        assert_eq!(1.0, narrow.num_or("width", 0.0));
+
+        viewport.content.width = 1024.0;
+        let wide = StyledNode::new(&node, &sheets, &MediaEnvironment::new(&viewport));
+        assert_eq!(2.0, wide.num_or("width", 0.0));
+    }
+
+    /// Parse a stylesheet and a div containing a p, both with the given classes.
+    fn setup_tree(css: &str, parent_classes: &str, child_classes: &str) -> (Stylesheet, Node) {
+        let (_, child) = setup("", "p", None, child_classes);
+        let (ss, parent) = setup(css, "div", None, parent_classes);
+
+        (ss, Node::new(parent.node_type, vec![child]))
+    }
+
+    /// Test custom properties are inherited and substituted into declarations.
+    #[test]
+    fn var_inheritance() {
+        let (ss, node) = setup_tree(":root, div { --w: 1px; --m: 2px 3px; }
+            p { --w: 4px; width: var(--w); height: var(--missing, var(--w)); margin: var(--m); }",
+            "", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let child = &styled.children[0];
+
+        assert_eq!(Some(&Value::Other(String::from("1px"))), styled.value("--w"));
+        assert_eq!(4.0, child.num_or("width", 0.0));
+        assert_eq!(4.0, child.num_or("height", 0.0));
+        assert_eq!(2.0, child.num_or("margin-top", 0.0));
+        assert_eq!(3.0, child.num_or("margin-left", 0.0));
+        assert_eq!(Some(&Value::Other(String::from("2px 3px"))), child.value("--m"));
+    }
+
+    /// Test custom properties with references are resolved on the element that declares them.
+    #[test]
+    fn var_custom_references() {
+        let (ss, node) = setup_tree("div { --a: 1px; --b: var(--a) } .x { --a: 5px; --c: initial; width: var(--b); }",
+            "", "x");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(Some(&Value::Other(String::from("1px"))), styled.value("--b"));
+        assert_eq!(1.0, styled.children[0].num_or("width", 0.0));
+        assert_eq!(None, styled.children[0].value("--c"));
+    }
+
+    /// Test reference cycles and invalid substitutions are invalid at computed-value time.
+    #[test]
+    fn var_invalid() {
+        let (ss, node) = setup("div { --a: var(--b, 1px); --b: var(--a, 2px); --c: var(--a, 3px);
+            --color: red; width: var(--a); height: var(--color); margin: var(--missing); }
+            div { padding-left: var(--c); }", "div", None, "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let auto = Value::Keyword(Keyword::Auto);
+
+        // invalid declarations compute to unset, the initial value of these properties
+        assert_eq!(None, styled.value("--a"));
+        assert_eq!(None, styled.value("--b"));
+        assert_eq!(Some(&Value::Other(String::from("3px"))), styled.value("--c"));
+        assert_eq!(Some(&auto), styled.value("width"));
+        assert_eq!(Some(&auto), styled.value("height"));
+        assert_eq!(Some(&Value::Length(0.0, Unit::Px)), styled.value("margin-top"));
+        assert_eq!(3.0, styled.num_or("padding-left", 0.0));
+    }
+
+    /// Test inherited properties propagate to children and other properties don't.
+    #[test]
+    fn inherit_propagation() {
+        let (ss, node) = setup_tree("div { color: red; width: 5px; text-align: center; }
+            p { text-align: left; }", "", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let child = &styled.children[0];
+        let red = Value::Color(Color::new(1.0, 0.0, 0.0, 1.0));
+
+        assert_eq!(Some(&red), child.value("color"));
+        assert_eq!(None, child.value("width"));
+        assert_eq!(Some(&Value::Keyword(Keyword::Left)), child.value("text-align"));
+    }
+
+    /// Test inherit, initial and unset resolve against the parent and the initial values.
+    #[test]
+    fn inherit_keywords() {
+        let (ss, node) = setup_tree("div { width: 5px; color: red; }
+            p { width: inherit; color: initial; margin-left: unset; padding-left: inherit; }
+            .unset { color: unset; width: unset; }", "", "unset");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let child = &styled.children[0];
+
+        assert_eq!(Some(&Value::Color(Color::new(1.0, 0.0, 0.0, 1.0))), child.value("color"));
+        assert_eq!(Some(&Value::Keyword(Keyword::Auto)), child.value("width"));
+        assert_eq!(Some(&Value::Length(0.0, Unit::Px)), child.value("margin-left"));
+        assert_eq!(Some(&Value::Length(0.0, Unit::Px)), child.value("padding-left"));
+
+        let (ss, node) = setup_tree("div { width: 5px; color: red; }
+            p { width: inherit; color: initial; }", "", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let child = &styled.children[0];
+
+        assert_eq!(Some(&Value::Length(5.0, Unit::Px)), child.value("width"));
+        assert_eq!(Some(&Value::Color(Color::new(0.0, 0.0, 0.0, 1.0))), child.value("color"));
+    }
+
+    /// Test revert rolls back to the declarations of lower origins.
+    #[test]
+    fn inherit_revert() {
+        let (_, node) = setup("", "p", Some("main"), "");
+        let sheets = vec![
+            sheet("#main { width: 1px; height: 1px; }", Origin::User),
+            sheet("p { width: revert; height: revert; margin-top: revert; color: revert; }", Origin::Author),
+            sheet("#main { height: revert; }", Origin::User),
+        ];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(1.0, styled.num_or("width", 0.0));
+        // reverting past the user origin falls back to the user agent's declarations, or to
+        // unset when there are none
+        assert_eq!(Some(&Value::Length(1.0, Unit::Em)), styled.value("margin-top"));
+        assert_eq!(Some(&Value::Keyword(Keyword::Auto)), styled.value("height"));
+        assert_eq!(Some(&Value::Color(Color::new(0.0, 0.0, 0.0, 1.0))), styled.value("color"));
+    }
+
+    /// Test font sizes compute to pixels, so relative sizes don't compound when inherited.
+    #[test]
+    fn inherit_font_size() {
+        let (ss, node) = setup_tree("div { font-size: 2em; } p { margin-top: 1em; }", "", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let child = &styled.children[0];
+
+        assert_eq!(Some(&Value::Length(32.0, Unit::Px)), styled.value("font-size"));
+        assert_eq!(Some(&Value::Length(32.0, Unit::Px)), child.value("font-size"));
+
+        let (ss, node) = setup_tree("div { font-size: larger; } p { font-size: 2rem; }", "", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(Some(&Value::Length(38.4, Unit::Px)), styled.children[0].value("font-size"));
+    }
+
+    /// Test line heights in lengths and percentages inherit as pixels, and numbers inherit
+    /// as numbers that scale with the child's font size.
+    #[test]
+    fn inherit_line_height() {
+        let (ss, node) = setup_tree("div { font-size: 10px; line-height: 150%; } p { font-size: 20px; }", "", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(LineHeight::Px(15.0), styled.computed().line_height);
+        assert_eq!(LineHeight::Px(15.0), styled.children[0].computed().line_height);
+
+        let (ss, node) = setup_tree("div { font-size: 10px; line-height: 2em; } p { font-size: 20px; line-height: 1.5; }", "", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(Some(&Value::Length(20.0, Unit::Px)), styled.value("line-height"));
+        assert_eq!(LineHeight::Number(1.5), styled.children[0].computed().line_height);
+    }
+
+    /// Test the typed style holds the computed values, with currentcolor and relative weights
+    /// resolved against the parent.
+    #[test]
+    fn computed_style() {
+        let (ss, node) = setup_tree("div { color: red; font-weight: bold; margin-left: 2em; font-size: 10px; }
+            p { color: currentcolor; font-weight: bolder; border-top-width: 1em;
+            border-top-style: solid; }", "", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let child = styled.children[0].computed();
+        let red = Color::new(1.0, 0.0, 0.0, 1.0);
+
+        assert_eq!(Some(20.0), styled.computed().margin.left.resolve(None));
+        assert_eq!(red, child.color);
+        assert_eq!(red, child.border_color.top);
+        assert_eq!(10.0, child.border_width.top);
+        assert_eq!(900.0, child.font_weight);
+    }
+
+    /// Test text nodes are kept in the style tree with the properties they inherit.
+    #[test]
+    fn text_nodes() {
+        let (ss, div) = setup("div { color: red; width: 5px; --x: 1px; }", "div", None, "");
+        let text = Node::new(NodeType::Text(String::from("hello")), vec![]);
+        let comment = Node::new(NodeType::Comment(String::from("note")), vec![]);
+        let node = Node::new(div.node_type, vec![text, comment]);
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let text = &styled.children[0];
+
+        assert_eq!(1, styled.children.len());
+        assert_eq!(Some("hello"), text.text());
+        assert_eq!(None, styled.text());
+        assert_eq!(Color::new(1.0, 0.0, 0.0, 1.0), text.computed().color);
+        assert_eq!(None, text.value("width"));
+        assert_eq!(Some(&Value::Other(String::from("1px"))), text.value("--x"));
+    }
+
+    /// Test siblings and cousins with the same key share styles, and elements that differ
+    /// in classes, style attribute or parent style don't.
+    #[test]
+    fn sharing_cache() {
+        let elem = |tag: &str, classes: &str, style: Option<&str>, children| {
+            let mut attrs = HashMap::new();
+            attrs.insert(String::from("class"), String::from(classes));
+            if let Some(style) = style {
+                attrs.insert(String::from("style"), String::from(style));
+            }
+            Node::new(NodeType::Element(ElementData::new(String::from(tag), attrs)), children)
+        };
+        let row = |wide| elem("tr", if wide { "wide" } else { "" }, None,
+            vec![elem("td", "a b", None, vec![]), elem("td", "b a", None, vec![]), elem("td", "c", None, vec![])]);
+        let node = elem("table", "", None, vec![row(false), row(false), row(true),
+            elem("tr", "", Some("width: 9px"), vec![])]);
+        let sheets = vec![CssParser::new("td { width: 1px; } .wide { font-size: 20px; } .c { width: 2px; }
+            .b { height: 3em; }").parse_stylesheet()];
+        let media = MediaEnvironment::default();
+        let mut context = StyleContext::new(&sheets, &media);
+        let styled = context.style(&node);
+        let rows = &styled.children;
+
+        assert_eq!(SharingStats { lookups: 14, hits: 6 }, context.stats);
+        assert_eq!(6.0 / 14.0, context.stats.hit_rate());
+        assert_eq!(48.0, rows[1].children[1].computed().height.resolve(None).unwrap());
+        assert_eq!(60.0, rows[2].children[0].computed().height.resolve(None).unwrap());
+        assert_eq!(2.0, rows[2].children[2].num_or("width", 0.0));
+        assert_eq!(9.0, rows[3].num_or("width", 0.0));
+        assert_eq!(0.0, SharingStats::default().hit_rate());
+    }
+
+    /// Test tracing an element lists its rules in cascade order with overridden declarations.
+    #[test]
+    fn trace_cascade() {
+        let nodes = HtmlParser::new("<div id=\"main\"><p class=\"a\" style=\"color: blue\">x</p><p>y</p></div>").parse_nodes();
+        let mut user = CssParser::new("p { height: 1px !important; width: 1px; }").parse_stylesheet();
+        user.origin = Origin::User;
+        let sheets = vec![user, CssParser::new("#main p { width: 2px; color: red; }\n.a { height: 3px; width: 3px; }")
+            .parse_stylesheet()];
+        let media = MediaEnvironment::default();
+        let context = StyleContext::new(&sheets, &media);
+        let trace = context.trace(&nodes[0], &[0]).unwrap();
+        let authored = trace.iter().filter(|r| r.origin != Origin::UserAgent).collect::<Vec<_>>();
+        let dump = authored.iter().map(|r| format!("{:?}", r)).collect::<String>();
+
+        assert_eq!(Origin::UserAgent, trace[0].origin);
+        assert_eq!("p (0, 0, 1) User 1:1\n    height: 1px !important\n    width: 1px (overridden)\n\
+            .a (0, 1, 0) Author 2:1\n    height: 3px (overridden)\n    width: 3px (overridden)\n\
+            #main p (1, 0, 1) Author 1:1\n    width: 2px\n    color: r: 1 g: 0 b: 0 a: 1 (overridden)\n\
+            style attribute\n    color: r: 0 g: 0 b: 1 a: 1\n", dump);
+        assert!(context.trace(&nodes[0], &[0, 0]).is_none());
+        assert!(context.trace(&nodes[0], &[5]).is_none());
+    }
+
+    /// Test the computed dump lists every property sorted by name, with relative lengths in
+    /// pixels and initial values filled in.
+    #[test]
+    fn computed_dump() {
+        let (ss, node) = setup_tree("div { font-size: 10px; --x: 1; } p { width: 2em; max-width: 50%; }", "", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let dump = styled.children[0].computed_dump();
+        let lines = dump.lines().collect::<Vec<&str>>();
+
+        assert_eq!(Some(&"--x: \"1\""), lines.first());
+        for line in &["display: block", "font-size: 10px", "width: 20px", "margin-top: 10px",
+            "margin-left: 0px", "max-width: 50%", "height: auto", "position: static",
+            "border-top-width: 0px", "line-height: normal"] {
+            assert!(lines.contains(line), "{} missing from\n{}", line, dump);
+        }
+        let mut sorted = lines.clone();
+        sorted.sort();
+        assert_eq!(sorted, lines);
+    }
+
+    /// Assert two style trees have the same nodes and styles.
+    fn assert_same_tree(a: &StyledNode, b: &StyledNode) {
+        assert!(a.node == b.node);
+        assert_eq!(a.styles, b.styles);
+        assert_eq!(a.computed, b.computed);
+        assert_eq!(a.children.len(), b.children.len());
+        for (a, b) in a.children.iter().zip(&b.children) {
+            assert_same_tree(a, b);
+        }
+    }
+
+    /// Return the element at a path of child indices from a dom node.
+    fn element_at<'n>(node: &'n mut Node, path: &[usize]) -> &'n mut ElementData {
+        let node = path.iter().fold(node, |node, &i| &mut node.children[i]);
+        match node.node_type {
+            NodeType::Element(ref mut e) => e,
+            _ => panic!("not an element"),
+        }
+    }
+
+    /// Parse the document the restyle tests change.
+    fn restyle_document() -> Node {
+        HtmlParser::new("<html><body><div class=\"x\"><p>one</p><p class=\"y\">two</p></div>\
+            <div><p>three</p></div></body></html>").parse_nodes().remove(0)
+    }
+
+    /// Test changed attributes restyle only the nodes their dependencies could affect.
+    #[test]
+    fn restyle_attributes() {
+        let mut node = restyle_document();
+        let sheets = vec![CssParser::new(".x p { width: 1px; } .z { color: red; } .y { height: 2px; }
+            p.w { width: 5px; }").parse_stylesheet()];
+        let media = MediaEnvironment::default();
+        let mut context = StyleContext::new(&sheets, &media);
+        let mut styled = context.style(&node);
+
+        assert_eq!(0, context.restyle(&mut styled, &node));
+
+        // .z only matches the div, whose color its descendants inherit
+        let old = element_at(&mut node, &[0, 1]).clone();
+        element_at(&mut node, &[0, 1]).set_attribute("class", "z");
+        context.element_changed(&mut styled, &[0, 1], &old, element_at(&mut node, &[0, 1]));
+        assert_eq!(3, context.restyle(&mut styled, &node));
+        let text = &styled.children[0].children[1].children[0].children[0];
+        assert_eq!(Color::new(1.0, 0.0, 0.0, 1.0), text.computed().color);
+
+        // .x matches the ancestors of p elements, so the whole subtree is restyled
+        let old = element_at(&mut node, &[0, 0]).clone();
+        element_at(&mut node, &[0, 0]).remove_attribute("class");
+        context.element_changed(&mut styled, &[0, 0], &old, element_at(&mut node, &[0, 0]));
+        assert_eq!(5, context.restyle(&mut styled, &node));
+        assert_eq!(None, styled.children[0].children[0].children[0].value("width"));
+
+        // no selector depends on the title attribute
+        let old = element_at(&mut node, &[0, 0, 1]).clone();
+        element_at(&mut node, &[0, 0, 1]).set_attribute("title", "w");
+        context.element_changed(&mut styled, &[0, 0, 1], &old, element_at(&mut node, &[0, 0, 1]));
+        assert_eq!(0, context.restyle(&mut styled, &node));
+
+        assert_same_tree(&StyleContext::new(&sheets, &media).style(&node), &styled);
+    }
+
+    /// Test added children are styled and their siblings left alone.
+    #[test]
+    fn restyle_children() {
+        let mut node = restyle_document();
+        let sheets = vec![CssParser::new(".y { height: 2px; }").parse_stylesheet()];
+        let media = MediaEnvironment::default();
+        let mut context = StyleContext::new(&sheets, &media);
+        let mut styled = context.style(&node);
+
+        let mut attrs = HashMap::new();
+        attrs.insert(String::from("class"), String::from("y"));
+        let p = Node::new(NodeType::Element(ElementData::new(String::from("p"), attrs)), vec![]);
+        node.children[0].children[1].children.push(p);
+        styled.invalidate_children(&[0, 1]);
+
+        assert_eq!(3, context.restyle(&mut styled, &node));
+        assert_eq!(2.0, styled.children[0].children[1].children[1].num_or("height", 0.0));
+        assert_same_tree(&StyleContext::new(&sheets, &media).style(&node), &styled);
+    }
+
+    /// Test a stylesheet change restyles the elements the changed rules could match.
+    #[test]
+    fn restyle_stylesheets() {
+        let node = restyle_document();
+        let sheets = vec![CssParser::new(".x p { width: 1px; } .y { height: 2px; }").parse_stylesheet()];
+        let media = MediaEnvironment::default();
+        let mut styled = StyleContext::new(&sheets, &media).style(&node);
+
+        let new_sheets = vec![CssParser::new(".x p { width: 1px; } .y { height: 3px; }").parse_stylesheet()];
+        assert!(styled.invalidate_rules(&changed_rules(&sheets, &new_sheets)));
+
+        assert_eq!(2, StyleContext::new(&new_sheets, &media).restyle(&mut styled, &node));
+        assert_eq!(3.0, styled.children[0].children[0].children[1].num_or("height", 0.0));
+        assert_same_tree(&StyleContext::new(&new_sheets, &media).style(&node), &styled);
+    }
+
+    /// Test the parallel traversal constructs the same style tree as the sequential one.
+    #[test]
+    fn parallel_traversal() {
+        let html = (0..20).map(|i| format!("<div class=\"c{}\"><p id=\"p{}\">text {}<span>a</span></p>
+            <ul><li style=\"width: {}px\">b</li><li>c</li></ul></div>", i % 3, i, i, i)).collect::<String>();
+        let nodes = HtmlParser::new(&format!("<html><body>{}</body></html>", html)).parse_nodes();
+        let sheets = vec![CssParser::new("body { font-size: 20px; --w: 3px; } .c1 p { width: var(--w); }
+            div > p { color: red; } .c2 > ul li { font-size: 2em; } #p7 span { height: 1em; }
+            li + li, li { margin-left: 1px; }").parse_stylesheet()];
+        let media = MediaEnvironment::default();
+
+        let mut context = StyleContext::new(&sheets, &media);
+        let sequential = context.style(&nodes[0]);
+        let lookups = context.stats.lookups;
+
+        for threads in 1..5 {
+            let mut context = StyleContext::new(&sheets, &media);
+            context.traversal = Traversal::Parallel(threads);
+            assert_same_tree(&sequential, &context.style(&nodes[0]));
+            assert_eq!(lookups, context.stats.lookups);
+        }
+        assert_eq!(Some(&Value::Length(3.0, Unit::Px)), sequential.children[0].children[1].children[0].value("width"));
+    }
+
+    /// Test descendant and child selectors match through the style tree.
+    #[test]
+    fn cascade_combinators() {
+        let (ss, node) = setup_tree(".outer p { width: 1px; } div > p { height: 2px; }
+            span > p { height: 3px; } .outer > .outer p { width: 4px; }", "outer", "");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+        let child = &styled.children[0];
+
+        assert_eq!(1.0, child.num_or("width", 0.0));
+        assert_eq!(2.0, child.num_or("height", 0.0));
+        assert_eq!(None, styled.value("width"));
+    }
+
+    /// Parse a stylesheet and an element with an inline style.
+    fn setup_inline(css: &str, style: &str) -> (Stylesheet, Node) {
+        let mut attrs = HashMap::new();
+        attrs.insert(String::from("id"), String::from("main"));
+        attrs.insert(String::from("style"), String::from(style));
+        let elem = ElementData::new(String::from("div"), attrs);
+
+        (CssParser::new(css).parse_stylesheet(), Node::new(NodeType::Element(elem), vec![]))
+    }
+
+    /// Test inline styles beat selectors of any specificity, but not important declarations.
+    #[test]
+    fn cascade_inline() {
+        let (ss, node) = setup_inline("#main { width: 1px; height: 1px !important; margin-left: 1px; }",
+            "width: 2px; height: 2px; margin: 3px 4px; margin: 5px oops; padding-left: 6px");
+        let sheets = vec![ss];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(2.0, styled.num_or("width", 0.0));
+        assert_eq!(1.0, styled.num_or("height", 0.0));
+        assert_eq!(4.0, styled.num_or("margin-left", 0.0));
+        assert_eq!(6.0, styled.num_or("padding-left", 0.0));
+    }
+
+    /// Test important inline styles beat important author declarations, but not user ones.
+    #[test]
+    fn cascade_inline_important() {
+        let (ss, node) = setup_inline("#main { width: 1px !important; }", "width: 2px !important; height: 2px !important");
+        let sheets = vec![ss, sheet("div { height: 3px !important; }", Origin::User)];
+        let styled = StyledNode::new(&node, &sheets, &MediaEnvironment::default());
+
+        assert_eq!(2.0, styled.num_or("width", 0.0));
+        assert_eq!(3.0, styled.num_or("height", 0.0));
+    }
+
+    /// Test the user agent stylesheet applies at the user agent origin.
+    #[test]
+    fn cascade_user_agent() {
+        let (ss, node) = setup("p { margin-bottom: 2px; }", "body", None, "");
+        let (_, head) = setup("", "head", None, "");
+        let (_, p) = setup("", "p", None, "");
+        let sheets = vec![ss];
+        let media = MediaEnvironment::default();
+        let p = StyledNode::new(&p, &sheets, &media);
+
+        assert_eq!(Origin::UserAgent, user_agent_stylesheet().origin);
+        assert!(user_agent_stylesheet().rules.iter()
+            .all(|r| !r.selectors.is_empty() && !r.declarations.is_empty()));
+        assert!(matches!(StyledNode::new(&node, &sheets, &media).computed().display, Display::BLOCK));
+        assert_eq!(8.0, StyledNode::new(&node, &sheets, &media).num_or("margin-left", 0.0));
+        assert!(matches!(StyledNode::new(&head, &sheets, &media).computed().display, Display::None));
+        assert_eq!(Some(&Value::Length(1.0, Unit::Em)), p.value("margin-top"));
+        assert_eq!(2.0, p.num_or("margin-bottom", 0.0));
+    }
 }